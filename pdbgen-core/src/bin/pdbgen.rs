@@ -0,0 +1,33 @@
+//! Turn a JSON model produced by the plugin or an external tool into a PDB
+//!
+//! Usage: `pdbgen <model.json> <output.pdb>`
+
+use anyhow::{Context as _, Result, bail};
+use pdbgen_core::{Model, Options, Report};
+use std::fs;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+fn main() -> Result<()> {
+    let args: Vec<_> = std::env::args_os().skip(1).collect();
+    let [model_path, pdb_path] = args.as_slice() else {
+        bail!("usage: pdbgen <model.json> <output.pdb>");
+    };
+    let (model_path, pdb_path) = (PathBuf::from(model_path), PathBuf::from(pdb_path));
+
+    let file = fs::File::open(&model_path)
+        .with_context(|| format!("failed to open {}", model_path.display()))?;
+    let model = Model::from_json(BufReader::new(file))
+        .with_context(|| format!("failed to parse {}", model_path.display()))?;
+
+    let mut report = Report::default();
+    pdbgen_core::write_pdb(&model, &Options::default(), &pdb_path, &mut report, &mut ())?;
+    for message in &report.warnings {
+        eprintln!("warning: {message}");
+    }
+    for (item, err) in &report.skipped {
+        eprintln!("skipped {item}: {err:#}");
+    }
+
+    Ok(())
+}
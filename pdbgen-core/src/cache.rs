@@ -0,0 +1,180 @@
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::hash::hash_json;
+use crate::model::{Function, Section};
+use crate::{Model, Options};
+
+/// Module of the cache holding the functions that start outside every section, a name no PE
+/// section can have since those are at most 8 bytes
+pub(crate) const OUTSIDE_SECTIONS: &str = "<outside sections>";
+
+/// Functions collected per module on a previous run, so unchanged modules can skip collection
+/// and an unchanged image can skip writing its PDB altogether
+///
+/// Only collection is skipped. When anything changed the whole PDB is built and written again,
+/// without copying the streams of unchanged modules from the previous one.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cache {
+    version: String,
+    /// Hash of the options the functions were collected with
+    #[serde(default)]
+    options: u64,
+    modules: HashMap<String, CachedModule>,
+    /// Hash of the model and options the PDB was last written from
+    #[serde(default)]
+    written: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedModule {
+    hash: u64,
+    functions: Vec<Function>,
+    /// Functions were replaced by [`Cache::replace`], so they no longer match `hash`
+    #[serde(default)]
+    patched: bool,
+}
+
+impl Cache {
+    /// Location of the cache file belonging to `pdb_path`
+    pub fn path_for(pdb_path: &Path) -> PathBuf {
+        pdb_path.with_extension("pdbgen-cache.json")
+    }
+
+    /// Load the cache, starting fresh if it is missing, unreadable, from another version or
+    /// collected with other options than `options`
+    pub fn load(path: &Path, options: &Options) -> Self {
+        let cache = fs::File::open(path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, Self>(BufReader::new(file)).ok());
+        let options = hash_json(options);
+        match cache {
+            Some(cache)
+                if cache.version == env!("CARGO_PKG_VERSION") && cache.options == options =>
+            {
+                cache
+            }
+            cache => {
+                if cache.is_some() {
+                    info!(
+                        "Discarding {}, it was built by another version or with other options",
+                        path.display()
+                    );
+                }
+                Self {
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    options,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// Whether the cached functions were collected with `options`
+    pub fn matches(&self, options: &Options) -> bool {
+        self.options == hash_json(options)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let output = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?,
+        );
+        serde_json::to_writer(output, self)?;
+        Ok(())
+    }
+
+    /// Functions of `module` from the previous run if its input hash is unchanged
+    pub fn get(&self, module: &str, hash: u64) -> Option<&[Function]> {
+        let cached = self.modules.get(module)?;
+        if cached.hash == hash && !cached.patched {
+            info!(
+                "Module {module} unchanged, reusing {} functions",
+                cached.functions.len()
+            );
+            Some(&cached.functions)
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&mut self, module: String, hash: u64, functions: Vec<Function>) {
+        self.modules.insert(
+            module,
+            CachedModule {
+                hash,
+                functions,
+                patched: false,
+            },
+        );
+    }
+
+    /// Whether the PDB was last written from exactly `model` and `options`, so writing it again
+    /// would produce the same streams
+    pub fn is_written(&self, model: &Model, options: &Options) -> bool {
+        self.written != 0 && self.written == hash_json(&(model, options))
+    }
+
+    /// Remember that the PDB was written from `model` and `options`
+    pub fn set_written(&mut self, model: &Model, options: &Options) {
+        self.written = hash_json(&(model, options));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    /// Every function cached, across modules
+    pub(crate) fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.modules.values().flat_map(|module| &module.functions)
+    }
+
+    /// Drop the cached functions starting at any of `starts` and file `functions` under the
+    /// section they start in, or under [`OUTSIDE_SECTIONS`] if they start in none
+    ///
+    /// Modules changed this way are collected afresh on the next full run.
+    pub(crate) fn replace(
+        &mut self,
+        starts: &HashSet<u32>,
+        functions: Vec<Function>,
+        sections: &[Section],
+    ) {
+        for module in self.modules.values_mut() {
+            let before = module.functions.len();
+            module
+                .functions
+                .retain(|f| f.ranges.first().is_none_or(|r| !starts.contains(&r.start)));
+            module.patched |= module.functions.len() != before;
+        }
+
+        for function in functions {
+            let start = function.ranges.first().map_or(0, |r| r.start);
+            let name = sections
+                .iter()
+                .find(|s| s.range().contains(&start))
+                .map_or(OUTSIDE_SECTIONS, |section| section.name.as_str());
+            let module = self
+                .modules
+                .entry(name.to_string())
+                .or_insert_with(|| CachedModule {
+                    hash: 0,
+                    functions: Vec::new(),
+                    patched: true,
+                });
+            module.functions.push(function);
+            module.patched = true;
+        }
+    }
+
+    /// Remember the cache for the next run, logging rather than failing since it is optional
+    pub fn store(&self, path: &Path) {
+        if let Err(err) = self.save(path) {
+            warn!("Failed to write cache {}: {err:#}", path.display());
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Locating the managed parts of .NET and mixed-mode (C++/CLI) images
+//!
+//! Binary Ninja disassembles IL method bodies and metadata as native code, which produces
+//! functions that mean nothing to a native debugger. Everything the CLI header and the MethodDef
+//! table describe as managed is collected here so those functions can be left out.
+
+use anyhow::{Context as _, Result, bail, ensure};
+use std::ops::Range;
+
+use crate::Report;
+use crate::collect::{data_directory, read_bytes, u16_at, u32_at};
+use crate::reader::ViewReader;
+
+const IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR: usize = 14;
+const COR20_HEADER_SIZE: usize = 72;
+/// Offset of the metadata directory within IMAGE_COR20_HEADER
+const COR20_METADATA: usize = 8;
+/// Offsets of the metadata, managed resources, strong name signature and vtable fixup
+/// directories within IMAGE_COR20_HEADER
+const COR20_DIRECTORIES: [usize; 4] = [COR20_METADATA, 24, 32, 48];
+
+const METADATA_SIGNATURE: u32 = 0x424a_5342;
+const TABLE_MODULE: usize = 0x00;
+const TABLE_TYPE_REF: usize = 0x01;
+const TABLE_TYPE_DEF: usize = 0x02;
+const TABLE_FIELD: usize = 0x04;
+const TABLE_METHOD_DEF: usize = 0x06;
+const TABLE_PARAM: usize = 0x08;
+const TABLE_MODULE_REF: usize = 0x1a;
+const TABLE_TYPE_SPEC: usize = 0x1b;
+const TABLE_ASSEMBLY_REF: usize = 0x23;
+
+/// MethodImplAttributes code type of methods with an IL body
+const METHOD_IMPL_IL: u16 = 0;
+const METHOD_IMPL_CODE_TYPE_MASK: u16 = 3;
+
+const CORILMETHOD_TINY_FORMAT: u8 = 0x2;
+const CORILMETHOD_FAT_FORMAT: u8 = 0x3;
+const CORILMETHOD_MORE_SECTS: u16 = 0x8;
+const CORILMETHOD_SECT_FAT_FORMAT: u8 = 0x40;
+const CORILMETHOD_SECT_MORE_SECTS: u8 = 0x80;
+
+/// Sorted, non-overlapping RVA ranges of the CLI header, metadata and IL method bodies, empty
+/// for native images
+///
+/// Failing to walk the metadata tables still returns the directory ranges.
+pub(crate) fn managed_ranges(
+    reader: &impl ViewReader,
+    report: &mut Report,
+) -> Result<Vec<Range<u32>>> {
+    let Some((header_rva, header_size)) =
+        data_directory(reader, IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR)?
+    else {
+        return Ok(Vec::new());
+    };
+    let base = reader.start();
+    let header = read_bytes(reader, base + header_rva as u64, COR20_HEADER_SIZE)
+        .context("failed to read the CLI header")?;
+
+    let mut ranges = vec![header_rva..header_rva + header_size];
+    for offset in COR20_DIRECTORIES {
+        let (rva, size) = (u32_at(&header, offset), u32_at(&header, offset + 4));
+        if rva != 0 && size != 0 {
+            ranges.push(rva..rva.saturating_add(size));
+        }
+    }
+
+    match il_method_bodies(reader, u32_at(&header, COR20_METADATA)) {
+        Ok(bodies) => ranges.extend(bodies),
+        Err(err) => report.warn(format!("Could not find IL method bodies: {err:#}")),
+    }
+
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u32>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    Ok(merged)
+}
+
+/// Extents of every IL method body the MethodDef table points at
+fn il_method_bodies(reader: &impl ViewReader, metadata_rva: u32) -> Result<Vec<Range<u32>>> {
+    let base = reader.start();
+    let metadata = base + metadata_rva as u64;
+
+    let root = read_bytes(reader, metadata, 16)?;
+    ensure!(
+        u32_at(&root, 0) == METADATA_SIGNATURE,
+        "bad metadata signature"
+    );
+    let version_len = u32_at(&root, 12) as u64;
+    let streams_header = metadata + 16 + version_len;
+    let num_streams = u16_at(&read_bytes(reader, streams_header, 4)?, 2);
+
+    // Stream headers are an offset, a size and a NUL terminated name padded to 4 bytes
+    let mut tables = None;
+    let mut position = streams_header + 4;
+    for _ in 0..num_streams {
+        let entry = read_bytes(reader, position, 8 + 32)?;
+        let name_len = entry[8..]
+            .iter()
+            .position(|&b| b == 0)
+            .context("bad stream name")?;
+        if matches!(&entry[8..8 + name_len], b"#~" | b"#-") {
+            tables = Some(metadata + u32_at(&entry, 0) as u64);
+        }
+        position += 8 + (name_len as u64 + 1).next_multiple_of(4);
+    }
+    let Some(tables) = tables else {
+        bail!("metadata has no tables stream");
+    };
+
+    let header = read_bytes(reader, tables, 24)?;
+    let heap_sizes = header[6];
+    let valid = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    let present = valid.count_ones() as usize;
+    let counts = read_bytes(reader, tables + 24, present * 4)?;
+
+    let mut rows = [0u32; 64];
+    let mut next = 0;
+    for (table, count) in rows.iter_mut().enumerate() {
+        if valid & (1 << table) != 0 {
+            *count = u32_at(&counts, next * 4);
+            next += 1;
+        }
+    }
+    // Uncompressed tables streams may carry an extra word after the row counts
+    let extra = if heap_sizes & 0x40 != 0 { 4 } else { 0 };
+    let data = tables + 24 + present as u64 * 4 + extra;
+
+    let string = if heap_sizes & 0x1 != 0 { 4 } else { 2 };
+    let guid = if heap_sizes & 0x2 != 0 { 4 } else { 2 };
+    let blob = if heap_sizes & 0x4 != 0 { 4 } else { 2 };
+    let index = |table: usize| if rows[table] < 1 << 16 { 2 } else { 4 };
+    let coded = |tables: &[usize], tag_bits: u32| {
+        let max = tables.iter().map(|&t| rows[t]).max().unwrap_or(0);
+        if max < 1 << (16 - tag_bits) { 2 } else { 4 }
+    };
+    let resolution_scope = coded(
+        &[
+            TABLE_MODULE,
+            TABLE_MODULE_REF,
+            TABLE_ASSEMBLY_REF,
+            TABLE_TYPE_REF,
+        ],
+        2,
+    );
+    let type_def_or_ref = coded(&[TABLE_TYPE_DEF, TABLE_TYPE_REF, TABLE_TYPE_SPEC], 2);
+
+    // Row sizes of the tables stored before MethodDef: Module, TypeRef, TypeDef, FieldPtr,
+    // Field and MethodPtr
+    let row_sizes = [
+        2 + string + 3 * guid,
+        resolution_scope + 2 * string,
+        4 + 2 * string + type_def_or_ref + index(TABLE_FIELD) + index(TABLE_METHOD_DEF),
+        index(TABLE_FIELD),
+        2 + string + blob,
+        index(TABLE_METHOD_DEF),
+    ];
+    let method_defs = data
+        + row_sizes
+            .iter()
+            .enumerate()
+            .map(|(table, &size)| rows[table] as u64 * size as u64)
+            .sum::<u64>();
+    let method_def_size = 8 + string + blob + index(TABLE_PARAM);
+    let method_count = rows[TABLE_METHOD_DEF] as usize;
+    let methods = read_bytes(reader, method_defs, method_count * method_def_size)
+        .context("failed to read the MethodDef table")?;
+
+    Ok(methods
+        .chunks_exact(method_def_size)
+        .filter(|row| u16_at(row, 4) & METHOD_IMPL_CODE_TYPE_MASK == METHOD_IMPL_IL)
+        .map(|row| u32_at(row, 0))
+        .filter(|&rva| rva != 0)
+        .filter_map(|rva| {
+            let len = il_body_len(reader, base + rva as u64).ok()?;
+            Some(rva..rva.saturating_add(len))
+        })
+        .collect())
+}
+
+/// Length of the IL method body at `addr`, including its header and exception sections
+fn il_body_len(reader: &impl ViewReader, addr: u64) -> Result<u32> {
+    let header = read_bytes(reader, addr, 1)?;
+    match header[0] & 0x3 {
+        CORILMETHOD_TINY_FORMAT => Ok(1 + (header[0] >> 2) as u32),
+        CORILMETHOD_FAT_FORMAT => {
+            let header = read_bytes(reader, addr, 12)?;
+            let flags = u16_at(&header, 0);
+            let header_size = (flags >> 12) as u32 * 4;
+            let mut len = header_size + u32_at(&header, 4);
+            let mut more = flags & CORILMETHOD_MORE_SECTS != 0;
+            while more {
+                len = len.next_multiple_of(4);
+                let section = read_bytes(reader, addr + len as u64, 4)?;
+                let size = match section[0] & CORILMETHOD_SECT_FAT_FORMAT {
+                    0 => section[1] as u32,
+                    _ => u32::from_le_bytes([section[1], section[2], section[3], 0]),
+                };
+                ensure!(size != 0, "empty method data section");
+                len += size;
+                more = section[0] & CORILMETHOD_SECT_MORE_SECTS != 0;
+            }
+            Ok(len)
+        }
+        _ => bail!("unknown method header format"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::MemoryReader;
+
+    const BASE: u64 = 0x40_0000;
+
+    /// An image with `bytes` written at each RVA and zeroes everywhere else
+    fn image(bytes: &[(u32, &[u8])]) -> MemoryReader {
+        let mut memory = vec![0; 0x3000];
+        for &(rva, bytes) in bytes {
+            memory[rva as usize..rva as usize + bytes.len()].copy_from_slice(bytes);
+        }
+        MemoryReader {
+            start: BASE,
+            regions: [(BASE, memory)].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn measures_tiny_bodies() {
+        let reader = image(&[(0x2000, &[(5 << 2) | CORILMETHOD_TINY_FORMAT])]);
+        assert_eq!(il_body_len(&reader, BASE + 0x2000).unwrap(), 6);
+    }
+
+    #[test]
+    fn measures_fat_bodies() {
+        // Three dword header, 10 bytes of code
+        let reader = image(&[(0x2000, &[0x03, 0x30, 0, 0, 10, 0, 0, 0])]);
+        assert_eq!(il_body_len(&reader, BASE + 0x2000).unwrap(), 22);
+    }
+
+    #[test]
+    fn measures_fat_bodies_with_data_sections() {
+        let reader = image(&[
+            // 6 bytes of code, padded to 20 before the first section
+            (0x2000, &[0x0b, 0x30, 0, 0, 6, 0, 0, 0]),
+            // Small section of 16 bytes followed by another
+            (0x2014, &[0x81, 16, 0, 0]),
+            // Fat section of 28 bytes, the last
+            (0x2024, &[0x41, 28, 0, 0]),
+        ]);
+        assert_eq!(il_body_len(&reader, BASE + 0x2000).unwrap(), 64);
+    }
+
+    #[test]
+    fn rejects_unknown_body_formats() {
+        let reader = image(&[(0x2000, &[0x01])]);
+        assert!(il_body_len(&reader, BASE + 0x2000).is_err());
+    }
+
+    #[test]
+    fn finds_il_bodies_through_the_method_def_table() {
+        const METADATA: u32 = 0x1000;
+        const TABLES: u32 = METADATA + 0x40;
+
+        let mut root = METADATA_SIGNATURE.to_le_bytes().to_vec();
+        root.extend([1, 0, 1, 0, 0, 0, 0, 0]);
+        root.extend(12u32.to_le_bytes());
+        root.extend(b"v4.0.30319\0\0");
+        // Flags, then a single stream
+        root.extend([0, 0, 1, 0]);
+        root.extend((TABLES - METADATA).to_le_bytes());
+        root.extend(0x100u32.to_le_bytes());
+        root.extend(b"#~\0\0");
+
+        // Module, TypeDef and MethodDef tables, every heap index 2 bytes wide
+        let mut tables = vec![0, 0, 0, 0, 2, 0, 0, 1];
+        let valid = (1u64 << TABLE_MODULE) | (1 << TABLE_TYPE_DEF) | (1 << TABLE_METHOD_DEF);
+        tables.extend(valid.to_le_bytes());
+        tables.extend(0u64.to_le_bytes());
+        for rows in [1u32, 1, 4] {
+            tables.extend(rows.to_le_bytes());
+        }
+        // A Module row of 10 bytes and a TypeDef row of 14
+        tables.extend([0; 10 + 14]);
+        let method = |rva: u32, impl_flags: u16| {
+            let mut row = rva.to_le_bytes().to_vec();
+            row.extend(impl_flags.to_le_bytes());
+            row.extend([0; 8]);
+            row
+        };
+        tables.extend(method(0x2000, METHOD_IMPL_IL));
+        // Native code of a mixed-mode image
+        tables.extend(method(0x2100, 1));
+        // Abstract, without a body
+        tables.extend(method(0, METHOD_IMPL_IL));
+        tables.extend(method(0x2200, METHOD_IMPL_IL));
+
+        let reader = image(&[
+            (METADATA, &root),
+            (TABLES, &tables),
+            (0x2000, &[(5 << 2) | CORILMETHOD_TINY_FORMAT]),
+            (0x2200, &[0x03, 0x30, 0, 0, 10, 0, 0, 0]),
+        ]);
+        assert_eq!(
+            il_method_bodies(&reader, METADATA).unwrap(),
+            [0x2000..0x2006, 0x2200..0x2216]
+        );
+    }
+
+    #[test]
+    fn rejects_metadata_without_a_signature() {
+        let reader = image(&[]);
+        assert!(il_method_bodies(&reader, 0x1000).is_err());
+    }
+}
@@ -0,0 +1,1136 @@
+use anyhow::{Context as _, Result, bail, ensure};
+use std::collections::HashMap;
+use std::hash::{Hash as _, Hasher as _};
+use std::ops::Range;
+use std::time::Instant;
+use tracing::info;
+
+use crate::cache::OUTSIDE_SECTIONS;
+use crate::clr;
+use crate::demangle::{function_name, name_in_form};
+use crate::hash::{Fnv1a, hash_json};
+use crate::layout::{self, LayoutRange};
+use crate::literals::literal_name;
+use crate::model::{self, Model, PdbInfo};
+use crate::reader::{FunctionInfo, ViewReader};
+use crate::registers::Arch;
+use crate::{
+    Cache, Interner, Options, Progress, Report, StringLiteralNames, SymbolType, TypeIssue,
+};
+
+/// Offset of NumberOfSections within `__coff_header`, which starts at the "PE\0\0" signature
+const COFF_NUMBER_OF_SECTIONS: usize = 0x6;
+/// Offset of Machine within `__coff_header`
+const COFF_MACHINE: usize = 0x4;
+/// Offset of TimeDateStamp within `__coff_header`
+const COFF_TIME_DATE_STAMP: usize = 0x8;
+/// The "PE\0\0" signature followed by IMAGE_FILE_HEADER
+const COFF_HEADER_SIZE: usize = 24;
+/// Size of an IMAGE_SECTION_HEADER
+const SECTION_HEADER_SIZE: usize = 40;
+/// Offset of AddressOfEntryPoint within the optional header
+const OPTIONAL_ENTRY_POINT: usize = 16;
+/// Offset of the data directories within the PE32 and PE32+ optional headers
+const OPTIONAL_DATA_DIRECTORIES_32: usize = 96;
+const OPTIONAL_DATA_DIRECTORIES_64: usize = 112;
+const IMAGE_DIRECTORY_ENTRY_EXCEPTION: usize = 3;
+const IMAGE_DIRECTORY_ENTRY_TLS: usize = 9;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+/// Size of an x64 RUNTIME_FUNCTION
+const RUNTIME_FUNCTION_SIZE: usize = 12;
+/// Longest string read back to name. Longer ones get plain names from their start but no
+/// mangled name, which covers every byte.
+const MAX_LITERAL_BYTES: usize = 4096;
+/// Stop walking a vtable that runs on for longer than this many slots
+const MAX_VTABLE_SLOTS: usize = 4096;
+/// Stop walking a TLS callback array that isn't terminated within this many entries
+const MAX_TLS_CALLBACKS: u64 = 1024;
+
+/// Module for functions recognized as part of the C/C++ runtime
+const CRT_MODULE: &str = "msvcrt_static.obj";
+/// Module for other functions matched against a signature library
+const LIBRARY_MODULE: &str = "static_library.obj";
+/// Prefix of the module holding the import thunks of a library, followed by its name
+pub(crate) const IMPORT_MODULE_PREFIX: &str = "Import:";
+/// Name prefixes of functions the static MSVC runtime links into every image
+const CRT_PREFIXES: &[&str] = &[
+    "__scrt_",
+    "__acrt_",
+    "__vcrt_",
+    "__security_",
+    "__report_",
+    "__GSHandlerCheck",
+    "__C_specific_handler",
+    "__CxxFrameHandler",
+    "__std_",
+    "__isa_",
+    "__raise_securityfailure",
+    "__chkstk",
+    "_RTC_",
+    "_CRT_",
+    "_initterm",
+    "__dyn_tls_",
+    "__tlregdtor",
+    "_guard_",
+    "mainCRTStartup",
+    "wmainCRTStartup",
+    "WinMainCRTStartup",
+    "wWinMainCRTStartup",
+    "_DllMainCRTStartup",
+    "pre_c_initialization",
+    "post_pgo_initialization",
+    "pre_cpp_initialization",
+];
+
+/// Functions whose ranges are separated by at most this many unclaimed bytes are merged
+const MAX_RANGE_GAP: u64 = 16;
+
+const OVERLAY_SECTION: &str = ".overlay";
+
+const IMAGE_SCN_CNT_CODE: u32 = 0x0000_0020;
+const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x0000_0040;
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// Filler compilers put between functions
+const INT3: u8 = 0xcc;
+/// Longest run of filler trimmed from the end of a range, enough for 64 byte alignment
+const MAX_PADDING: u64 = 64;
+
+/// Collect everything needed to build a PDB from an analyzed PE image
+pub fn collect_model(
+    reader: &impl ViewReader,
+    options: &Options,
+    cache: Option<&mut Cache>,
+    report: &mut Report,
+    progress: &mut dyn Progress,
+) -> Result<Model> {
+    let pdb_info = collect_pdbinfo(reader, report)?;
+    info!("PdbInfo = {pdb_info:?}");
+    let machine = coff_machine(reader)?;
+
+    let start = Instant::now();
+    let mut sections = collect_sections(reader)?;
+    if options.user_sections {
+        let user_sections = collect_user_sections(reader, &sections, report);
+        sections.extend(user_sections);
+    }
+    report.time("Section parsing", start);
+
+    // Functions are moved to the image's layout as they are collected, so they land in the
+    // module of the section they end up in
+    let ranges = layout_ranges(reader, options, &sections);
+    if !ranges.is_empty() {
+        info!("Moving RVAs through {} layout range(s)", ranges.len());
+    }
+
+    let start = Instant::now();
+    let functions =
+        collect_functions(reader, options, &sections, &ranges, cache, report, progress)?;
+    report.time("Symbol collection", start);
+
+    if options.overlay_section {
+        sections.extend(overlay_section(&sections, &functions));
+    }
+
+    // Not having these is no reason to give up on the rest of the PDB
+    let mut publics = collect_entry_points(reader, &functions, &ranges).unwrap_or_else(|err| {
+        report.warn(format!("Could not read entry points: {err:#}"));
+        Vec::new()
+    });
+    publics.extend(collect_data_symbols(reader, options, &sections, &ranges));
+    let vtables = match options.vtables {
+        true => collect_vtables(reader, &functions, &sections, &ranges, &mut publics)
+            .unwrap_or_else(|err| {
+                report.warn(format!("Could not read vtables: {err:#}"));
+                Vec::new()
+            }),
+        false => Vec::new(),
+    };
+    if options.string_literals != StringLiteralNames::Off {
+        let start = Instant::now();
+        let literals = collect_string_literals(reader, options.string_literals, &sections, &ranges);
+        info!("Naming {} string literal(s)", literals.len());
+        publics.extend(literals);
+        report.time("String literals", start);
+    }
+
+    let exception_data = exception_table(reader).unwrap_or_else(|err| {
+        report.warn(format!("Could not read the exception directory: {err:#}"));
+        None
+    });
+    let unwind_functions = match &exception_data {
+        Some((machine, table)) if *machine == IMAGE_FILE_MACHINE_AMD64 => {
+            collect_unwind_functions(table, &functions, &ranges)
+        }
+        _ => Vec::new(),
+    };
+    if !unwind_functions.is_empty() {
+        info!(
+            "Exception directory describes {} function(s) analysis did not find",
+            unwind_functions.len()
+        );
+    }
+
+    let mut model = Model {
+        info: pdb_info,
+        machine,
+        sections,
+        functions,
+        unwind_functions,
+        exception_data: match options.exception_stream {
+            true => exception_data.map(|(_, table)| table).unwrap_or_default(),
+            false => Vec::new(),
+        },
+        publics,
+        vtables,
+        types: reader.types(),
+        frontend: reader.frontend(),
+        image_name: reader.image_name(),
+    };
+
+    if !ranges.is_empty() {
+        layout::relocate(&mut model, &ranges, report);
+    }
+
+    Ok(model)
+}
+
+fn symbol_address(reader: &impl ViewReader, raw_name: &str) -> Result<u64> {
+    reader
+        .symbol_address(raw_name)
+        .with_context(|| format!("could not find {raw_name} symbol"))
+}
+
+/// Read exactly `len` bytes at `addr` in one call, failing on a short read
+pub(crate) fn read_bytes(reader: &impl ViewReader, addr: u64, len: usize) -> Result<Vec<u8>> {
+    let bytes = reader.read(addr, len);
+    ensure!(
+        bytes.len() == len,
+        "short read at 0x{addr:x}: got {} of {len} bytes",
+        bytes.len()
+    );
+    Ok(bytes)
+}
+
+/// The `N` bytes at `offset` in a buffer already checked to be long enough by [`read_bytes`]
+fn field<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    bytes[offset..offset + N].try_into().unwrap()
+}
+
+pub(crate) fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(field(bytes, offset))
+}
+
+pub(crate) fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(field(bytes, offset))
+}
+
+fn collect_pdbinfo(reader: &impl ViewReader, report: &mut Report) -> Result<PdbInfo> {
+    let coff_header = symbol_address(reader, "__coff_header")?;
+    let header = read_bytes(reader, coff_header, COFF_HEADER_SIZE)?;
+    let timestamp = u32_at(&header, COFF_TIME_DATE_STAMP);
+
+    let (Some(pdb_age), Some(pdb_guid)) = (
+        reader.symbol_address("PDBAge"),
+        reader.symbol_address("PDBGuid"),
+    ) else {
+        // MinGW and other GCC based toolchains emit DWARF and no CodeView entry. The functions
+        // Binary Ninja imported from the DWARF are still worth a PDB, it just can't be matched
+        // to the image automatically.
+        report.warn(
+            "Image has no CodeView debug directory entry (usual for MinGW/GCC builds), \
+             the PDB gets a GUID derived from the headers and must be loaded with .reload /i"
+                .to_string(),
+        );
+        // FNV-1a rather than DefaultHasher, whose output may change with the toolchain and
+        // would give the same image another GUID after an upgrade
+        let mut hasher = Fnv1a::default();
+        hasher.write(&header);
+        let low = hasher.finish();
+        hasher.write(&low.to_le_bytes());
+        let high = hasher.finish();
+        let mut guid = [0; 16];
+        guid[..8].copy_from_slice(&low.to_le_bytes());
+        guid[8..].copy_from_slice(&high.to_le_bytes());
+        return Ok(PdbInfo {
+            age: 1,
+            timestamp,
+            guid,
+        });
+    };
+
+    Ok(PdbInfo {
+        age: u32_at(&read_bytes(reader, pdb_age, 4)?, 0),
+        timestamp,
+        guid: field(&read_bytes(reader, pdb_guid, 16)?, 0),
+    })
+}
+
+/// Where the view RVAs `rvas` live in the image, as [`collect_model`] moves them under
+/// `options`. RVAs no layout range covers are dropped.
+pub fn image_rvas(reader: &impl ViewReader, options: &Options, rvas: &[u32]) -> Result<Vec<u32>> {
+    let sections = collect_sections(reader)?;
+    let ranges = layout_ranges(reader, options, &sections);
+    Ok(rvas
+        .iter()
+        .filter_map(|&rva| layout::translate(&ranges, rva))
+        .collect())
+}
+
+/// Ranges moving the view's layout to the image's, empty when the two agree
+fn layout_ranges(
+    reader: &impl ViewReader,
+    options: &Options,
+    sections: &[model::Section],
+) -> Vec<LayoutRange> {
+    let ranges = match (options.layout_ranges.is_empty(), options.derive_layout) {
+        (false, _) => options.layout_ranges.clone(),
+        (true, true) => layout::derive(&reader.segments(), reader.start(), sections),
+        (true, false) => Vec::new(),
+    };
+    match layout::is_identity(&ranges) {
+        true => Vec::new(),
+        false => ranges,
+    }
+}
+
+fn collect_sections(reader: &impl ViewReader) -> Result<Vec<model::Section>> {
+    let coff_header = symbol_address(reader, "__coff_header")?;
+    let section_headers = symbol_address(reader, "__section_headers")?;
+
+    let header = read_bytes(reader, coff_header, COFF_HEADER_SIZE)?;
+    let num_sections = u16_at(&header, COFF_NUMBER_OF_SECTIONS) as usize;
+
+    // The whole table in one read rather than one per field
+    let table = read_bytes(reader, section_headers, num_sections * SECTION_HEADER_SIZE)
+        .context("failed to read section table")?;
+
+    Ok(table
+        .chunks_exact(SECTION_HEADER_SIZE)
+        .map(|header| {
+            let name: [u8; 8] = field(header, 0);
+            model::Section {
+                name: String::from_utf8_lossy(&name)
+                    .trim_end_matches('\0')
+                    .to_string(),
+                virtual_size: u32_at(header, 8),
+                virtual_address: u32_at(header, 12),
+                size_of_raw_data: u32_at(header, 16),
+                pointer_to_raw_data: u32_at(header, 20),
+                pointer_to_relocations: u32_at(header, 24),
+                pointer_to_line_numbers: u32_at(header, 28),
+                number_of_relocations: u16_at(header, 32),
+                number_of_line_numbers: u16_at(header, 34),
+                characteristics: u32_at(header, 36),
+            }
+        })
+        .collect())
+}
+
+/// Location of the optional header and whether it is PE32+
+fn optional_header(reader: &impl ViewReader) -> Result<(u64, bool)> {
+    let optional_header = symbol_address(reader, "__coff_header")? + COFF_HEADER_SIZE as u64;
+    let pe64 = match u16_at(&read_bytes(reader, optional_header, 2)?, 0) {
+        0x10b => false,
+        0x20b => true,
+        magic => bail!("unknown optional header magic 0x{magic:x}"),
+    };
+    Ok((optional_header, pe64))
+}
+
+/// RVA and size of data directory `index`, if present and non-empty
+pub(crate) fn data_directory(reader: &impl ViewReader, index: usize) -> Result<Option<(u32, u32)>> {
+    let (optional_header, pe64) = optional_header(reader)?;
+    let data_directories = match pe64 {
+        false => OPTIONAL_DATA_DIRECTORIES_32,
+        true => OPTIONAL_DATA_DIRECTORIES_64,
+    };
+    let count = read_bytes(reader, optional_header + data_directories as u64 - 4, 4)?;
+    if u32_at(&count, 0) as usize <= index {
+        return Ok(None);
+    }
+    let entry = read_bytes(
+        reader,
+        optional_header + (data_directories + index * 8) as u64,
+        8,
+    )?;
+    Ok(match (u32_at(&entry, 0), u32_at(&entry, 4)) {
+        (0, _) | (_, 0) => None,
+        directory => Some(directory),
+    })
+}
+
+/// Section headers for sections created in the analysis that the PE header doesn't have
+fn collect_user_sections(
+    reader: &impl ViewReader,
+    sections: &[model::Section],
+    report: &mut Report,
+) -> Vec<model::Section> {
+    let base = reader.start();
+    let mut user_sections: Vec<model::Section> = Vec::new();
+    for section in reader.sections().into_iter().filter(|s| s.user) {
+        let range = section
+            .range
+            .start
+            .checked_sub(base)
+            .zip(section.range.end.checked_sub(base))
+            .and_then(|(start, end)| Some(u32::try_from(start).ok()?..u32::try_from(end).ok()?));
+        let Some(range) = range else {
+            report.warn(format!(
+                "User section {} cannot be addressed as an RVA and was left out",
+                section.name
+            ));
+            continue;
+        };
+        // Containment is resolved in header order, so an overlapping header would never be used
+        let overlaps = sections
+            .iter()
+            .chain(&user_sections)
+            .any(|s| s.range().start < range.end && range.start < s.range().end);
+        if overlaps {
+            report.warn(format!(
+                "User section {} overlaps another section and was left out",
+                section.name
+            ));
+            continue;
+        }
+
+        info!(
+            "Adding user section {} at 0x{:x}",
+            section.name, range.start
+        );
+        let characteristics = match (section.code, section.writable) {
+            (true, _) => IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+            (false, true) => {
+                IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE
+            }
+            (false, false) => IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+        };
+        user_sections.push(model::Section {
+            name: section.name,
+            virtual_size: range.end - range.start,
+            virtual_address: range.start,
+            characteristics,
+            ..Default::default()
+        });
+    }
+    user_sections
+}
+
+/// Synthetic section covering every function past the end of the image, such as code in an
+/// overlay or in a region the analyst mapped by hand
+fn overlay_section(
+    sections: &[model::Section],
+    functions: &[model::Function],
+) -> Option<model::Section> {
+    let image_end = sections.iter().map(|s| s.range().end).max().unwrap_or(0);
+    let beyond = functions
+        .iter()
+        .filter(|f| f.ranges.first().is_some_and(|r| r.start >= image_end));
+    let start = beyond.clone().map(|f| f.ranges[0].start).min()?;
+    let end = beyond
+        .flat_map(|f| &f.ranges)
+        .map(|r| r.end)
+        .max()
+        .unwrap_or(start);
+    info!("Adding overlay section at 0x{start:x}..0x{end:x}");
+
+    Some(model::Section {
+        name: OVERLAY_SECTION.to_string(),
+        virtual_size: end - start,
+        virtual_address: start,
+        characteristics: IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        ..Default::default()
+    })
+}
+
+/// Pointer-sized little-endian value at `offset`
+fn pointer_at(bytes: &[u8], offset: usize, pe64: bool) -> u64 {
+    if pe64 {
+        u64::from_le_bytes(field(bytes, offset))
+    } else {
+        u32_at(bytes, offset) as u64
+    }
+}
+
+/// Publics for AddressOfEntryPoint and every TLS callback that no collected function starts at,
+/// so crashes during early initialization still resolve to a name
+fn collect_entry_points(
+    reader: &impl ViewReader,
+    functions: &[model::Function],
+    ranges: &[LayoutRange],
+) -> Result<Vec<model::Public>> {
+    let (optional_header, pe64) = optional_header(reader)?;
+
+    let mut entry_points = Vec::new();
+    let header = read_bytes(reader, optional_header, OPTIONAL_ENTRY_POINT + 4)?;
+    let entry_point = u32_at(&header, OPTIONAL_ENTRY_POINT);
+    if entry_point != 0 {
+        entry_points.push(("EntryPoint".to_string(), entry_point));
+    }
+
+    if let Some((tls_rva, _)) = data_directory(reader, IMAGE_DIRECTORY_ENTRY_TLS)? {
+        let tls = reader.start() + tls_rva as u64;
+        for (i, rva) in tls_callbacks(reader, tls, pe64)?.into_iter().enumerate() {
+            entry_points.push((format!("TlsCallback_{i}"), rva));
+        }
+    }
+
+    Ok(entry_points
+        .into_iter()
+        .filter(|(_, rva)| {
+            let rva = layout::translate(ranges, *rva);
+            !functions
+                .iter()
+                .any(|f| rva.is_some() && f.ranges.first().map(|r| r.start) == rva)
+        })
+        .map(|(name, rva)| model::Public {
+            name: name.into(),
+            rva,
+            function: true,
+        })
+        .collect())
+}
+
+/// Data publics for the symbols of the selected types inside the image's sections
+fn collect_data_symbols(
+    reader: &impl ViewReader,
+    options: &Options,
+    sections: &[model::Section],
+    ranges: &[LayoutRange],
+) -> Vec<model::Public> {
+    // Enumerating every symbol is not free, skip it when no data kind is wanted
+    let wanted = |t: &SymbolType| matches!(t, SymbolType::Data | SymbolType::External);
+    if !options.symbol_types.iter().any(wanted) {
+        return Vec::new();
+    }
+
+    let base = reader.start();
+    reader
+        .data_symbols()
+        .into_iter()
+        .filter(|symbol| options.symbol_types.contains(&symbol.symbol_type))
+        .filter_map(|symbol| {
+            let rva = u32::try_from(symbol.address.checked_sub(base)?).ok()?;
+            in_sections(sections, ranges, rva).then(|| model::Public {
+                name: name_in_form(
+                    options.name_form,
+                    &symbol.raw_name,
+                    &symbol.name,
+                    &symbol.full_name,
+                )
+                .into(),
+                rva,
+                function: false,
+            })
+        })
+        .collect()
+}
+
+/// Data symbols named like vtables, sized by walking slots while they point at function
+/// starts, with a public labelling every slot after the function it holds
+fn collect_vtables(
+    reader: &impl ViewReader,
+    functions: &[model::Function],
+    sections: &[model::Section],
+    ranges: &[LayoutRange],
+    publics: &mut Vec<model::Public>,
+) -> Result<Vec<model::Vtable>> {
+    let (_, pe64) = optional_header(reader)?;
+    let slot_size = if pe64 { 8 } else { 4 };
+    let base = reader.start();
+    let starts: HashMap<u32, &str> = functions
+        .iter()
+        .filter_map(|f| Some((f.ranges.first()?.start, &*f.name)))
+        .collect();
+
+    let mut vtables = Vec::new();
+    for symbol in reader.data_symbols() {
+        if !is_vtable_name(&symbol.name, &symbol.raw_name) {
+            continue;
+        }
+        let Some(rva) = symbol
+            .address
+            .checked_sub(base)
+            .and_then(|rva| u32::try_from(rva).ok())
+            .filter(|&rva| in_sections(sections, ranges, rva))
+        else {
+            continue;
+        };
+
+        // An Itanium vtable opens with the offset-to-top and RTTI pointer, objects point past
+        // them at the first slot
+        let header = match is_itanium_vtable_name(&symbol.name, &symbol.raw_name) {
+            true => 2 * slot_size,
+            false => 0,
+        };
+        let Some(rva) = rva.checked_add(header as u32) else {
+            continue;
+        };
+        let table = reader.read(symbol.address + header as u64, MAX_VTABLE_SLOTS * slot_size);
+        let targets: Vec<&str> = table
+            .chunks_exact(slot_size)
+            .map_while(|slot| {
+                let target = pointer_at(slot, 0, pe64).checked_sub(base)?;
+                let target = layout::translate(ranges, u32::try_from(target).ok()?)?;
+                starts.get(&target).copied()
+            })
+            .collect();
+        if targets.is_empty() {
+            continue;
+        }
+
+        for (i, target) in targets.iter().enumerate() {
+            publics.push(model::Public {
+                name: format!("{}[{i}]->{target}", symbol.name).into(),
+                rva: rva + (i * slot_size) as u32,
+                function: false,
+            });
+        }
+        vtables.push(model::Vtable {
+            name: symbol.name.into(),
+            rva,
+            slot_size: slot_size as u32,
+            slot_count: targets.len() as u32,
+        });
+    }
+    Ok(vtables)
+}
+
+/// MSVC vftables (`??_7`, "`vftable'") and Itanium vtables ("vtable for", `_ZTV`)
+fn is_vtable_name(name: &str, raw_name: &str) -> bool {
+    raw_name.starts_with("??_7")
+        || name.ends_with("`vftable'")
+        || is_itanium_vtable_name(name, raw_name)
+}
+
+fn is_itanium_vtable_name(name: &str, raw_name: &str) -> bool {
+    raw_name.starts_with("_ZTV") || name.starts_with("vtable for ")
+}
+
+/// Data publics for the string constants inside the image's sections
+fn collect_string_literals(
+    reader: &impl ViewReader,
+    style: StringLiteralNames,
+    sections: &[model::Section],
+    ranges: &[LayoutRange],
+) -> Vec<model::Public> {
+    let base = reader.start();
+    reader
+        .strings()
+        .into_iter()
+        .filter_map(|string| {
+            let rva = u32::try_from(string.address.checked_sub(base)?).ok()?;
+            if !in_sections(sections, ranges, rva) {
+                return None;
+            }
+            if style == StringLiteralNames::Mangled && string.length > MAX_LITERAL_BYTES {
+                return None;
+            }
+            let width = string.width.max(1);
+            let len = string.length.min(MAX_LITERAL_BYTES) / width * width;
+            let data = reader.read(string.address, len);
+            let data = &data[..data.len() / width * width];
+            Some(model::Public {
+                name: literal_name(style, data, width)?.into(),
+                rva,
+                function: false,
+            })
+        })
+        .collect()
+}
+
+/// Whether the view RVA `rva` lands in one of the image's `sections` once moved through `ranges`
+///
+/// Publics keep their view RVA until [`layout::relocate`] moves them, but the section table
+/// describes the image's layout.
+fn in_sections(sections: &[model::Section], ranges: &[LayoutRange], rva: u32) -> bool {
+    layout::translate(ranges, rva)
+        .is_some_and(|rva| sections.iter().any(|s| s.range().contains(&rva)))
+}
+
+/// The image's COFF Machine field
+fn coff_machine(reader: &impl ViewReader) -> Result<u16> {
+    let coff_header = symbol_address(reader, "__coff_header")?;
+    let header = read_bytes(reader, coff_header, COFF_HEADER_SIZE)?;
+    Ok(u16_at(&header, COFF_MACHINE))
+}
+
+/// The image's machine type and the contents of its exception directory, if it has one
+fn exception_table(reader: &impl ViewReader) -> Result<Option<(u16, Vec<u8>)>> {
+    let Some((rva, size)) = data_directory(reader, IMAGE_DIRECTORY_ENTRY_EXCEPTION)? else {
+        return Ok(None);
+    };
+    let table = read_bytes(reader, reader.start() + rva as u64, size as usize)
+        .context("failed to read RUNTIME_FUNCTION table")?;
+    Ok(Some((coff_machine(reader)?, table)))
+}
+
+/// Whether an RVA lies in any range of `functions`
+pub(crate) fn coverage(functions: &[model::Function]) -> impl Fn(u32) -> bool {
+    // Starts of every known range alongside the furthest end reached by it or any earlier range,
+    // so coverage is one binary search even when ranges overlap
+    let mut ranges: Vec<Range<u32>> = functions
+        .iter()
+        .flat_map(|f| f.ranges.iter().cloned())
+        .collect();
+    ranges.sort_by_key(|r| r.start);
+    let covered: Vec<(u32, u32)> = ranges
+        .iter()
+        .scan(0, |max_end, r| {
+            *max_end = r.end.max(*max_end);
+            Some((r.start, *max_end))
+        })
+        .collect();
+    move |rva: u32| match covered.partition_point(|&(start, _)| start <= rva) {
+        0 => false,
+        i => covered[i - 1].1 > rva,
+    }
+}
+
+/// Functions in the x64 exception directory whose start no collected function covers, named
+/// after their RVA and sized from the RUNTIME_FUNCTION entry
+fn collect_unwind_functions(
+    table: &[u8],
+    functions: &[model::Function],
+    ranges: &[LayoutRange],
+) -> Vec<model::Function> {
+    let is_covered = coverage(functions);
+    table
+        .chunks_exact(RUNTIME_FUNCTION_SIZE)
+        .map(|entry| u32_at(entry, 0)..u32_at(entry, 4))
+        .filter(|range| {
+            range.start < range.end
+                && layout::translate(ranges, range.start).is_none_or(|rva| !is_covered(rva))
+        })
+        .map(|range| model::Function {
+            name: format!("unwind_{:x}", range.start).into(),
+            ranges: vec![range],
+            kind: model::FunctionKind::Procedure,
+            component: None,
+            aliases: Vec::new(),
+            library: None,
+            parameters: Vec::new(),
+            entry_length: 0,
+            call_sites: Vec::new(),
+            heap_alloc_sites: Vec::new(),
+            calls: Vec::new(),
+            frame: None,
+            blocks: Vec::new(),
+        })
+        .collect()
+}
+
+/// RVAs in the NULL-terminated callback array of the IMAGE_TLS_DIRECTORY at `tls`
+fn tls_callbacks(reader: &impl ViewReader, tls: u64, pe64: bool) -> Result<Vec<u32>> {
+    let base = reader.start();
+    let pointer_size = if pe64 { 8 } else { 4 };
+
+    // AddressOfCallBacks is the fourth pointer of the directory
+    let directory = read_bytes(reader, tls, 4 * pointer_size)?;
+    let array = pointer_at(&directory, 3 * pointer_size, pe64);
+    if array == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut callbacks = Vec::new();
+    for i in 0..MAX_TLS_CALLBACKS {
+        let entry = read_bytes(reader, array + i * pointer_size as u64, pointer_size)?;
+        let callback = pointer_at(&entry, 0, pe64);
+        if callback == 0 {
+            break;
+        }
+        let rva = callback
+            .checked_sub(base)
+            .and_then(|rva| u32::try_from(rva).ok())
+            .with_context(|| format!("TLS callback 0x{callback:x} is outside the image"))?;
+        callbacks.push(rva);
+    }
+    Ok(callbacks)
+}
+
+fn merge_function_ranges(
+    ranges: &[Range<u64>],
+    max_gap: u64,
+    reader: &impl ViewReader,
+    function_start: u64,
+) -> Vec<Range<u64>> {
+    if ranges.is_empty() {
+        return Vec::new();
+    }
+
+    let mut sorted_ranges: Vec<_> = ranges.to_vec();
+    sorted_ranges.sort_by_key(|r| r.start);
+
+    let mut merged = Vec::new();
+    let mut current_start = sorted_ranges[0].start;
+    let mut current_end = sorted_ranges[0].end;
+
+    for range in sorted_ranges.iter().skip(1) {
+        let gap_start = current_end;
+        let gap_end = range.start;
+        let gap_size = gap_end.saturating_sub(gap_start);
+
+        if gap_size <= max_gap {
+            // Check if any other function occupies the gap
+            let gap_occupied = (gap_start..gap_end).any(|addr| {
+                reader
+                    .functions_containing(addr)
+                    .iter()
+                    .any(|&start| start != function_start)
+            });
+
+            if !gap_occupied {
+                current_end = current_end.max(range.end);
+            } else {
+                merged.push(current_start..current_end);
+                current_start = range.start;
+                current_end = range.end;
+            }
+        } else {
+            merged.push(current_start..current_end);
+            current_start = range.start;
+            current_end = range.end;
+        }
+    }
+
+    merged.push(current_start..current_end);
+    merged
+}
+
+fn collect_functions(
+    reader: &impl ViewReader,
+    options: &Options,
+    sections: &[model::Section],
+    ranges: &[LayoutRange],
+    mut cache: Option<&mut Cache>,
+    report: &mut Report,
+    progress: &mut dyn Progress,
+) -> Result<Vec<model::Function>> {
+    let managed = clr::managed_ranges(reader, report).unwrap_or_else(|err| {
+        report.warn(format!("Could not read the CLI header: {err:#}"));
+        Vec::new()
+    });
+    let mut view_functions = reader.functions();
+    view_functions.retain(|function| options.symbol_types.contains(&function.symbol_type));
+
+    // Analysis of mixed-mode images finds "functions" in IL bodies and metadata, which only
+    // confuse a native debugger
+    if !managed.is_empty() {
+        let base = reader.start();
+        let before = view_functions.len();
+        view_functions.retain(|function| {
+            // Managed ranges come from the image's headers, so starts are compared in its layout
+            let Some(rva) = u32::try_from(function.start.wrapping_sub(base))
+                .ok()
+                .and_then(|rva| layout::translate(ranges, rva))
+            else {
+                return true;
+            };
+            match managed.partition_point(|r| r.start <= rva) {
+                0 => true,
+                i => !managed[i - 1].contains(&rva),
+            }
+        });
+        info!(
+            "Left out {} function(s) inside managed code or metadata",
+            before - view_functions.len()
+        );
+    }
+
+    let mut collector = FunctionCollector {
+        reader,
+        base_address: reader.start(),
+        arch: Arch::from_machine(coff_machine(reader)?),
+        layout: ranges,
+        options,
+        names: Interner::default(),
+        report,
+        progress,
+        done: 0,
+        total: view_functions.len(),
+    };
+    let mut functions = Vec::new();
+
+    // Group by the section each function starts in within the image, so each module can be
+    // hashed and reused on its own. Functions outside every section form a group of their own.
+    let mut by_section: Vec<Vec<FunctionInfo>> = vec![Vec::new(); sections.len()];
+    let mut outside = Vec::new();
+    for function in view_functions {
+        let rva = function.start.wrapping_sub(collector.base_address);
+        let section = u32::try_from(rva)
+            .ok()
+            .and_then(|rva| layout::translate(ranges, rva))
+            .and_then(|rva| sections.iter().position(|s| s.range().contains(&rva)));
+        collector.report_type_issues(&function);
+        match section {
+            Some(i) => by_section[i].push(function),
+            None => outside.push(function),
+        }
+    }
+    let groups = sections
+        .iter()
+        .map(|section| section.name.as_str())
+        .zip(by_section)
+        .chain([(OUTSIDE_SECTIONS, outside)]);
+
+    let mut unmapped = 0;
+    for (module, section_functions) in groups {
+        let Some(cache) = cache.as_deref_mut() else {
+            let mut collected = Vec::with_capacity(section_functions.len());
+            for function in &section_functions {
+                collector.collect_into(function, &mut collected)?;
+            }
+            unmapped += layout::relocate_functions(&mut collected, ranges);
+            functions.extend(collected);
+            continue;
+        };
+
+        let hash = hash_functions(&section_functions, options);
+        if let Some(cached) = cache.get(module, hash) {
+            functions.extend_from_slice(cached);
+            collector.advance(section_functions.len())?;
+            continue;
+        }
+
+        let mut collected = Vec::with_capacity(section_functions.len());
+        for function in &section_functions {
+            collector.collect_into(function, &mut collected)?;
+        }
+        unmapped += layout::relocate_functions(&mut collected, ranges);
+        cache.insert(module.to_string(), hash, collected.clone());
+        functions.extend(collected);
+    }
+
+    if unmapped > 0 {
+        collector.report.warn(format!(
+            "Dropped {unmapped} function(s) at addresses the layout mapping doesn't cover"
+        ));
+    }
+
+    Ok(functions)
+}
+
+/// Number of int3 filler bytes at the end of `range`, never the whole range since a function that
+/// is nothing but int3 is a deliberate trap stub
+fn trailing_padding(reader: &impl ViewReader, range: &Range<u64>) -> u64 {
+    let len = (range.end - range.start).min(MAX_PADDING);
+    let tail = reader.read(range.end - len, len as usize);
+    let padding = tail.iter().rev().take_while(|&&b| b == INT3).count() as u64;
+    if padding == range.end - range.start {
+        0
+    } else {
+        padding
+    }
+}
+
+/// Hash of the options and of everything collection reads from a module's functions except
+/// the gap contents consulted while merging ranges, which only change along with a neighbouring
+/// function, and the padding bytes trimmed from range ends, which only change along with the code
+///
+/// All of the options are hashed, even those only emission reads, so none can be forgotten.
+fn hash_functions(functions: &[FunctionInfo], options: &Options) -> u64 {
+    let mut hasher = Fnv1a::default();
+    hash_json(options).hash(&mut hasher);
+    functions.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Module a function recognized as runtime or static library code belongs in, preferring the
+/// library a signature match named
+fn library_of(function: &FunctionInfo) -> Option<&str> {
+    if let Some(library) = &function.library {
+        return Some(library);
+    }
+
+    // 32-bit images decorate C names with an extra leading underscore
+    let raw = &function.raw_name;
+    let undecorated = raw.strip_prefix('_').unwrap_or(raw);
+    if CRT_PREFIXES
+        .iter()
+        .any(|prefix| raw.starts_with(prefix) || undecorated.starts_with(prefix))
+    {
+        Some(CRT_MODULE)
+    } else if function.symbol_type == SymbolType::LibraryFunction {
+        Some(LIBRARY_MODULE)
+    } else {
+        None
+    }
+}
+
+/// State shared while converting the view's functions into the model
+struct FunctionCollector<'a, R> {
+    reader: &'a R,
+    base_address: u64,
+    arch: Option<Arch>,
+    /// Mapping from the view's RVAs to the image's, see [`layout::derive`]
+    layout: &'a [LayoutRange],
+    options: &'a Options,
+    names: Interner,
+    report: &'a mut Report,
+    progress: &'a mut dyn Progress,
+    done: usize,
+    total: usize,
+}
+
+impl<R: ViewReader> FunctionCollector<'_, R> {
+    fn collect_into(
+        &mut self,
+        function: &FunctionInfo,
+        functions: &mut Vec<model::Function>,
+    ) -> Result<()> {
+        match self.collect(function) {
+            Ok(func) => functions.push(func),
+            Err(err) => self
+                .report
+                .skip(format!("function 0x{:x}", function.start), err),
+        }
+        self.advance(1)
+    }
+
+    /// Pass on the types the frontend had to approximate, whether or not the function itself
+    /// comes from the cache
+    fn report_type_issues(&mut self, function: &FunctionInfo) {
+        for issue in &function.type_issues {
+            // In the image's layout, like the RVAs emit reports issues at
+            let rva = issue.address.wrapping_sub(self.base_address) as u32;
+            self.report.type_issue(TypeIssue {
+                symbol: function.name.clone(),
+                rva: layout::translate(self.layout, rva).unwrap_or(rva),
+                item: issue.item.clone(),
+                type_name: issue.type_name.clone(),
+                reason: issue.reason.clone(),
+            });
+        }
+    }
+
+    fn advance(&mut self, count: usize) -> Result<()> {
+        self.done += count;
+        self.progress
+            .update("Collecting functions", self.done, self.total);
+        Ok(self.progress.check_cancelled()?)
+    }
+
+    fn collect(&mut self, function: &FunctionInfo) -> Result<model::Function> {
+        let import_library = function
+            .import_library
+            .as_ref()
+            .filter(|_| function.symbol_type == SymbolType::ImportedFunction);
+        let library = match (self.options.import_modules, import_library) {
+            (true, Some(library)) => Some(
+                self.names
+                    .intern(&format!("{IMPORT_MODULE_PREFIX}{library}")),
+            ),
+            _ if self.options.library_modules => {
+                library_of(function).map(|library| self.names.intern(library))
+            }
+            _ => None,
+        };
+        let (name, aliases) = function_name(function, self.options, self.arch);
+        let name = self.names.intern(&name);
+        let aliases = aliases
+            .iter()
+            .map(|alias| self.names.intern(alias))
+            .collect();
+        let component = function
+            .component
+            .as_deref()
+            .map(|component| self.names.intern(component));
+
+        let mut merged_ranges =
+            merge_function_ranges(&function.ranges, MAX_RANGE_GAP, self.reader, function.start);
+        if self.options.trim_padding {
+            for range in &mut merged_ranges {
+                range.end -= trailing_padding(self.reader, range);
+            }
+        }
+
+        let ranges = merged_ranges
+            .iter()
+            .map(|range| Ok(self.rva(range.start)?..self.rva(range.end)?))
+            .collect::<Result<_>>()?;
+        let call_sites = match self.options.call_site_types {
+            true => function
+                .call_sites
+                .iter()
+                .filter_map(|site| {
+                    Some(model::CallSite {
+                        rva: self.rva(site.address).ok()?,
+                        return_type: site.return_type,
+                        parameters: site.parameters.clone(),
+                    })
+                })
+                .collect(),
+            false => Vec::new(),
+        };
+        let heap_alloc_sites = match self.options.heap_alloc_sites {
+            true => function
+                .heap_alloc_sites
+                .iter()
+                .filter_map(|site| {
+                    Some(model::HeapAllocSite {
+                        rva: self.rva(site.address).ok()?,
+                        length: site.length,
+                        allocated: site.allocated,
+                    })
+                })
+                .collect(),
+            false => Vec::new(),
+        };
+        let calls = match self.options.cross_module_references {
+            true => {
+                let mut calls: Vec<u32> = function
+                    .calls
+                    .iter()
+                    .filter_map(|&target| self.rva(target).ok())
+                    .collect();
+                calls.sort_unstable();
+                calls.dedup();
+                calls
+            }
+            false => Vec::new(),
+        };
+
+        let blocks = match self.options.basic_block_labels {
+            true => {
+                let mut blocks: Vec<u32> = function
+                    .blocks
+                    .iter()
+                    .filter_map(|&block| self.rva(block).ok())
+                    .collect();
+                blocks.sort_unstable();
+                blocks
+            }
+            false => Vec::new(),
+        };
+
+        let (parameters, entry_length) = match self.options.parameter_locations {
+            true => (function.parameters.clone(), function.entry_length),
+            false => (Vec::new(), 0),
+        };
+
+        Ok(model::Function {
+            name,
+            ranges,
+            kind: function.kind,
+            component,
+            aliases,
+            library,
+            parameters,
+            entry_length,
+            call_sites,
+            heap_alloc_sites,
+            calls,
+            frame: function.frame.filter(|_| self.options.frame_info),
+            blocks,
+        })
+    }
+
+    fn rva(&self, addr: u64) -> Result<u32> {
+        addr.checked_sub(self.base_address)
+            .and_then(|rva| u32::try_from(rva).ok())
+            .with_context(|| format!("address 0x{addr:x} is outside the image"))
+    }
+}
@@ -0,0 +1,156 @@
+//! Changes to the DBI stream of a committed PDB that pdb-sdk has no builder for
+
+use anyhow::{Context as _, Result, bail, ensure};
+
+use crate::msf::Editor;
+use crate::options::DbiHeader;
+
+/// Index of the DBI stream in the MSF directory
+const DBI_STREAM: usize = 3;
+
+// Offsets within DBI_STREAM_HEADER
+const VERSION_HEADER: u32 = 4;
+const BUILD_NUMBER: u32 = 14;
+const PDB_DLL_VERSION: u32 = 18;
+const PDB_DLL_RBLD: u32 = 22;
+const FLAGS: u32 = 56;
+const MACHINE: u32 = 58;
+const MOD_INFO_SIZE: u32 = 24;
+const SECTION_CONTRIBUTION_SIZE: u32 = 28;
+const SECTION_MAP_SIZE: u32 = 32;
+const SOURCE_INFO_SIZE: u32 = 36;
+const TYPE_SERVER_MAP_SIZE: u32 = 40;
+const OPTIONAL_DBG_HEADER_SIZE: u32 = 48;
+const EC_SUBSTREAM_SIZE: u32 = 52;
+const HEADER_SIZE: u32 = 64;
+
+// Offsets within a ModInfo record of the module info substream
+const MODULE_STREAM: usize = 34;
+const SYMBOL_BYTES: usize = 36;
+const C11_BYTES: usize = 40;
+const C13_BYTES: usize = 44;
+/// Fixed part of a ModInfo record, followed by the NUL terminated module and object file names
+/// and padding to 4 bytes
+const MOD_INFO_FIXED_SIZE: usize = 64;
+
+/// Substreams stored between the header and the optional debug header, in file order
+const SUBSTREAMS_BEFORE_DEBUG_HEADER: [u32; 6] = [
+    MOD_INFO_SIZE,
+    SECTION_CONTRIBUTION_SIZE,
+    SECTION_MAP_SIZE,
+    SOURCE_INFO_SIZE,
+    TYPE_SERVER_MAP_SIZE,
+    EC_SUBSTREAM_SIZE,
+];
+
+/// Slots of the optional debug header, an array of stream indices following the substreams
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum DebugStream {
+    Exception = 1,
+}
+
+/// Overwrite every field of the committed PDB's DBI header that `header` sets
+pub(crate) fn patch_header(msf: &mut Editor, header: &DbiHeader) -> Result<()> {
+    if let Some(version) = header.version {
+        msf.patch_stream(DBI_STREAM, VERSION_HEADER, &version.to_le_bytes())?;
+    }
+    let fields = [
+        (BUILD_NUMBER, header.build_number),
+        (PDB_DLL_VERSION, header.pdb_dll_version),
+        (PDB_DLL_RBLD, header.pdb_dll_rebuild),
+        (FLAGS, header.flags),
+        (MACHINE, header.machine),
+    ];
+    for (offset, value) in fields {
+        if let Some(value) = value {
+            msf.patch_stream(DBI_STREAM, offset, &value.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Store `data` in a new stream and point `slot` of the DBI optional debug header at it
+pub(crate) fn add_debug_stream(msf: &mut Editor, slot: DebugStream, data: Vec<u8>) -> Result<()> {
+    let header = msf.read_stream(DBI_STREAM, 0, HEADER_SIZE as usize)?;
+    let size_at = |offset: u32| {
+        let offset = offset as usize;
+        u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap())
+    };
+    let slot_offset = slot as u32 * 2;
+    ensure!(
+        slot_offset + 2 <= size_at(OPTIONAL_DBG_HEADER_SIZE),
+        "DBI optional debug header has no slot for the {slot:?} stream"
+    );
+    let debug_header = HEADER_SIZE
+        + SUBSTREAMS_BEFORE_DEBUG_HEADER
+            .into_iter()
+            .map(size_at)
+            .sum::<u32>();
+
+    let stream = msf.add_stream(data)?;
+    msf.patch_stream(
+        DBI_STREAM,
+        debug_header + slot_offset,
+        &stream.to_le_bytes(),
+    )
+}
+
+/// A module as its ModInfo record describes it
+pub(crate) struct ModuleInfo {
+    pub name: String,
+    /// Offset of the ModInfo record in the DBI stream
+    record: u32,
+    /// Stream holding the module's symbols and line information, if it has one
+    pub stream: Option<usize>,
+    /// Size of the symbols at the start of the stream, including the signature
+    pub symbol_bytes: u32,
+    pub c11_bytes: u32,
+    /// Size of the C13 debug subsections following the symbols and any C11 lines
+    pub c13_bytes: u32,
+}
+
+/// Every module of the committed PDB, in the order of the module info substream
+pub(crate) fn modules(msf: &Editor) -> Result<Vec<ModuleInfo>> {
+    let header = msf.read_stream(DBI_STREAM, 0, HEADER_SIZE as usize)?;
+    let size = u32_at(&header, MOD_INFO_SIZE as usize);
+    let data = msf.read_stream(DBI_STREAM, HEADER_SIZE, size as usize)?;
+
+    let mut modules = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let record = data
+            .get(offset..offset + MOD_INFO_FIXED_SIZE)
+            .context("module info substream ends inside a record")?;
+        let mut names = data[offset + MOD_INFO_FIXED_SIZE..].splitn(3, |&b| b == 0);
+        let (Some(name), Some(object), Some(_)) = (names.next(), names.next(), names.next()) else {
+            bail!(
+                "module info record {} has unterminated names",
+                modules.len()
+            );
+        };
+        let stream = u16::from_le_bytes([record[MODULE_STREAM], record[MODULE_STREAM + 1]]);
+        modules.push(ModuleInfo {
+            name: String::from_utf8_lossy(name).into_owned(),
+            record: HEADER_SIZE + offset as u32,
+            stream: (stream != u16::MAX).then_some(stream as usize),
+            symbol_bytes: u32_at(record, SYMBOL_BYTES),
+            c11_bytes: u32_at(record, C11_BYTES),
+            c13_bytes: u32_at(record, C13_BYTES),
+        });
+        offset = (offset + MOD_INFO_FIXED_SIZE + name.len() + object.len() + 2).next_multiple_of(4);
+    }
+    Ok(modules)
+}
+
+/// Record a new size for the C13 debug subsections of `module`
+pub(crate) fn set_c13_bytes(msf: &mut Editor, module: &ModuleInfo, size: u32) -> Result<()> {
+    msf.patch_stream(
+        DBI_STREAM,
+        module.record + C13_BYTES as u32,
+        &size.to_le_bytes(),
+    )
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
@@ -0,0 +1,183 @@
+//! PDBs for a few functions changed since the last full run
+
+use anyhow::{Result, ensure};
+use std::collections::HashSet;
+use tracing::info;
+
+use crate::collect::coverage;
+use crate::{Cache, Model, Options};
+
+/// Model of the whole image from the functions `cache` holds from the last full run, with
+/// those starting at any of `replaced` swapped for the functions of `delta`
+///
+/// `replaced` are RVAs in the image's layout, as [`image_rvas`](crate::image_rvas) gives them.
+///
+/// `delta` is collected from only the changed functions, while everything else in it (sections,
+/// publics, vtables, types) describes the whole image and is kept as is. The cache is updated so
+/// repeated deltas build on each other.
+///
+/// Fails if the cache was collected with other options than `delta`, whose functions would be
+/// named or laid out differently from the cached ones.
+pub fn merge_delta(
+    cache: &mut Cache,
+    mut delta: Model,
+    replaced: &[u32],
+    options: &Options,
+) -> Result<Model> {
+    ensure!(
+        cache.matches(options),
+        "the incremental cache was collected with other options"
+    );
+    let starts: HashSet<u32> = replaced
+        .iter()
+        .copied()
+        .chain(
+            delta
+                .functions
+                .iter()
+                .filter_map(|f| f.ranges.first())
+                .map(|r| r.start),
+        )
+        .collect();
+    let updated = delta.functions.len();
+    cache.replace(
+        &starts,
+        std::mem::take(&mut delta.functions),
+        &delta.sections,
+    );
+
+    let functions: Vec<_> = cache.functions().cloned().collect();
+    info!(
+        "Merged {updated} updated function(s) into {} from the last run",
+        functions.len() - updated
+    );
+
+    // Placeholders were made for whatever the delta alone didn't cover
+    let is_covered = coverage(&functions);
+    delta
+        .unwind_functions
+        .retain(|f| f.ranges.first().is_none_or(|r| !is_covered(r.start)));
+    // Likewise for entry points, which only get a public when no function starts there
+    let function_starts: HashSet<u32> = functions
+        .iter()
+        .filter_map(|f| f.ranges.first())
+        .map(|r| r.start)
+        .collect();
+    delta
+        .publics
+        .retain(|p| !p.function || !function_starts.contains(&p.rva));
+    delta.functions = functions;
+    Ok(delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::OUTSIDE_SECTIONS;
+    use crate::model::{Function, Public, Section};
+
+    fn function(name: &str, start: u32, end: u32) -> Function {
+        Function {
+            name: name.into(),
+            ranges: vec![start..end],
+            ..Default::default()
+        }
+    }
+
+    fn cache(options: &Options) -> Cache {
+        let missing = std::env::temp_dir().join("pdbgen-delta-test-missing.json");
+        let mut cache = Cache::load(&missing, options);
+        cache.insert(
+            ".text".to_string(),
+            1,
+            vec![function("a", 0x1000, 0x1010), function("b", 0x1010, 0x1020)],
+        );
+        cache
+    }
+
+    #[test]
+    fn replaces_changed_functions() {
+        let options = Options::default();
+        let mut cache = cache(&options);
+        let delta = Model {
+            sections: vec![Section {
+                name: ".text".to_string(),
+                virtual_address: 0x1000,
+                virtual_size: 0x1000,
+                ..Default::default()
+            }],
+            functions: vec![function("b2", 0x1010, 0x1030)],
+            unwind_functions: vec![function("", 0x1020, 0x1028), function("", 0x1040, 0x1048)],
+            publics: vec![
+                Public {
+                    name: "b_entry".into(),
+                    rva: 0x1010,
+                    function: true,
+                },
+                Public {
+                    name: "data".into(),
+                    rva: 0x1010,
+                    function: false,
+                },
+            ],
+            ..Default::default()
+        };
+
+        // a was deleted, b changed into b2
+        let merged = merge_delta(&mut cache, delta, &[0x1000], &options).unwrap();
+        let names: Vec<_> = merged.functions.iter().map(|f| &*f.name).collect();
+        assert_eq!(names, ["b2"]);
+        assert_eq!(merged.unwind_functions.len(), 1);
+        assert_eq!(merged.unwind_functions[0].ranges, [0x1040..0x1048]);
+        let publics: Vec<_> = merged.publics.iter().map(|p| &*p.name).collect();
+        assert_eq!(publics, ["data"]);
+
+        // The next delta builds on this one
+        let names: Vec<_> = cache.functions().map(|f| &*f.name).collect();
+        assert_eq!(names, ["b2"]);
+    }
+
+    #[test]
+    fn keeps_functions_outside_every_section() {
+        let options = Options::default();
+        let mut cache = cache(&options);
+        cache.insert(
+            OUTSIDE_SECTIONS.to_string(),
+            2,
+            vec![function("overlay", 0x9000, 0x9010)],
+        );
+        let delta = Model {
+            sections: vec![Section {
+                name: ".text".to_string(),
+                virtual_address: 0x1000,
+                virtual_size: 0x1000,
+                ..Default::default()
+            }],
+            functions: vec![
+                function("a2", 0x1000, 0x1010),
+                function("stub", 0x9800, 0x9810),
+            ],
+            ..Default::default()
+        };
+
+        let merged = merge_delta(&mut cache, delta, &[], &options).unwrap();
+        let mut names: Vec<_> = merged.functions.iter().map(|f| &*f.name).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["a2", "b", "overlay", "stub"]);
+
+        // The delta's own out of section function is there for the next one too
+        let mut names: Vec<_> = cache.functions().map(|f| &*f.name).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["a2", "b", "overlay", "stub"]);
+    }
+
+    #[test]
+    fn rejects_caches_collected_with_other_options() {
+        let mut cache = cache(&Options::default());
+        let options = Options {
+            vtables: !Options::default().vtables,
+            ..Default::default()
+        };
+        assert!(merge_delta(&mut cache, Model::default(), &[], &options).is_err());
+    }
+}
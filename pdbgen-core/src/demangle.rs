@@ -0,0 +1,255 @@
+//! Turning the mangled names of non-MSVC toolchains into something a Windows debugger shows well
+
+use cpp_demangle::{DemangleOptions, Symbol};
+
+use crate::Options;
+use crate::options::{ItaniumNames, NameForm, NamePrecedence, RustNames};
+use crate::reader::FunctionInfo;
+use crate::registers::Arch;
+
+/// Name to emit for `function` and any further names to emit as publics alongside it
+///
+/// When the user renamed a function the analysis had also named, [`Options::name_precedence`]
+/// picks which name leads and [`Options::shadowed_name_publics`] keeps the other as an alias.
+/// `arch` selects the calling convention of MSVC decorated member functions.
+pub(crate) fn function_name(
+    function: &FunctionInfo,
+    options: &Options,
+    arch: Option<Arch>,
+) -> (String, Vec<String>) {
+    let user = Names {
+        raw: &function.raw_name,
+        short: &function.name,
+        full: &function.full_name,
+        auto: function.auto,
+    };
+    let shadowed = function.auto_symbol.as_ref().map(|auto| Names {
+        raw: &auto.raw_name,
+        short: &auto.name,
+        full: &auto.full_name,
+        auto: true,
+    });
+    let (primary, secondary) = match (shadowed, options.name_precedence) {
+        (Some(auto), NamePrecedence::Auto) => (auto, Some(user)),
+        (shadowed, _) => (user, shadowed),
+    };
+
+    let (name, mut aliases) = symbol_name(&primary, options, arch);
+    if options.shadowed_name_publics
+        && let Some(secondary) = secondary
+    {
+        let (other, _) = symbol_name(&secondary, options, arch);
+        if other != name {
+            aliases.push(other);
+        }
+    }
+    (name, aliases)
+}
+
+/// The forms of one symbol's name
+struct Names<'a> {
+    raw: &'a str,
+    short: &'a str,
+    full: &'a str,
+    /// Defined by a loader or analysis rather than the user
+    auto: bool,
+}
+
+/// Name to emit for a symbol, demangling its raw name if it is Rust or Itanium mangled, and the
+/// raw name as an alias when [`Options::rust_mangled_publics`] asks for it
+fn symbol_name(names: &Names, options: &Options, arch: Option<Arch>) -> (String, Vec<String>) {
+    if options.name_form == NameForm::Raw || (options.raw_auto_names && names.auto) {
+        return (names.raw.to_string(), Vec::new());
+    }
+    let fallback = name_in_form(options.name_form, names.raw, names.short, names.full);
+
+    // Legacy Rust names are valid Itanium names too, so Rust gets the first look
+    if options.rust_names != RustNames::Keep
+        && let Ok(demangled) = rustc_demangle::try_demangle(names.raw)
+    {
+        let name = match options.rust_names {
+            RustNames::Full => format!("{demangled}"),
+            _ => format!("{demangled:#}"),
+        };
+        let aliases = match options.rust_mangled_publics {
+            true => vec![names.raw.to_string()],
+            false => Vec::new(),
+        };
+        return (name, aliases);
+    }
+
+    let itanium = options.itanium_names;
+    if itanium == ItaniumNames::Keep || !is_itanium(names.raw) {
+        return (fallback.to_string(), Vec::new());
+    }
+    let full = options.name_form == NameForm::Full;
+    let Some(demangled) = demangle_itanium(names.raw, full) else {
+        return (fallback.to_string(), Vec::new());
+    };
+    let name = match itanium {
+        ItaniumNames::Msvc if !full => {
+            msvc_decorate(&demangled, names.raw, arch).unwrap_or(demangled)
+        }
+        _ => demangled,
+    };
+    (name, Vec::new())
+}
+
+/// Mach-O style names carry an extra leading underscore
+fn is_itanium(name: &str) -> bool {
+    name.starts_with("_Z") || name.starts_with("__Z")
+}
+
+/// Qualified name, with the full signature if `full` and otherwise without parameters or return
+/// type as Binary Ninja's short names look
+fn demangle_itanium(raw_name: &str, full: bool) -> Option<String> {
+    let mangled = raw_name.strip_prefix('_').filter(|n| n.starts_with("_Z"));
+    let symbol = Symbol::new(mangled.unwrap_or(raw_name)).ok()?;
+    let options = match full {
+        true => DemangleOptions::new(),
+        false => DemangleOptions::new().no_params().no_return_type(),
+    };
+    symbol.demangle(&options).ok()
+}
+
+/// Re-encode a qualified name as an MSVC decorated name, so Visual Studio groups functions by
+/// the scopes in their name
+///
+/// The signature is unknown, so functions are decorated as taking and returning nothing. A
+/// scope is only known to be a class for constructors, destructors and const member functions,
+/// and the encoding of a free function would be wrong for anything else in a scope as often as
+/// not, so those are left alone along with names that don't split into plain identifiers.
+fn msvc_decorate(qualified: &str, raw_name: &str, arch: Option<Arch>) -> Option<String> {
+    let parts: Vec<&str> = qualified.split("::").collect();
+    let is_identifier = |part: &&str| {
+        !part.is_empty()
+            && !part.starts_with(|c: char| c.is_ascii_digit())
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+
+    let (&last, scopes) = parts.split_last()?;
+    if !scopes.iter().all(is_identifier) {
+        return None;
+    }
+    let Some(&class) = scopes.last() else {
+        return is_identifier(&last).then(|| format!("?{last}@@YAXXZ"));
+    };
+    let scope: String = scopes.iter().rev().map(|s| format!("{s}@")).collect();
+
+    // Access, `this` qualifiers and calling convention of a public member function: __thiscall
+    // on x86, __cdecl with a __ptr64 `this` on 64 bit machines
+    let member = |constant: bool| match (arch?, constant) {
+        (Arch::X86, false) => Some("QAE"),
+        (Arch::X86, true) => Some("QBE"),
+        (_, false) => Some("QEAA"),
+        (_, true) => Some("QEBA"),
+    };
+    if last == class {
+        Some(format!("??0{scope}@{}@XZ", member(false)?))
+    } else if last.strip_prefix('~') == Some(class) {
+        Some(format!("??1{scope}@{}@XZ", member(false)?))
+    } else if is_identifier(&last) && is_const_member(raw_name) {
+        Some(format!("?{last}@{scope}@{}XXZ", member(true)?))
+    } else {
+        None
+    }
+}
+
+/// Whether an Itanium mangled name is a const member function, whose nested name starts with
+/// the `K` qualifier of `this` (`_ZNK1A3getEv`)
+fn is_const_member(raw_name: &str) -> bool {
+    let mangled = raw_name
+        .strip_prefix('_')
+        .filter(|n| n.starts_with("_Z"))
+        .unwrap_or(raw_name);
+    mangled
+        .strip_prefix("_ZN")
+        .is_some_and(|rest| rest.trim_start_matches(['r', 'V']).starts_with('K'))
+}
+
+/// The form of a symbol's name `form` selects
+pub(crate) fn name_in_form<'a>(
+    form: NameForm,
+    raw: &'a str,
+    short: &'a str,
+    full: &'a str,
+) -> &'a str {
+    match form {
+        NameForm::Raw => raw,
+        NameForm::Short => short,
+        NameForm::Full => full,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decorates_global_functions_and_special_members() {
+        let decorate = |qualified, raw_name, arch| msvc_decorate(qualified, raw_name, arch);
+        let x64 = Some(Arch::X64);
+        let x86 = Some(Arch::X86);
+        assert_eq!(decorate("f", "_Z1fv", x64).as_deref(), Some("?f@@YAXXZ"));
+        assert_eq!(
+            decorate("a::Foo::Foo", "_ZN1a3FooC2Ev", x64).as_deref(),
+            Some("??0Foo@a@@QEAA@XZ")
+        );
+        assert_eq!(
+            decorate("a::Foo::Foo", "_ZN1a3FooC2Ev", x86).as_deref(),
+            Some("??0Foo@a@@QAE@XZ")
+        );
+        assert_eq!(
+            decorate("Foo::~Foo", "_ZN3FooD2Ev", x64).as_deref(),
+            Some("??1Foo@@QEAA@XZ")
+        );
+        assert_eq!(
+            decorate("Foo::get", "_ZNK3Foo3getEv", x64).as_deref(),
+            Some("?get@Foo@@QEBAXXZ")
+        );
+        assert_eq!(
+            decorate("Foo::get", "__ZNK3Foo3getEv", x86).as_deref(),
+            Some("?get@Foo@@QBEXXZ")
+        );
+    }
+
+    #[test]
+    fn leaves_ambiguous_names_undecorated() {
+        let x64 = Some(Arch::X64);
+        // A namespace function and a non-const member look the same
+        assert_eq!(msvc_decorate("ns::f", "_ZN2ns1fEv", x64), None);
+        // Member encodings depend on the machine
+        assert_eq!(msvc_decorate("Foo::Foo", "_ZN3FooC2Ev", None), None);
+        assert_eq!(
+            msvc_decorate("std::vector<int>::size", "_ZNKSt6vectorIiE4sizeEv", x64),
+            None
+        );
+        assert_eq!(msvc_decorate("operator new", "_Znwm", x64), None);
+    }
+
+    #[test]
+    fn recognizes_const_members() {
+        assert!(is_const_member("_ZNK3Foo3getEv"));
+        assert!(is_const_member("__ZNK3Foo3getEv"));
+        assert!(is_const_member("_ZNVK3Foo3getEv"));
+        assert!(!is_const_member("_ZN3Foo3getEv"));
+        assert!(!is_const_member("_Z3getv"));
+    }
+
+    #[test]
+    fn demangles_itanium_names() {
+        assert_eq!(
+            demangle_itanium("_ZN2ns1fEi", false).as_deref(),
+            Some("ns::f")
+        );
+        assert_eq!(
+            demangle_itanium("__ZN2ns1fEi", false).as_deref(),
+            Some("ns::f")
+        );
+        assert_eq!(
+            demangle_itanium("_ZN2ns1fEi", true).as_deref(),
+            Some("ns::f(int)")
+        );
+        assert_eq!(demangle_itanium("not_mangled", false), None);
+    }
+}
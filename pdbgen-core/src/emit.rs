@@ -0,0 +1,1797 @@
+use anyhow::{Context as _, Result, ensure};
+use pdb_sdk::Guid;
+use pdb_sdk::builders::{ModuleBuilder, PdbBuilder};
+use pdb_sdk::codeview::DataRegionOffset;
+use pdb_sdk::codeview::symbols::UserDefinedType;
+use pdb_sdk::codeview::symbols::{
+    CallSiteInfo, CoffGroup, Data, DefRangeRegister, DefRangeRegisterRel, FrameProc,
+    FrameProcFlags, HeapAllocationSite, Label, Local, LocalVariableAddrRange, LocalVariableFlags,
+    Procedure, ProcedureProperties, Public, PublicProperties, Section as SectionSymbol,
+    SymbolRecord, Thunk, ThunkOrdinal,
+};
+use pdb_sdk::codeview::types::{
+    CallingConvention, FieldAttributes, FieldRecord, FunctionProperties, MemberAccess,
+    PointerAttributes, PointerKind, StructProperties, TypeIndex, TypeRecord,
+};
+use pdb_sdk::dbi::{SectionContrib, SectionHeader};
+use pdb_sdk::utils::StrBuf;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use tracing::info;
+
+use crate::collect::IMPORT_MODULE_PREFIX;
+use crate::model::{
+    CallSite, Frame, Function, FunctionKind, Location, Member, Model, Section, TypeDefinition,
+    TypeDefinitionKind, TypeReference, ValueKind, ValueType,
+};
+use crate::names::NameRules;
+use crate::registers::{Arch, cv_register};
+use crate::types::{TypeCache, TypeKey};
+use crate::{CANARY, ModuleLayout, Options, Progress, Report, TypeIssue};
+
+/// Name link.exe gives the module holding linker-generated symbols
+const LINKER_MODULE: &str = "* Linker *";
+// Primitive CodeView type indices
+const T_NOTYPE: TypeIndex = TypeIndex(0x0000);
+const T_VOID: TypeIndex = TypeIndex(0x0003);
+const T_UCHAR: TypeIndex = TypeIndex(0x0020);
+const T_USHORT: TypeIndex = TypeIndex(0x0021);
+const T_ULONG: TypeIndex = TypeIndex(0x0022);
+const T_UQUAD: TypeIndex = TypeIndex(0x0023);
+const T_REAL32: TypeIndex = TypeIndex(0x0040);
+const T_REAL64: TypeIndex = TypeIndex(0x0041);
+const T_REAL80: TypeIndex = TypeIndex(0x0042);
+const T_32PVOID: TypeIndex = TypeIndex(0x0403);
+const T_64PVOID: TypeIndex = TypeIndex(0x0603);
+/// Section alignment as a power of two, the 4K link.exe uses unless told otherwise
+const SECTION_ALIGNMENT_LOG2: u8 = 12;
+/// Largest module symbol stream or symbol record stream, since Microsoft's reader addresses
+/// records in both with signed 32 bit offsets
+const MAX_SYMBOL_STREAM_BYTES: u64 = i32::MAX as u64;
+/// Upper bound on the bytes of a symbol record besides its name, covering the length and
+/// kind fields, the largest fixed part pdbgen emits, the NUL terminator and padding
+const MAX_RECORD_FIXED_BYTES: u64 = 64;
+/// Largest LF_FIELDLIST, past which a field list continues in another record through LF_INDEX
+const MAX_FIELD_LIST_BYTES: usize = 0xff00;
+/// Upper bound on the bytes of an LF_MEMBER, LF_ENUMERATE or LF_INDEX besides its name
+const MAX_FIELD_FIXED_BYTES: usize = 24;
+/// Longest chain of aliases followed before a type is left as T_NOTYPE
+const MAX_ALIAS_DEPTH: usize = 16;
+/// Longest suffix added to a function name for a record, `$bb_0x` and a 64 bit block address
+const MAX_NAME_SUFFIX_BYTES: u64 = 22;
+
+/// Build a PDB from the model, recording anything that had to be skipped in `report`
+pub fn build_pdb(
+    model: &Model,
+    options: &Options,
+    report: &mut Report,
+    progress: &mut dyn Progress,
+) -> Result<PdbBuilder> {
+    let mut builder = PdbBuilder::default();
+    builder.info().guid(Guid(model.info.guid));
+    builder.info().age(model.info.age);
+    builder.info().signature(model.info.timestamp);
+
+    let canary = format!(
+        "PDB generated by: binja_pdbgen/{CANARY} https://github.com/trumank/binja_pdbgen\0"
+    );
+    // The canary is what marks the PDB as generated, so its room is set aside first
+    let mut globals = GlobalBudget::new(MAX_RECORD_FIXED_BYTES + canary.len() as u64);
+
+    build_sections(model, &mut builder);
+    let mut types = TypeCache::default();
+    build_functions(
+        model,
+        options,
+        &mut builder,
+        &mut types,
+        &mut globals,
+        report,
+        progress,
+    )?;
+    build_publics(model, options, &mut builder, &mut globals, report);
+    build_vtables(
+        model,
+        options,
+        &mut builder,
+        &mut types,
+        &mut globals,
+        report,
+    );
+    build_types(model, &mut builder, &mut types, &mut globals, report);
+    build_linker_module(model, options, &mut builder);
+    globals.finish(report);
+
+    // Add pdbgen_canary marker to identify PDBs generated by this tool
+    builder
+        .dbi()
+        .symbols()
+        .finish_publics()
+        .add(SymbolRecord::EnvBlock {
+            reserved: 0,
+            fields: vec![StrBuf::new(canary)],
+        });
+
+    Ok(builder)
+}
+
+fn build_sections(model: &Model, builder: &mut PdbBuilder) {
+    for section in &model.sections {
+        info!(
+            "Adding section: {} (VA: 0x{:x}, Size: 0x{:x})",
+            section.name,
+            section.virtual_address,
+            section.size()
+        );
+
+        builder.dbi().add_section_header(SectionHeader {
+            name: section.raw_name(),
+            virtual_size: section.virtual_size,
+            virtual_address: section.virtual_address,
+            size_of_raw_data: section.size_of_raw_data,
+            pointer_to_raw_data: section.pointer_to_raw_data,
+            pointer_to_relocations: section.pointer_to_relocations,
+            pointer_to_line_numbers: section.pointer_to_line_numbers,
+            number_of_relocations: section.number_of_relocations,
+            number_of_line_numbers: section.number_of_line_numbers,
+            characteristics: section.characteristics,
+        });
+    }
+}
+
+fn build_publics(
+    model: &Model,
+    options: &Options,
+    builder: &mut PdbBuilder,
+    globals: &mut GlobalBudget,
+    report: &mut Report,
+) {
+    let mut discarded = 0;
+    let mut excluded = 0;
+    for public in &model.publics {
+        let Some(section_idx) = section_index_of(&model.sections, public.rva) else {
+            report.skip(
+                format!("public {}", public.name),
+                anyhow::anyhow!("RVA 0x{:x} is not inside any section", public.rva),
+            );
+            continue;
+        };
+        let section = &model.sections[section_idx as usize - 1];
+        if options.skip_discardable_sections && section.is_discardable() {
+            discarded += 1;
+            continue;
+        }
+        if !options.includes_section(&section.name) {
+            excluded += 1;
+            continue;
+        }
+        if !globals.claim(MAX_RECORD_FIXED_BYTES + public.name.len() as u64) {
+            globals.publics += 1;
+            continue;
+        }
+        builder.dbi().symbols().add(Public {
+            properties: PublicProperties::new().with_is_function(public.function),
+            offset: DataRegionOffset::new(public.rva - section.virtual_address, section_idx),
+            name: StrBuf::new(&*public.name),
+        });
+    }
+    if discarded > 0 {
+        info!("Left out {discarded} public symbol(s) in discardable sections");
+    }
+    if excluded > 0 {
+        info!("Left out {excluded} public symbol(s) in sections not selected");
+    }
+}
+
+/// Every vtable as an S_GDATA32 typed as an array of pointers, so a debugger shows its slots
+fn build_vtables(
+    model: &Model,
+    options: &Options,
+    builder: &mut PdbBuilder,
+    types: &mut TypeCache,
+    globals: &mut GlobalBudget,
+    report: &mut Report,
+) {
+    for vtable in &model.vtables {
+        let Some(section_idx) = section_index_of(&model.sections, vtable.rva) else {
+            report.skip(
+                format!("vtable {}", vtable.name),
+                anyhow::anyhow!("RVA 0x{:x} is not inside any section", vtable.rva),
+            );
+            continue;
+        };
+        let section = &model.sections[section_idx as usize - 1];
+        if options.skip_discardable_sections && section.is_discardable() {
+            continue;
+        }
+        if !options.includes_section(&section.name) {
+            continue;
+        }
+        if !globals.claim(MAX_RECORD_FIXED_BYTES + vtable.name.len() as u64) {
+            globals.vtables += 1;
+            continue;
+        }
+
+        let (element_type, index_type) = match vtable.slot_size {
+            8 => (T_64PVOID, T_UQUAD),
+            _ => (T_32PVOID, T_ULONG),
+        };
+        let size = vtable.slot_size as u64 * vtable.slot_count as u64;
+        let key = TypeKey::PointerArray {
+            slot_size: vtable.slot_size,
+            count: vtable.slot_count,
+        };
+        let array_type = types.get_or_insert_with(key, || {
+            builder.tpi().add(
+                "vtable",
+                TypeRecord::Array {
+                    element_type,
+                    index_type,
+                    size,
+                    name: StrBuf::new(""),
+                },
+            )
+        });
+
+        builder.dbi().symbols().add(SymbolRecord::GlobalData(Data {
+            type_index: array_type,
+            offset: DataRegionOffset::new(vtable.rva - section.virtual_address, section_idx),
+            name: StrBuf::new(&*vtable.name),
+        }));
+    }
+}
+
+/// Every type definition as a TPI record named by an S_UDT, so a debugger can show and cast to
+/// the types the frontend knows
+fn build_types(
+    model: &Model,
+    builder: &mut PdbBuilder,
+    types: &mut TypeCache,
+    globals: &mut GlobalBudget,
+    report: &mut Report,
+) {
+    if model.types.is_empty() {
+        return;
+    }
+    let Some(arch) = Arch::from_machine(model.machine) else {
+        report.warn(format!(
+            "Left out {} type definition(s), pointers of machine 0x{:04x} can't be described",
+            model.types.len(),
+            model.machine
+        ));
+        return;
+    };
+
+    let mut definitions = HashMap::new();
+    for definition in &model.types {
+        definitions.entry(&*definition.name).or_insert(definition);
+    }
+    let mut emitter = TypeEmitter {
+        builder,
+        types,
+        arch,
+        definitions,
+    };
+    let mut emitted = HashSet::new();
+    for definition in &model.types {
+        if !emitted.insert(&*definition.name) {
+            continue;
+        }
+        if !globals.claim(MAX_RECORD_FIXED_BYTES + definition.name.len() as u64) {
+            globals.types += 1;
+            continue;
+        }
+        let type_index = emitter.definition(definition);
+        emitter
+            .builder
+            .dbi()
+            .symbols()
+            .add(SymbolRecord::UserDefinedType(UserDefinedType {
+                type_index,
+                name: StrBuf::new(&*definition.name),
+            }));
+    }
+    info!("Added {} type definition(s)", emitted.len());
+}
+
+/// Emits [`TypeDefinition`]s and what they refer to, each distinct record once
+struct TypeEmitter<'a> {
+    builder: &'a mut PdbBuilder,
+    types: &'a mut TypeCache,
+    arch: Arch,
+    /// The first definition of each name
+    definitions: HashMap<&'a str, &'a TypeDefinition>,
+}
+
+impl<'a> TypeEmitter<'a> {
+    /// Index of the complete record of `definition`, or of the type an alias names
+    fn definition(&mut self, definition: &TypeDefinition) -> TypeIndex {
+        let name = StrBuf::new(&*definition.name);
+        match &definition.kind {
+            TypeDefinitionKind::Structure { size, members } => {
+                let (count, field_list) = self.members(members);
+                self.builder.tpi().add(
+                    "structure",
+                    TypeRecord::Structure {
+                        count,
+                        properties: StructProperties::new(),
+                        field_list,
+                        derived_from: None,
+                        vtable_shape: None,
+                        size: *size as u64,
+                        name,
+                        unique_name: None,
+                    },
+                )
+            }
+            TypeDefinitionKind::Union { size, members } => {
+                let (count, field_list) = self.members(members);
+                self.builder.tpi().add(
+                    "union",
+                    TypeRecord::Union {
+                        count,
+                        properties: StructProperties::new(),
+                        field_list,
+                        size: *size as u64,
+                        name,
+                        unique_name: None,
+                    },
+                )
+            }
+            TypeDefinitionKind::Enumeration { size, members } => {
+                let fields = members
+                    .iter()
+                    .map(|member| {
+                        let field = FieldRecord::Enumerate {
+                            attributes: FieldAttributes::new().with_access(MemberAccess::Public),
+                            value: member.value,
+                            name: StrBuf::new(&*member.name),
+                        };
+                        (field, member.name.len())
+                    })
+                    .collect();
+                let field_list = self.field_list(fields);
+                let underlying_type = self.underlying_type(*size);
+                self.builder.tpi().add(
+                    "enum",
+                    TypeRecord::Enum {
+                        count: field_count(members.len()),
+                        properties: StructProperties::new(),
+                        underlying_type,
+                        field_list,
+                        name,
+                        unique_name: None,
+                    },
+                )
+            }
+            TypeDefinitionKind::Alias(target) => self.reference(target, 0),
+        }
+    }
+
+    /// Member count and field list of a structure or union
+    fn members(&mut self, members: &[Member]) -> (u16, TypeIndex) {
+        let fields = members
+            .iter()
+            .map(|member| {
+                let field = FieldRecord::Member {
+                    attributes: FieldAttributes::new().with_access(MemberAccess::Public),
+                    field_type: self.reference(&member.ty, 0),
+                    offset: member.offset as u64,
+                    name: StrBuf::new(&*member.name),
+                };
+                (field, member.name.len())
+            })
+            .collect();
+        (field_count(members.len()), self.field_list(fields))
+    }
+
+    /// A field list of `fields` and the length of their names, split into records chained by
+    /// LF_INDEX where it outgrows one
+    fn field_list(&mut self, fields: Vec<(FieldRecord, usize)>) -> TypeIndex {
+        let mut chunks = vec![Vec::new()];
+        let mut bytes = 0;
+        for (field, name_len) in fields {
+            let size = MAX_FIELD_FIXED_BYTES + name_len;
+            // Room is left for the LF_INDEX that continues the list
+            if bytes > 0 && bytes + size > MAX_FIELD_LIST_BYTES - MAX_FIELD_FIXED_BYTES {
+                chunks.push(Vec::new());
+                bytes = 0;
+            }
+            chunks.last_mut().unwrap().push(field);
+            bytes += size;
+        }
+
+        // Each record points at the next, so they are added from the last one back
+        let mut continuation = None;
+        for mut chunk in chunks.into_iter().rev() {
+            chunk.extend(continuation.map(|continuation| FieldRecord::Index { continuation }));
+            continuation = Some(
+                self.builder
+                    .tpi()
+                    .add("fields", TypeRecord::FieldList { fields: chunk }),
+            );
+        }
+        continuation.unwrap_or(T_NOTYPE)
+    }
+
+    /// Index of the type `reference` describes, having followed `depth` aliases to it
+    fn reference(&mut self, reference: &TypeReference, depth: usize) -> TypeIndex {
+        match reference {
+            TypeReference::Value(value) => self.value(*value),
+            TypeReference::Named(name) => {
+                let kind = self.definitions.get(&**name).copied().map(|d| &d.kind);
+                match kind {
+                    Some(TypeDefinitionKind::Alias(target)) => match depth < MAX_ALIAS_DEPTH {
+                        true => self.reference(target, depth + 1),
+                        false => T_NOTYPE,
+                    },
+                    kind => self.declaration(name, kind),
+                }
+            }
+            TypeReference::Pointer { target, size } => {
+                self.cached(TypeKey::Reference(reference.clone()), |this| {
+                    let referent_type = this.reference(target, depth);
+                    let kind = match size {
+                        8 => PointerKind::Near64,
+                        _ => PointerKind::Near32,
+                    };
+                    this.builder.tpi().add(
+                        "pointer",
+                        TypeRecord::Pointer {
+                            referent_type,
+                            attributes: PointerAttributes::new()
+                                .with_kind(kind)
+                                .with_size(*size as u8),
+                        },
+                    )
+                })
+            }
+            TypeReference::Array { element, count } => {
+                self.cached(TypeKey::Reference(reference.clone()), |this| {
+                    let element_type = this.reference(element, depth);
+                    let size = this.size_of(element, depth).saturating_mul(*count as u64);
+                    let index_type = this.index_type();
+                    this.builder.tpi().add(
+                        "array",
+                        TypeRecord::Array {
+                            element_type,
+                            index_type,
+                            size,
+                            name: StrBuf::new(""),
+                        },
+                    )
+                })
+            }
+        }
+    }
+
+    /// A primitive for `value`, or an array of bytes of its size where CodeView has none
+    fn value(&mut self, value: ValueType) -> TypeIndex {
+        if value.size == 0 {
+            return T_VOID;
+        }
+        if let Some(index) = primitive_type(self.arch, value) {
+            return index;
+        }
+        self.cached(TypeKey::Reference(TypeReference::Value(value)), |this| {
+            let index_type = this.index_type();
+            this.builder.tpi().add(
+                "bytes",
+                TypeRecord::Array {
+                    element_type: T_UCHAR,
+                    index_type,
+                    size: value.size as u64,
+                    name: StrBuf::new(""),
+                },
+            )
+        })
+    }
+
+    /// Forward declaration of the structure, union or enumeration called `name`, which
+    /// debuggers resolve to the complete record by name, so definitions can refer to each
+    /// other whatever order they are emitted in
+    ///
+    /// A name with no definition is declared as a structure and stays opaque.
+    fn declaration(&mut self, name: &str, kind: Option<&'a TypeDefinitionKind>) -> TypeIndex {
+        self.cached(
+            TypeKey::Reference(TypeReference::Named(name.into())),
+            |this| {
+                let properties = StructProperties::new().with_forward_ref(true);
+                let name = StrBuf::new(name);
+                let record = match kind {
+                    Some(TypeDefinitionKind::Union { .. }) => TypeRecord::Union {
+                        count: 0,
+                        properties,
+                        field_list: T_NOTYPE,
+                        size: 0,
+                        name,
+                        unique_name: None,
+                    },
+                    Some(TypeDefinitionKind::Enumeration { size, .. }) => TypeRecord::Enum {
+                        count: 0,
+                        properties,
+                        underlying_type: this.underlying_type(*size),
+                        field_list: T_NOTYPE,
+                        name,
+                        unique_name: None,
+                    },
+                    _ => TypeRecord::Structure {
+                        count: 0,
+                        properties,
+                        field_list: T_NOTYPE,
+                        derived_from: None,
+                        vtable_shape: None,
+                        size: 0,
+                        name,
+                        unique_name: None,
+                    },
+                };
+                this.builder.tpi().add("declaration", record)
+            },
+        )
+    }
+
+    /// Size in bytes of the type `reference` describes, 0 for names without a definition
+    fn size_of(&self, reference: &TypeReference, depth: usize) -> u64 {
+        match reference {
+            TypeReference::Value(value) => value.size as u64,
+            TypeReference::Pointer { size, .. } => *size as u64,
+            TypeReference::Array { element, count } => {
+                self.size_of(element, depth).saturating_mul(*count as u64)
+            }
+            TypeReference::Named(name) => match self.definitions.get(&**name).map(|d| &d.kind) {
+                Some(
+                    TypeDefinitionKind::Structure { size, .. }
+                    | TypeDefinitionKind::Union { size, .. }
+                    | TypeDefinitionKind::Enumeration { size, .. },
+                ) => *size as u64,
+                Some(TypeDefinitionKind::Alias(target)) if depth < MAX_ALIAS_DEPTH => {
+                    self.size_of(target, depth + 1)
+                }
+                _ => 0,
+            },
+        }
+    }
+
+    /// Integer an enumeration of `size` bytes is stored as
+    fn underlying_type(&self, size: u32) -> TypeIndex {
+        let value = ValueType {
+            size,
+            kind: ValueKind::Integer,
+        };
+        primitive_type(self.arch, value).unwrap_or(T_ULONG)
+    }
+
+    /// Type arrays are indexed with, as wide as a pointer
+    fn index_type(&self) -> TypeIndex {
+        match self.arch {
+            Arch::X86 => T_ULONG,
+            Arch::X64 | Arch::Arm64 => T_UQUAD,
+        }
+    }
+
+    /// The record cached under `key`, emitting it with `emit` on first use
+    fn cached(&mut self, key: TypeKey, emit: impl FnOnce(&mut Self) -> TypeIndex) -> TypeIndex {
+        if let Some(index) = self.types.get(&key) {
+            return index;
+        }
+        let index = emit(self);
+        self.types.insert(key, index);
+        index
+    }
+}
+
+/// Field count of a structure, union or enumeration record, which CodeView stores in 16 bits
+fn field_count(fields: usize) -> u16 {
+    u16::try_from(fields).unwrap_or(u16::MAX)
+}
+
+/// The "* Linker *" module link.exe writes, describing every section with S_SECTION and
+/// S_COFFGROUP records for tools that read the layout from symbols rather than the DBI stream
+fn build_linker_module(model: &Model, options: &Options, builder: &mut PdbBuilder) {
+    if model.sections.is_empty() {
+        return;
+    }
+
+    // The linker module owns no code, so it gets an empty contribution
+    let sec_contrib = SectionContrib {
+        i_sect: 1,
+        pad1: [0, 0],
+        offset: 0,
+        size: 0,
+        characteristics: 0,
+        i_mod: 0,
+        pad2: [0, 0],
+        data_crc: 0,
+        reloc_crc: 0,
+    };
+    let mut module = ModuleBuilder::new(LINKER_MODULE.to_string(), String::new(), sec_contrib);
+    if let Some(env_block) = module_env_block(options) {
+        module.add_symbol(env_block);
+    }
+
+    for (i, section) in model.sections.iter().enumerate() {
+        let section_idx = (i as u16) + 1;
+        module.add_symbol(SymbolRecord::Section(SectionSymbol {
+            section: section_idx,
+            alignment: SECTION_ALIGNMENT_LOG2,
+            reserved: 0,
+            rva: section.virtual_address,
+            length: section.size(),
+            characteristics: section.characteristics,
+            name: StrBuf::new(&*section.name),
+        }));
+        // Without the object files there is nothing finer than the section to group by
+        module.add_symbol(SymbolRecord::CoffGroup(CoffGroup {
+            size: section.size(),
+            characteristics: section.characteristics,
+            offset: DataRegionOffset::new(0, section_idx),
+            name: StrBuf::new(&*section.name),
+        }));
+    }
+
+    builder.dbi().add_module(module);
+}
+
+/// S_ENVBLOCK describing the pdbgen run, if [`Options::env_block`] asks for it
+fn module_env_block(options: &Options) -> Option<SymbolRecord> {
+    let fields = env_block_fields(options)?;
+    Some(SymbolRecord::EnvBlock {
+        reserved: 0,
+        fields: fields.into_iter().map(StrBuf::new).collect(),
+    })
+}
+
+/// Strings of the S_ENVBLOCK [`module_env_block`] emits, pairs of key and value the way
+/// compilers record their invocation
+fn env_block_fields(options: &Options) -> Option<Vec<String>> {
+    if !options.env_block {
+        return None;
+    }
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let exe = std::env::current_exe().unwrap_or_default();
+    let pairs = [
+        ("cwd", cwd.display().to_string()),
+        ("exe", exe.display().to_string()),
+        (
+            "tool",
+            format!("binja_pdbgen {}", env!("CARGO_PKG_VERSION")),
+        ),
+        ("cmd", serde_json::to_string(options).unwrap_or_default()),
+    ];
+    let mut fields: Vec<String> = pairs
+        .into_iter()
+        .flat_map(|(key, value)| [key.to_string(), value])
+        .collect();
+    // The block ends with an empty string
+    fields.push(String::new());
+    Some(fields)
+}
+
+/// 1-based index of the section containing `rva`, as used by PDB segment:offset addressing
+fn section_index_of(sections: &[Section], rva: u32) -> Option<u16> {
+    sections
+        .iter()
+        .position(|s| s.range().contains(&rva))
+        .map(|i| (i as u16) + 1)
+}
+
+fn build_functions(
+    model: &Model,
+    options: &Options,
+    builder: &mut PdbBuilder,
+    types: &mut TypeCache,
+    globals: &mut GlobalBudget,
+    report: &mut Report,
+    progress: &mut dyn Progress,
+) -> Result<()> {
+    let unwind_functions = match options.include_unwind_functions {
+        true => model.unwind_functions.as_slice(),
+        false => &[],
+    };
+    let mut functions: Vec<&Function> = model.functions.iter().chain(unwind_functions).collect();
+
+    // Still write the info stream and section headers so the PDB matches the image, but say why
+    // it holds no symbols
+    if functions.is_empty() {
+        report.warn(
+            "No functions were collected, the PDB only holds section headers. Wait for analysis \
+             to finish before generating it"
+                .to_string(),
+        );
+        return Ok(());
+    }
+    if model.sections.is_empty() {
+        report.warn(format!(
+            "The image has no sections, so none of its {} function(s) can be addressed and the \
+             PDB only identifies the image",
+            functions.len()
+        ));
+        return Ok(());
+    }
+
+    // Procedure types are anonymous in CodeView, so they are keyed by their signature
+    let key = TypeKey::Procedure {
+        return_type: None,
+        parameters: Vec::new(),
+    };
+    let void_fn_type = types.get_or_insert_with(key, || {
+        let tpi = builder.tpi();
+
+        let arg_list = tpi.add(
+            "args",
+            TypeRecord::ArgList {
+                count: 0,
+                arg_list: vec![],
+            },
+        );
+
+        tpi.add(
+            "void_func",
+            TypeRecord::Procedure {
+                return_type: None,
+                calling_conv: CallingConvention::NearC,
+                properties: FunctionProperties::new(),
+                arg_count: 0,
+                arg_list,
+            },
+        )
+    });
+
+    if options.deterministic {
+        functions.sort_by(|a, b| {
+            let key = |f: &Function| f.ranges.first().map(|r| r.start);
+            key(a).cmp(&key(b)).then_with(|| a.name.cmp(&b.name))
+        });
+    }
+
+    let mut modules: BTreeMap<(u16, String), Vec<(u16, &Function)>> = BTreeMap::new();
+
+    let mut empty_ranges = 0;
+    let mut discarded = 0;
+    let mut excluded = 0;
+
+    for &function in &functions {
+        let Some(start) = function.ranges.first().map(|r| r.start) else {
+            report.skip(
+                format!("function {}", function.name),
+                anyhow::anyhow!("function has no address ranges"),
+            );
+            continue;
+        };
+
+        // Zero-length ranges would become procedures with no code, see add_function
+        let empty = function.ranges.iter().filter(|r| r.start == r.end).count();
+        empty_ranges += empty;
+        if empty == function.ranges.len() {
+            report.skip(
+                format!("function {}", function.name),
+                anyhow::anyhow!("every address range of the function is empty"),
+            );
+            continue;
+        }
+
+        let Some(section_idx) = section_index_of(&model.sections, start) else {
+            // Segment:offset addressing has no way to express these, so make the loss visible
+            report.skip(
+                format!("function {}", function.name),
+                anyhow::anyhow!("RVA 0x{start:x} is not inside any section"),
+            );
+            continue;
+        };
+        let section = &model.sections[section_idx as usize - 1];
+        if options.skip_discardable_sections && section.is_discardable() {
+            discarded += 1;
+            continue;
+        }
+        if !options.includes_section(&section.name) {
+            excluded += 1;
+            continue;
+        }
+        modules
+            .entry(module_key(
+                options.module_layout,
+                model,
+                section_idx,
+                function,
+            ))
+            .or_default()
+            .push((section_idx, function));
+    }
+
+    if discarded > 0 {
+        info!("Left out {discarded} function(s) in discardable sections");
+    }
+    if excluded > 0 {
+        info!("Left out {excluded} function(s) in sections not selected");
+    }
+    if empty_ranges > 0 {
+        report.warn(format!(
+            "Dropped {empty_ranges} zero-length function range(s)"
+        ));
+    }
+
+    let total = modules.values().map(Vec::len).sum();
+    let mut done = 0;
+    let mut emitted = 0;
+    let mut budget = SymbolBudget::new(options);
+    let mut names = NameRules::new(options, &functions, report);
+    let env_block = module_env_block(options);
+    // Every module starts with the S_ENVBLOCK, ahead of its functions' records
+    let module_overhead = env_block_fields(options).map_or(0, |fields| {
+        MAX_RECORD_FIXED_BYTES + fields.iter().map(|f| f.len() as u64 + 1).sum::<u64>()
+    });
+    let mut context = ProcContext {
+        sections: &model.sections,
+        function_type: void_fn_type,
+        arch: Arch::from_machine(model.machine),
+        types,
+        skipped_parameters: 0,
+        skipped_call_sites: 0,
+        type_issues: Vec::new(),
+    };
+
+    let mut module_names = UniqueNames::default();
+    let mut object_paths = UniqueNames::default();
+    let binary = model
+        .image_name
+        .rsplit_once('.')
+        .map_or(&*model.image_name, |(stem, _)| stem);
+
+    // Each module's function list is dropped once its records are handed to the builder, which
+    // still holds every module's records until commit
+    for ((_, base_name), mut functions) in modules {
+        let chunk_size = match options.max_functions_per_module {
+            _ if options.module_layout == ModuleLayout::Single => functions.len(),
+            0 => functions.len(),
+            n => n,
+        };
+        // Even modules that are never split by count get continuation modules rather than a
+        // symbol stream past the format's limit
+        let bytes: u64 = functions
+            .iter()
+            .map(|(_, f)| estimated_symbol_bytes(f))
+            .sum();
+        let oversized = module_overhead + bytes > MAX_SYMBOL_STREAM_BYTES;
+        if oversized {
+            info!(
+                "Splitting {base_name} into continuation modules, its symbols take about {bytes} \
+                 bytes"
+            );
+        }
+        // Only an unsplit per-section module covers its whole section
+        let whole_section = options.module_layout == ModuleLayout::Section
+            && functions.len() <= chunk_size
+            && !oversized;
+        if !whole_section {
+            // Keep each chunk's contribution a compact, non-overlapping slice of the section
+            functions.sort_by_key(|(_, f)| f.ranges[0].start);
+        }
+        // Import modules are named like the linker's, after the library and its import library
+        let import_library = base_name.strip_prefix(IMPORT_MODULE_PREFIX);
+
+        for (chunk_idx, chunk) in module_chunks(&functions, chunk_size, module_overhead)
+            .into_iter()
+            .enumerate()
+        {
+            progress.check_cancelled()?;
+
+            let suffix = if chunk_idx == 0 {
+                String::new()
+            } else {
+                format!("_{}", chunk_idx + 1)
+            };
+            // A module has a single contribution, so one spanning sections claims the first
+            let contrib_idx = chunk[0].0;
+            let contrib_section = &model.sections[contrib_idx as usize - 1];
+            let (offset, size) = if whole_section {
+                (0, contrib_section.size())
+            } else {
+                contribution_extent(chunk, contrib_idx, contrib_section)
+            };
+
+            info!(
+                "Creating module {base_name}{suffix} with {} functions",
+                chunk.len()
+            );
+
+            let sec_contrib = SectionContrib {
+                i_sect: contrib_idx,
+                pad1: [0, 0],
+                offset,
+                size,
+                characteristics: contrib_section.characteristics,
+                i_mod: 0,
+                pad2: [0, 0],
+                data_crc: 0,
+                reloc_crc: 0,
+            };
+
+            let (module_name, obj_path) = match import_library {
+                Some(library) => (
+                    format!("{base_name}{suffix}"),
+                    format!(
+                        "/fake/path/{}{suffix}.lib",
+                        library.rsplit_once('.').map_or(library, |(stem, _)| stem)
+                    ),
+                ),
+                None => {
+                    let values = [
+                        ("name", base_name.clone()),
+                        ("section", contrib_section.name.clone()),
+                        (
+                            "component",
+                            chunk[0].1.component.as_deref().unwrap_or("").into(),
+                        ),
+                        ("binary", binary.to_string()),
+                        ("suffix", suffix.clone()),
+                    ];
+                    let mut module_name = fill_template(&options.module_name, &values);
+                    if !options.module_name.contains("{suffix}") {
+                        module_name.push_str(&suffix);
+                    }
+                    let values = values.map(|(key, value)| (key, value.replace(['/', '\\'], "_")));
+                    let mut obj_path = fill_template(&options.object_path, &values);
+                    if !options.object_path.contains("{suffix}") {
+                        obj_path = insert_suffix(&obj_path, &suffix);
+                    }
+                    (module_name, obj_path)
+                }
+            };
+            // Templates that leave out what tells modules apart would otherwise merge them in
+            // tools keying off either
+            let module_name = module_names.claim(module_name, |name, n| format!("{name}_{n}"));
+            let obj_path =
+                object_paths.claim(obj_path, |path, n| insert_suffix(path, &format!("_{n}")));
+            budget.start_module(&module_name);
+            let mut module = ModuleBuilder::new(module_name, obj_path, sec_contrib);
+            if let Some(env_block) = &env_block {
+                module.add_symbol(env_block.clone());
+            }
+
+            for &(_, function) in chunk {
+                done += 1;
+                progress.update("Emitting functions", done, total);
+                // An S_PROCREF per procedure record lands in the global symbol stream
+                let procref_bytes = function.ranges.len() as u64
+                    * (MAX_RECORD_FIXED_BYTES + function.name.len() as u64 + MAX_NAME_SUFFIX_BYTES);
+                if !globals.fits(procref_bytes) {
+                    globals.functions += 1;
+                    continue;
+                }
+                if !budget.admit(function.ranges.len(), report) {
+                    continue;
+                }
+                globals.claim(procref_bytes);
+                match add_function(builder, &mut module, function, &mut names, &mut context) {
+                    Ok(()) => emitted += 1,
+                    Err(err) => report.skip(format!("function {}", function.name), err),
+                }
+            }
+
+            builder.dbi().add_module(module);
+        }
+    }
+
+    budget.finish(report);
+    names.finish(report);
+    let renamed = module_names.renamed + object_paths.renamed;
+    if renamed > 0 {
+        report.warn(format!(
+            "Numbered {renamed} module name(s) or object path(s) the naming templates gave more \
+             than one module"
+        ));
+    }
+    if context.skipped_parameters > 0 {
+        report.warn(format!(
+            "Left out {} parameter(s) in registers or of types CodeView can't describe for \
+             machine 0x{:x}",
+            context.skipped_parameters, model.machine
+        ));
+    }
+    for issue in context.type_issues.drain(..) {
+        report.type_issue(issue);
+    }
+    if context.skipped_call_sites > 0 {
+        report.warn(format!(
+            "Left out {} call site(s) with signatures CodeView can't describe",
+            context.skipped_call_sites
+        ));
+    }
+    if emitted == 0 {
+        report.warn(format!(
+            "None of the {} function(s) could be emitted, the PDB only holds section headers",
+            functions.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Soft caps on the number of procedure records, see [`Options::max_total_symbols`]
+struct SymbolBudget {
+    per_module: usize,
+    total: usize,
+    truncate: bool,
+    module: String,
+    module_count: usize,
+    total_count: usize,
+    module_warned: bool,
+    total_warned: bool,
+    truncated: usize,
+}
+
+impl SymbolBudget {
+    fn new(options: &Options) -> Self {
+        Self {
+            per_module: options.max_symbols_per_module,
+            total: options.max_total_symbols,
+            truncate: options.truncate_at_symbol_limit,
+            module: String::new(),
+            module_count: 0,
+            total_count: 0,
+            module_warned: false,
+            total_warned: false,
+            truncated: 0,
+        }
+    }
+
+    fn start_module(&mut self, name: &str) {
+        self.module = name.to_string();
+        self.module_count = 0;
+        self.module_warned = false;
+    }
+
+    /// Whether a function with `symbols` procedure records may still be emitted
+    fn admit(&mut self, symbols: usize, report: &mut Report) -> bool {
+        let over_module = self.per_module != 0 && self.module_count + symbols > self.per_module;
+        let over_total = self.total != 0 && self.total_count + symbols > self.total;
+
+        if over_module && !self.module_warned {
+            self.module_warned = true;
+            report.warn(format!(
+                "Module {} exceeds the limit of {} symbols",
+                self.module, self.per_module
+            ));
+        }
+        if over_total && !self.total_warned {
+            self.total_warned = true;
+            report.warn(format!(
+                "PDB exceeds the limit of {} symbols, debuggers may be slow to load it",
+                self.total
+            ));
+        }
+
+        if self.truncate && (over_module || over_total) {
+            self.truncated += 1;
+            return false;
+        }
+        self.module_count += symbols;
+        self.total_count += symbols;
+        true
+    }
+
+    fn finish(&self, report: &mut Report) {
+        if self.truncated > 0 {
+            report.warn(format!(
+                "Dropped {} function(s) beyond the symbol limits",
+                self.truncated
+            ));
+        }
+    }
+}
+
+/// Room left in the symbol record stream, which the S_PROCREF of every procedure, the publics,
+/// the vtables' S_GDATA32 records and the canary S_ENVBLOCK all share
+struct GlobalBudget {
+    bytes: u64,
+    /// What was left out for lack of room
+    functions: usize,
+    publics: usize,
+    vtables: usize,
+    types: usize,
+}
+
+impl GlobalBudget {
+    fn new(reserved: u64) -> Self {
+        Self {
+            bytes: reserved,
+            functions: 0,
+            publics: 0,
+            vtables: 0,
+            types: 0,
+        }
+    }
+
+    fn fits(&self, bytes: u64) -> bool {
+        self.bytes + bytes <= MAX_SYMBOL_STREAM_BYTES
+    }
+
+    /// Take `bytes` of the stream if they fit
+    fn claim(&mut self, bytes: u64) -> bool {
+        let fits = self.fits(bytes);
+        if fits {
+            self.bytes += bytes;
+        }
+        fits
+    }
+
+    fn finish(&self, report: &mut Report) {
+        if self.functions + self.publics + self.vtables + self.types > 0 {
+            report.warn(format!(
+                "Dropped {} function(s), {} public symbol(s), {} vtable(s) and {} type \
+                 name(s) past the {MAX_SYMBOL_STREAM_BYTES} byte limit of the symbol record \
+                 stream",
+                self.functions, self.publics, self.vtables, self.types
+            ));
+        }
+    }
+}
+
+/// Module a function is placed in, ordered by section and then name
+fn module_key(
+    layout: ModuleLayout,
+    model: &Model,
+    section_idx: u16,
+    function: &Function,
+) -> (u16, String) {
+    let section = || {
+        (
+            section_idx,
+            model.sections[section_idx as usize - 1].name.clone(),
+        )
+    };
+    if let Some(library) = &function.library
+        && layout != ModuleLayout::Single
+    {
+        return (0, library.to_string());
+    }
+    match layout {
+        ModuleLayout::Single => (0, "image".to_string()),
+        ModuleLayout::Section => section(),
+        ModuleLayout::Component => match &function.component {
+            Some(component) => (0, component.to_string()),
+            None => section(),
+        },
+        ModuleLayout::Chunked => (0, "functions".to_string()),
+    }
+}
+
+/// Split a module's functions into chunks of at most `max_functions` whose symbols stay under
+/// [`MAX_SYMBOL_STREAM_BYTES`] together with the `overhead` bytes every module starts with
+fn module_chunks<'a, 'f>(
+    functions: &'a [(u16, &'f Function)],
+    max_functions: usize,
+    overhead: u64,
+) -> Vec<&'a [(u16, &'f Function)]> {
+    let limit = MAX_SYMBOL_STREAM_BYTES.saturating_sub(overhead);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut bytes = 0;
+    for (i, (_, function)) in functions.iter().enumerate() {
+        let size = estimated_symbol_bytes(function);
+        if i > start && (i - start == max_functions || bytes + size > limit) {
+            chunks.push(&functions[start..i]);
+            start = i;
+            bytes = 0;
+        }
+        bytes += size;
+    }
+    if start < functions.len() {
+        chunks.push(&functions[start..]);
+    }
+    chunks
+}
+
+/// Upper bound on the bytes [`add_function`] adds to a module's symbol stream for `function`
+fn estimated_symbol_bytes(function: &Function) -> u64 {
+    let name = function.name.len() as u64 + MAX_NAME_SUFFIX_BYTES;
+    let ranges = function.ranges.len();
+    // S_GPROC32 or S_THUNK32, S_FRAMEPROC and S_END for every range, S_LOCAL and a def-range
+    // for every parameter
+    let records = ranges * 3
+        + function.parameters.len() * 2
+        + function.call_sites.len()
+        + function.heap_alloc_sites.len()
+        + function.blocks.len();
+    let names = (ranges + function.blocks.len()) as u64 * name
+        + function
+            .parameters
+            .iter()
+            .map(|parameter| parameter.name.len() as u64)
+            .sum::<u64>();
+    records as u64 * MAX_RECORD_FIXED_BYTES + names
+}
+
+/// `path` with `suffix` added to its file name, ahead of the extension if it has one
+fn insert_suffix(path: &str, suffix: &str) -> String {
+    let file_name = path.rfind(['/', '\\']).map_or(0, |slash| slash + 1);
+    match path[file_name..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let (stem, extension) = path.split_at(file_name + dot);
+            format!("{stem}{suffix}{extension}")
+        }
+        _ => format!("{path}{suffix}"),
+    }
+}
+
+/// Hands out module names or object paths, numbering any that an earlier module already got
+#[derive(Default)]
+struct UniqueNames {
+    used: HashSet<String>,
+    renamed: usize,
+}
+
+impl UniqueNames {
+    fn claim(&mut self, name: String, numbered: impl Fn(&str, usize) -> String) -> String {
+        if !self.used.contains(&name) {
+            self.used.insert(name.clone());
+            return name;
+        }
+        self.renamed += 1;
+        let name = (2..)
+            .map(|n| numbered(&name, n))
+            .find(|candidate| !self.used.contains(candidate))
+            .unwrap();
+        self.used.insert(name.clone());
+        name
+    }
+}
+
+/// Replace the `{key}` placeholders of a module naming template, leaving unknown ones as is
+fn fill_template(template: &str, values: &[(&str, String)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        filled.push_str(&rest[..open]);
+        rest = &rest[open + 1..];
+        let value = values.iter().find(|(key, _)| {
+            rest.strip_prefix(key)
+                .is_some_and(|after| after.starts_with('}'))
+        });
+        match value {
+            Some((key, value)) => {
+                filled.push_str(value);
+                rest = &rest[key.len() + 1..];
+            }
+            None => filled.push('{'),
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Offset and size of the part of `section` covered by the functions placed in it
+fn contribution_extent(
+    functions: &[(u16, &Function)],
+    section_idx: u16,
+    section: &Section,
+) -> (u32, u32) {
+    let ranges = functions
+        .iter()
+        .filter(|(idx, _)| *idx == section_idx)
+        .flat_map(|(_, f)| &f.ranges)
+        .filter(|r| section.range().contains(&r.start));
+    let start = ranges
+        .clone()
+        .map(|r| r.start)
+        .min()
+        .unwrap_or(section.virtual_address);
+    let end = ranges.map(|r| r.end).max().unwrap_or(start);
+    (start - section.virtual_address, end.saturating_sub(start))
+}
+
+/// What procedures are emitted with, and a tally of the details that had to be left out
+struct ProcContext<'a> {
+    sections: &'a [Section],
+    /// Type given to every procedure
+    function_type: TypeIndex,
+    arch: Option<Arch>,
+    types: &'a mut TypeCache,
+    skipped_parameters: usize,
+    skipped_call_sites: usize,
+    /// Types with no CodeView equivalent, passed on to the report
+    type_issues: Vec<TypeIssue>,
+}
+
+fn add_function(
+    builder: &mut PdbBuilder,
+    module: &mut ModuleBuilder,
+    function: &Function,
+    names: &mut NameRules,
+    context: &mut ProcContext,
+) -> Result<()> {
+    let sections = context.sections;
+    // Resolve every part up front so a bad range doesn't leave a half-emitted function behind
+    let mut pieces = Vec::with_capacity(function.ranges.len());
+    for range in &function.ranges {
+        if range.start == range.end {
+            continue;
+        }
+        ensure!(
+            range.end >= range.start,
+            "range at 0x{:x} ends before it starts",
+            range.start
+        );
+        // A range running past the end of its section is split so every piece is addressed
+        // from the section it actually lies in
+        let mut start = range.start;
+        loop {
+            let section_idx = section_index_of(sections, start).with_context(|| {
+                format!(
+                    "range 0x{:x}..0x{:x} leaves the section table at 0x{start:x}",
+                    range.start, range.end
+                )
+            })?;
+            let section = &sections[section_idx as usize - 1];
+            let end = range.end.min(section.range().end);
+            pieces.push((section_idx, start - section.virtual_address, end - start));
+            if end == range.end {
+                break;
+            }
+            start = end;
+        }
+    }
+
+    let name = names.base_name(function);
+    let entry = pieces
+        .first()
+        .map(|&(section_idx, func_offset, _)| DataRegionOffset::new(func_offset, section_idx));
+    let parts = pieces
+        .into_iter()
+        .enumerate()
+        .map(|(i, (section_idx, func_offset, func_size))| {
+            let func_name: Cow<str> = if i == 0 {
+                Cow::Borrowed(&name)
+            } else {
+                Cow::Owned(format!("{name}_part{}", i + 1))
+            };
+            (names.apply(func_name), section_idx, func_offset, func_size)
+        })
+        .collect::<Vec<_>>();
+
+    for (i, (func_name, section_idx, func_offset, func_size)) in parts.into_iter().enumerate() {
+        let code_offset = DataRegionOffset::new(func_offset, section_idx);
+
+        // add to module, S_THUNK32 only has room for a 16 bit length
+        let proc_idx = module.symbols.len();
+        let thunk_length = match function.kind {
+            FunctionKind::Thunk => u16::try_from(func_size).ok(),
+            FunctionKind::Procedure => None,
+        };
+        module.add_symbol(match thunk_length {
+            Some(length) => SymbolRecord::Thunk(Thunk {
+                parent: None,
+                end: 0.into(),
+                next: None,
+                offset: code_offset,
+                length,
+                ordinal: ThunkOrdinal::NoType,
+                name: StrBuf::new(&*func_name),
+                variant: Vec::new(),
+            }),
+            None => SymbolRecord::GlobalProc(Procedure {
+                parent: None,
+                end: 0.into(),
+                next: None,
+                code_size: func_size,
+                dbg_start_offset: 0,
+                dbg_end_offset: 0,
+                function_type: context.function_type,
+                code_offset,
+                properties: ProcedureProperties::new(),
+                name: StrBuf::new(&*func_name),
+            }),
+        });
+        if thunk_length.is_none() {
+            if let Some(frame) = function.frame {
+                module.add_symbol(frame_proc(frame));
+            }
+            if i == 0 && !function.parameters.is_empty() {
+                context.skipped_parameters += match context.arch {
+                    Some(arch) => add_parameters(
+                        module,
+                        function,
+                        arch,
+                        code_offset,
+                        &mut context.type_issues,
+                    ),
+                    None => function.parameters.len(),
+                };
+            }
+            let piece = (section_idx, func_offset..func_offset + func_size);
+            add_call_sites(builder, module, function, &piece, context);
+            add_heap_alloc_sites(module, function, &piece, context);
+            add_block_labels(module, function, &name, &piece, names, context);
+        }
+        let end_idx = module.add_symbol(SymbolRecord::ProcEnd);
+        match &mut module.symbols[proc_idx] {
+            SymbolRecord::GlobalProc(proc) => proc.end = end_idx,
+            SymbolRecord::Thunk(thunk) => thunk.end = end_idx,
+            _ => unreachable!(),
+        }
+
+        // add to publics table
+        builder.dbi().symbols().add(Public {
+            properties: PublicProperties::new().with_is_function(true),
+            offset: code_offset,
+            name: StrBuf::new(func_name),
+        });
+    }
+
+    if let Some(entry) = entry {
+        for alias in &function.aliases {
+            builder.dbi().symbols().add(Public {
+                properties: PublicProperties::new().with_is_function(true),
+                offset: entry,
+                name: StrBuf::new(names.apply(Cow::Borrowed(&**alias))),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// An S_LOCAL for each parameter followed by where it lives over the function's first
+/// instruction, returning how many parameters had to be left out
+///
+/// Without liveness information nothing is claimed beyond the entry instruction, after which
+/// the registers may already be reused.
+fn add_parameters(
+    module: &mut ModuleBuilder,
+    function: &Function,
+    arch: Arch,
+    entry: DataRegionOffset,
+    type_issues: &mut Vec<TypeIssue>,
+) -> usize {
+    let length = match u16::try_from(function.entry_length) {
+        Ok(0) | Err(_) => return function.parameters.len(),
+        Ok(length) => length,
+    };
+    let range = || LocalVariableAddrRange {
+        offset: entry,
+        length,
+    };
+
+    let mut skipped = 0;
+    for parameter in &function.parameters {
+        let location = match &parameter.location {
+            Location::Register(name) => cv_register(arch, name).map(|register| {
+                SymbolRecord::DefRangeRegister(DefRangeRegister {
+                    register,
+                    may_have_no_name: 0,
+                    range: range(),
+                    gaps: Vec::new(),
+                })
+            }),
+            Location::Stack(offset) => i32::try_from(*offset).ok().map(|offset| {
+                SymbolRecord::DefRangeRegisterRel(DefRangeRegisterRel {
+                    base_register: arch.stack_pointer(),
+                    flags: 0,
+                    base_pointer_offset: offset,
+                    range: range(),
+                    gaps: Vec::new(),
+                })
+            }),
+        };
+        let value = ValueType {
+            size: parameter.size,
+            kind: parameter.kind,
+        };
+        let type_index = primitive_type(arch, value);
+        if type_index.is_none() {
+            type_issues.push(TypeIssue {
+                symbol: function.name.to_string(),
+                rva: function.ranges[0].start,
+                item: format!("parameter {}", parameter.name),
+                type_name: describe(value),
+                reason: "no CodeView primitive, left out".to_string(),
+            });
+        }
+        let (Some(type_index), Some(location)) = (type_index, location) else {
+            skipped += 1;
+            continue;
+        };
+        module.add_symbol(SymbolRecord::Local(Local {
+            type_index,
+            flags: LocalVariableFlags::new().with_is_param(true),
+            name: StrBuf::new(&*parameter.name),
+        }));
+        module.add_symbol(location);
+    }
+    skipped
+}
+
+/// Encoded frame registers of S_FRAMEPROC, whose meaning depends on the architecture: esp based
+/// (VFRAME), rsp or sp as 1 and ebp, rbp or x29 as 2
+const FRAME_STACK_POINTER: u8 = 1;
+const FRAME_FRAME_POINTER: u8 = 2;
+
+/// S_FRAMEPROC describing which register locals and parameters are addressed from
+fn frame_proc(frame: Frame) -> SymbolRecord {
+    let base_pointer = match frame.frame_pointer {
+        true => FRAME_FRAME_POINTER,
+        false => FRAME_STACK_POINTER,
+    };
+    SymbolRecord::FrameProc(FrameProc {
+        frame_size: frame.size,
+        padding_size: 0,
+        padding_offset: 0,
+        callee_saved_size: 0,
+        exception_handler_offset: 0,
+        exception_handler_section: 0,
+        flags: FrameProcFlags::new()
+            .with_security_checks(frame.security_cookie)
+            .with_encoded_local_base_pointer(base_pointer)
+            .with_encoded_param_base_pointer(base_pointer),
+    })
+}
+
+/// An S_CALLSITEINFO for each typed indirect call inside one piece of a procedure
+fn add_call_sites(
+    builder: &mut PdbBuilder,
+    module: &mut ModuleBuilder,
+    function: &Function,
+    piece: &(u16, Range<u32>),
+    context: &mut ProcContext,
+) {
+    for site in &function.call_sites {
+        let Some(offset) = offset_in_piece(context.sections, piece, site.rva) else {
+            continue;
+        };
+        let Some(type_index) = call_site_type(builder, context, site) else {
+            context.skipped_call_sites += 1;
+            let unencodable = site
+                .return_type
+                .iter()
+                .chain(&site.parameters)
+                .find(|&&value| {
+                    context
+                        .arch
+                        .and_then(|arch| primitive_type(arch, value))
+                        .is_none()
+                });
+            let (type_name, reason) = match unencodable {
+                Some(&value) => (describe(value), "no CodeView primitive"),
+                None if u16::try_from(site.parameters.len()).is_err() => (
+                    format!("{} parameters", site.parameters.len()),
+                    "more parameters than a procedure type can count",
+                ),
+                None => ("signature".to_string(), "no CodeView primitive"),
+            };
+            context.type_issues.push(TypeIssue {
+                symbol: function.name.to_string(),
+                rva: site.rva,
+                item: "call".to_string(),
+                type_name,
+                reason: format!("{reason}, call site left out"),
+            });
+            continue;
+        };
+        module.add_symbol(SymbolRecord::CallSiteInfo(CallSiteInfo {
+            offset: DataRegionOffset::new(offset, piece.0),
+            padding: 0,
+            type_index,
+        }));
+    }
+}
+
+/// An S_HEAPALLOCSITE for each allocator call inside one piece of a procedure, typed with what
+/// the result points at when that is a primitive and left untyped otherwise
+fn add_heap_alloc_sites(
+    module: &mut ModuleBuilder,
+    function: &Function,
+    piece: &(u16, Range<u32>),
+    context: &mut ProcContext,
+) {
+    for site in &function.heap_alloc_sites {
+        let Some(offset) = offset_in_piece(context.sections, piece, site.rva) else {
+            continue;
+        };
+        let type_index = match (site.allocated, context.arch) {
+            (Some(value), Some(arch)) => primitive_type(arch, value).unwrap_or_else(|| {
+                context.type_issues.push(TypeIssue {
+                    symbol: function.name.to_string(),
+                    rva: site.rva,
+                    item: "heap allocation".to_string(),
+                    type_name: describe(value),
+                    reason: "no CodeView primitive, left untyped".to_string(),
+                });
+                T_NOTYPE
+            }),
+            _ => T_NOTYPE,
+        };
+        module.add_symbol(SymbolRecord::HeapAllocationSite(HeapAllocationSite {
+            offset: DataRegionOffset::new(offset, piece.0),
+            instruction_length: site.length,
+            type_index,
+        }));
+    }
+}
+
+/// An S_LABEL32 at each basic block inside one piece of a procedure, named after the function
+/// and the block's RVA
+fn add_block_labels(
+    module: &mut ModuleBuilder,
+    function: &Function,
+    function_name: &str,
+    piece: &(u16, Range<u32>),
+    names: &mut NameRules,
+    context: &ProcContext,
+) {
+    for &block in &function.blocks {
+        let Some(offset) = offset_in_piece(context.sections, piece, block) else {
+            continue;
+        };
+        let name = names.apply(Cow::Owned(format!("{function_name}$bb_0x{block:x}")));
+        module.add_symbol(SymbolRecord::Label(Label {
+            offset: DataRegionOffset::new(offset, piece.0),
+            flags: ProcedureProperties::new(),
+            name: StrBuf::new(name),
+        }));
+    }
+}
+
+/// Offset of `rva` within its section if it lies in the piece of a procedure given as its
+/// section index and the range of offsets it covers
+fn offset_in_piece(
+    sections: &[Section],
+    (section_idx, offsets): &(u16, Range<u32>),
+    rva: u32,
+) -> Option<u32> {
+    let section = &sections[*section_idx as usize - 1];
+    let offset = rva.checked_sub(section.virtual_address)?;
+    offsets.contains(&offset).then_some(offset)
+}
+
+/// Procedure type of a call site's target, built from primitive types since that is all the
+/// frontend reports. `None` if one has no primitive or there are more parameters than a
+/// procedure type can count.
+fn call_site_type(
+    builder: &mut PdbBuilder,
+    context: &mut ProcContext,
+    site: &CallSite,
+) -> Option<TypeIndex> {
+    let arch = context.arch?;
+    let return_type = match site.return_type {
+        Some(value) => Some(primitive_type(arch, value)?),
+        None => None,
+    };
+    let parameters = site
+        .parameters
+        .iter()
+        .map(|&value| primitive_type(arch, value))
+        .collect::<Option<Vec<_>>>()?;
+    let arg_count = u16::try_from(parameters.len()).ok()?;
+
+    let key = TypeKey::Procedure {
+        return_type: site.return_type,
+        parameters: site.parameters.clone(),
+    };
+    Some(context.types.get_or_insert_with(key, || {
+        let tpi = builder.tpi();
+        let arg_list = tpi.add(
+            "args",
+            TypeRecord::ArgList {
+                count: arg_count.into(),
+                arg_list: parameters,
+            },
+        );
+        tpi.add(
+            "call_site",
+            TypeRecord::Procedure {
+                return_type,
+                calling_conv: CallingConvention::NearC,
+                properties: FunctionProperties::new(),
+                arg_count,
+                arg_list,
+            },
+        )
+    }))
+}
+
+/// A value's size and kind for the report, such as `12 byte integer`
+fn describe(value: ValueType) -> String {
+    let kind = match value.kind {
+        ValueKind::Integer => "integer",
+        ValueKind::Float => "float",
+        ValueKind::Pointer => "pointer",
+    };
+    format!("{} byte {kind}", value.size)
+}
+
+/// Primitive type showing a value, as the frontend only reports its size and kind
+fn primitive_type(arch: Arch, value: ValueType) -> Option<TypeIndex> {
+    match (value.kind, value.size) {
+        (ValueKind::Pointer, _) => Some(match arch {
+            Arch::X86 => T_32PVOID,
+            Arch::X64 | Arch::Arm64 => T_64PVOID,
+        }),
+        (ValueKind::Float, 4) => Some(T_REAL32),
+        (ValueKind::Float, 8) => Some(T_REAL64),
+        (ValueKind::Float, 10) => Some(T_REAL80),
+        (_, 1) => Some(T_UCHAR),
+        (_, 2) => Some(T_USHORT),
+        (_, 4) => Some(T_ULONG),
+        (_, 8) => Some(T_UQUAD),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(start: u32) -> Function {
+        Function {
+            name: "f".into(),
+            ranges: vec![start..start + 0x10],
+            ..Default::default()
+        }
+    }
+
+    fn chunk_lens(functions: &[Function], max_functions: usize, overhead: u64) -> Vec<usize> {
+        let placed: Vec<_> = functions.iter().map(|f| (1, f)).collect();
+        module_chunks(&placed, max_functions, overhead)
+            .iter()
+            .map(|chunk| chunk.len())
+            .collect()
+    }
+
+    #[test]
+    fn splits_modules_by_function_count() {
+        let functions: Vec<_> = (0..5).map(|i| function(0x1000 + i * 0x10)).collect();
+        assert_eq!(chunk_lens(&functions, 2, 0), [2, 2, 1]);
+        assert_eq!(chunk_lens(&functions, usize::MAX, 0), [5]);
+        assert!(chunk_lens(&[], 2, 0).is_empty());
+    }
+
+    #[test]
+    fn splits_modules_by_stream_size() {
+        let functions: Vec<_> = (0..5).map(|i| function(0x1000 + i * 0x10)).collect();
+        let two = 2 * estimated_symbol_bytes(&functions[0]);
+        assert_eq!(
+            chunk_lens(&functions, usize::MAX, MAX_SYMBOL_STREAM_BYTES - two),
+            [2, 2, 1]
+        );
+        // A function too large for any module still gets one of its own
+        assert_eq!(
+            chunk_lens(&functions, usize::MAX, MAX_SYMBOL_STREAM_BYTES),
+            [1, 1, 1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn inserts_suffixes_ahead_of_the_extension() {
+        assert_eq!(insert_suffix("obj/foo.obj", "_2"), "obj/foo_2.obj");
+        assert_eq!(insert_suffix("a.b.c", "_2"), "a.b_2.c");
+        assert_eq!(insert_suffix(r"C:\dir.v2\foo", "_2"), r"C:\dir.v2\foo_2");
+        assert_eq!(insert_suffix("out/.hidden", "_2"), "out/.hidden_2");
+    }
+
+    #[test]
+    fn fills_known_placeholders() {
+        let values = [("name", "text".to_string()), ("suffix", "_2".to_string())];
+        assert_eq!(
+            fill_template("{name}_module{suffix}", &values),
+            "text_module_2"
+        );
+        assert_eq!(
+            fill_template("{names}/{other}/{name", &values),
+            "{names}/{other}/{name"
+        );
+        assert_eq!(fill_template("{{name}}", &values), "{text}");
+    }
+
+    #[test]
+    fn numbers_names_already_taken() {
+        let mut names = UniqueNames::default();
+        let numbered = |name: &str, n: usize| format!("{name}_{n}");
+        assert_eq!(names.claim("a".into(), numbered), "a");
+        assert_eq!(names.claim("a".into(), numbered), "a_2");
+        assert_eq!(names.claim("a_2".into(), numbered), "a_2_2");
+        assert_eq!(names.claim("a".into(), numbered), "a_3");
+        assert_eq!(names.renamed, 3);
+    }
+}
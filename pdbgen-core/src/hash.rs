@@ -0,0 +1,56 @@
+use serde::Serialize;
+use std::hash::Hasher;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x100_0000_01b3;
+
+/// 64 bit FNV-1a, stable across builds and platforms unlike `DefaultHasher`, for hashes that
+/// outlive the process: cache keys, shortened names and GUIDs
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv1a::default();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// [`fnv1a`] of `value`'s JSON form, which unlike its `Hash` covers every field without each
+/// one having to be listed
+pub(crate) fn hash_json(value: &impl Serialize) -> u64 {
+    let mut hasher = Fnv1a::default();
+    // Writing into a hasher can't fail, and every type hashed this way serializes
+    let _ = serde_json::to_writer(HashWriter(&mut hasher), value);
+    hasher.finish()
+}
+
+/// [`fnv1a`] as a `Hasher`, so derived `Hash` impls can feed it
+pub(crate) struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+struct HashWriter<'a>(&'a mut Fnv1a);
+
+impl std::io::Write for HashWriter<'_> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.write(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,409 @@
+//! Reading and patching the debug directory of an image on disk
+//!
+//! Debuggers only load a PDB whose GUID and age match the RSDS record the image's debug
+//! directory points at, so making a mismatched pair match again can mean editing that record,
+//! and tools that walk the directory get confused by entries that no longer describe the image.
+
+use anyhow::{Context as _, Result, ensure};
+use std::fs;
+use std::path::Path;
+
+/// Offset of e_lfanew within the DOS header
+const DOS_NEW_HEADER: usize = 0x3c;
+const PE_SIGNATURE: &[u8] = b"PE\0\0";
+/// Offsets within the "PE\0\0" signature followed by IMAGE_FILE_HEADER
+const COFF_NUMBER_OF_SECTIONS: usize = 0x6;
+const COFF_TIME_DATE_STAMP: usize = 0x8;
+const COFF_SIZE_OF_OPTIONAL_HEADER: usize = 0x14;
+const COFF_HEADER_SIZE: usize = 24;
+const OPTIONAL_MAGIC_PE32_PLUS: u16 = 0x20b;
+/// Offset of CheckSum within the optional header, the same for PE32 and PE32+
+const OPTIONAL_CHECKSUM: usize = 64;
+const OPTIONAL_DATA_DIRECTORIES_32: usize = 96;
+const OPTIONAL_DATA_DIRECTORIES_64: usize = 112;
+const IMAGE_DIRECTORY_ENTRY_DEBUG: usize = 6;
+/// Offsets within an IMAGE_SECTION_HEADER
+const SECTION_VIRTUAL_SIZE: usize = 8;
+const SECTION_VIRTUAL_ADDRESS: usize = 12;
+const SECTION_SIZE_OF_RAW_DATA: usize = 16;
+const SECTION_POINTER_TO_RAW_DATA: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+/// Offsets within an IMAGE_DEBUG_DIRECTORY
+const DEBUG_TIME_DATE_STAMP: usize = 4;
+const DEBUG_TYPE: usize = 12;
+const DEBUG_SIZE_OF_DATA: usize = 16;
+const DEBUG_POINTER_TO_RAW_DATA: usize = 24;
+const DEBUG_DIRECTORY_SIZE: usize = 28;
+const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+const RSDS_SIGNATURE: &[u8] = b"RSDS";
+/// Signature, GUID and age preceding the PDB path in an RSDS record
+const RSDS_HEADER_SIZE: usize = 24;
+/// Longest PDB path looked for when measuring an RSDS record
+const MAX_PDB_PATH: usize = 4096;
+
+/// The PDB an image asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeView {
+    pub guid: [u8; 16],
+    pub age: u32,
+    /// File offset of the age within the RSDS record
+    age_offset: usize,
+    /// File offset of the optional header's CheckSum
+    checksum_offset: usize,
+}
+
+/// The RSDS record of the image at `path`, `None` if it has no CodeView debug directory entry
+pub fn read_codeview(path: &Path) -> Result<Option<CodeView>> {
+    let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    codeview(&data)
+}
+
+/// Change the age the image at `path` asks for, updating the header checksum if it has one
+///
+/// An Authenticode signature does not survive this, the image has to be signed again.
+pub fn patch_codeview_age(path: &Path, age: u32) -> Result<()> {
+    let mut data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let codeview = codeview(&data)?.context("image has no CodeView debug directory entry")?;
+    data[codeview.age_offset..codeview.age_offset + 4].copy_from_slice(&age.to_le_bytes());
+    write_image(path, data, codeview.checksum_offset)
+}
+
+/// Bring the debug directory entries of the image at `path` back in line with the image after
+/// it was patched: every entry gets the COFF header's TimeDateStamp and RSDS entries the size of
+/// their record. Returns how many entries changed.
+pub fn sync_debug_directory(path: &Path) -> Result<usize> {
+    let mut data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let Some(directory) = debug_directory(&data)? else {
+        return Ok(0);
+    };
+    let timestamp = u32_at(&data, directory.coff + COFF_TIME_DATE_STAMP)?;
+
+    let mut changed = 0;
+    for entry in directory.entries() {
+        let mut fields = vec![(DEBUG_TIME_DATE_STAMP, timestamp)];
+        if let Some(size) = rsds_size(&data, entry)? {
+            fields.push((DEBUG_SIZE_OF_DATA, size));
+        }
+        let mut entry_changed = false;
+        for (field, value) in fields {
+            if u32_at(&data, entry + field)? != value {
+                data[entry + field..entry + field + 4].copy_from_slice(&value.to_le_bytes());
+                entry_changed = true;
+            }
+        }
+        changed += entry_changed as usize;
+    }
+
+    if changed > 0 {
+        write_image(path, data, directory.checksum_offset)?;
+    }
+    Ok(changed)
+}
+
+/// Remove the CodeView entries from the debug directory of the image at `path`, so nothing
+/// goes looking for a PDB it names. Returns how many were removed.
+///
+/// The records themselves stay in the file, only the directory stops pointing at them.
+pub fn strip_codeview(path: &Path) -> Result<usize> {
+    let mut data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let Some(directory) = debug_directory(&data)? else {
+        return Ok(0);
+    };
+
+    let mut kept = Vec::new();
+    for entry in directory.entries() {
+        if u32_at(&data, entry + DEBUG_TYPE)? != IMAGE_DEBUG_TYPE_CODEVIEW {
+            kept.extend_from_slice(&data[entry..entry + DEBUG_DIRECTORY_SIZE]);
+        }
+    }
+    let removed = directory.count - kept.len() / DEBUG_DIRECTORY_SIZE;
+    if removed == 0 {
+        return Ok(0);
+    }
+
+    // Compact the remaining entries and zero the slots freed at the end
+    let table = directory.offset..directory.offset + directory.count * DEBUG_DIRECTORY_SIZE;
+    data[table.clone()].fill(0);
+    data[table.start..table.start + kept.len()].copy_from_slice(&kept);
+    let size = match kept.len() {
+        0 => {
+            // An empty directory is written as no directory at all
+            data[directory.slot..directory.slot + 4].fill(0);
+            0
+        }
+        len => len as u32,
+    };
+    data[directory.slot + 4..directory.slot + 8].copy_from_slice(&size.to_le_bytes());
+
+    write_image(path, data, directory.checksum_offset)?;
+    Ok(removed)
+}
+
+/// The image's debug directory
+struct DebugDirectory {
+    /// File offset of the "PE\0\0" signature
+    coff: usize,
+    /// File offset of the optional header's CheckSum
+    checksum_offset: usize,
+    /// File offset of the debug entry among the optional header's data directories
+    slot: usize,
+    /// File offset of the first IMAGE_DEBUG_DIRECTORY
+    offset: usize,
+    count: usize,
+}
+
+impl DebugDirectory {
+    /// File offsets of the IMAGE_DEBUG_DIRECTORY entries
+    fn entries(&self) -> impl Iterator<Item = usize> + use<> {
+        let offset = self.offset;
+        (0..self.count).map(move |i| offset + i * DEBUG_DIRECTORY_SIZE)
+    }
+}
+
+fn debug_directory(data: &[u8]) -> Result<Option<DebugDirectory>> {
+    let pe = u32_at(data, DOS_NEW_HEADER)? as usize;
+    ensure!(data.get(pe..pe + 4) == Some(PE_SIGNATURE), "not a PE image");
+    let num_sections = u16_at(data, pe + COFF_NUMBER_OF_SECTIONS)? as usize;
+    let optional = pe + COFF_HEADER_SIZE;
+    let optional_size = u16_at(data, pe + COFF_SIZE_OF_OPTIONAL_HEADER)? as usize;
+    let directories = optional
+        + match u16_at(data, optional)? {
+            OPTIONAL_MAGIC_PE32_PLUS => OPTIONAL_DATA_DIRECTORIES_64,
+            _ => OPTIONAL_DATA_DIRECTORIES_32,
+        };
+    let slot = directories + IMAGE_DIRECTORY_ENTRY_DEBUG * 8;
+    if slot + 8 > optional + optional_size {
+        return Ok(None);
+    }
+    let (debug_rva, debug_size) = (u32_at(data, slot)?, u32_at(data, slot + 4)?);
+    if debug_rva == 0 {
+        return Ok(None);
+    }
+
+    let sections = optional + optional_size;
+    let mut offset = None;
+    for i in 0..num_sections {
+        let header = sections + i * SECTION_HEADER_SIZE;
+        let address = u32_at(data, header + SECTION_VIRTUAL_ADDRESS)?;
+        let raw_size = u32_at(data, header + SECTION_SIZE_OF_RAW_DATA)?;
+        let size = u32_at(data, header + SECTION_VIRTUAL_SIZE)?.max(raw_size);
+        if (address..address.saturating_add(size)).contains(&debug_rva) {
+            let raw = u32_at(data, header + SECTION_POINTER_TO_RAW_DATA)?;
+            offset = Some(raw as usize + (debug_rva - address) as usize);
+            break;
+        }
+    }
+    let Some(offset) = offset else {
+        return Ok(None);
+    };
+    let count = debug_size as usize / DEBUG_DIRECTORY_SIZE;
+    ensure!(
+        offset + count * DEBUG_DIRECTORY_SIZE <= data.len(),
+        "debug directory past the end of the image"
+    );
+
+    Ok(Some(DebugDirectory {
+        coff: pe,
+        checksum_offset: optional + OPTIONAL_CHECKSUM,
+        slot,
+        offset,
+        count,
+    }))
+}
+
+fn codeview(data: &[u8]) -> Result<Option<CodeView>> {
+    let Some(directory) = debug_directory(data)? else {
+        return Ok(None);
+    };
+    for entry in directory.entries() {
+        // Only the GUID and age are read, a malformed path doesn't matter here
+        let Some(record) = rsds_record(data, entry)? else {
+            continue;
+        };
+        ensure!(
+            record + RSDS_HEADER_SIZE <= data.len(),
+            "RSDS record past the end of the image"
+        );
+        return Ok(Some(CodeView {
+            guid: data[record + 4..record + 20].try_into().unwrap(),
+            age: u32_at(data, record + 20)?,
+            age_offset: record + 20,
+            checksum_offset: directory.checksum_offset,
+        }));
+    }
+    Ok(None)
+}
+
+/// Offset of the RSDS record the debug directory entry at `entry` points at, `None` if it is
+/// not a CodeView entry with an RSDS record
+fn rsds_record(data: &[u8], entry: usize) -> Result<Option<usize>> {
+    if u32_at(data, entry + DEBUG_TYPE)? != IMAGE_DEBUG_TYPE_CODEVIEW {
+        return Ok(None);
+    }
+    let record = u32_at(data, entry + DEBUG_POINTER_TO_RAW_DATA)? as usize;
+    if data.get(record..record + 4) != Some(RSDS_SIGNATURE) {
+        return Ok(None);
+    }
+    Ok(Some(record))
+}
+
+/// Size of the RSDS record the debug directory entry at `entry` points at, through the NUL
+/// ending the PDB path, `None` if it is not a CodeView entry with an RSDS record or the path
+/// isn't terminated
+fn rsds_size(data: &[u8], entry: usize) -> Result<Option<u32>> {
+    let Some(record) = rsds_record(data, entry)? else {
+        return Ok(None);
+    };
+    let path_len = data
+        .get(record + RSDS_HEADER_SIZE..)
+        .and_then(|path| path.iter().take(MAX_PDB_PATH).position(|&b| b == 0));
+    Ok(path_len.map(|len| (RSDS_HEADER_SIZE + len + 1) as u32))
+}
+
+/// Write `data` back to `path`, first updating the header checksum if the image has one
+fn write_image(path: &Path, mut data: Vec<u8>, checksum_offset: usize) -> Result<()> {
+    // Drivers and boot loaded images are rejected when the checksum is stale, images leaving
+    // it at 0 don't care
+    if u32_at(&data, checksum_offset)? != 0 {
+        let checksum = image_checksum(&data, checksum_offset);
+        data[checksum_offset..checksum_offset + 4].copy_from_slice(&checksum.to_le_bytes());
+    }
+    fs::write(path, &data).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// The optional header CheckSum of `data`, computed the way imagehlp's CheckSumMappedFile does
+fn image_checksum(data: &[u8], checksum_offset: usize) -> u32 {
+    let mut sum = 0u32;
+    for (i, word) in data.chunks(2).enumerate() {
+        if (checksum_offset..checksum_offset + 4).contains(&(i * 2)) {
+            continue;
+        }
+        sum += u16::from_le_bytes([word[0], word.get(1).copied().unwrap_or(0)]) as u32;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum.wrapping_add(data.len() as u32)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .context("image headers truncated")?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("image headers truncated")?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: [u8; 16] = *b"0123456789abcdef";
+    /// File offset of the RSDS record in [`image`]
+    const RSDS: usize = 0x240;
+
+    /// A PE32+ image with one section holding a debug directory with a single CodeView entry
+    fn image(pdb_path: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 0x400];
+        let mut put = |offset: usize, bytes: &[u8]| {
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        let pe = 0x40;
+        put(DOS_NEW_HEADER, &(pe as u32).to_le_bytes());
+        put(pe, PE_SIGNATURE);
+        put(pe + COFF_NUMBER_OF_SECTIONS, &1u16.to_le_bytes());
+        put(pe + COFF_TIME_DATE_STAMP, &0x1234u32.to_le_bytes());
+        let optional = pe + COFF_HEADER_SIZE;
+        let optional_size = OPTIONAL_DATA_DIRECTORIES_64 + 16 * 8;
+        put(
+            pe + COFF_SIZE_OF_OPTIONAL_HEADER,
+            &(optional_size as u16).to_le_bytes(),
+        );
+        put(optional, &OPTIONAL_MAGIC_PE32_PLUS.to_le_bytes());
+        let slot = optional + OPTIONAL_DATA_DIRECTORIES_64 + IMAGE_DIRECTORY_ENTRY_DEBUG * 8;
+        put(slot, &0x1000u32.to_le_bytes());
+        put(slot + 4, &(DEBUG_DIRECTORY_SIZE as u32).to_le_bytes());
+
+        let section = optional + optional_size;
+        put(section + SECTION_VIRTUAL_SIZE, &0x200u32.to_le_bytes());
+        put(section + SECTION_VIRTUAL_ADDRESS, &0x1000u32.to_le_bytes());
+        put(section + SECTION_SIZE_OF_RAW_DATA, &0x200u32.to_le_bytes());
+        put(
+            section + SECTION_POINTER_TO_RAW_DATA,
+            &0x200u32.to_le_bytes(),
+        );
+
+        let entry = 0x200;
+        put(entry + DEBUG_TYPE, &IMAGE_DEBUG_TYPE_CODEVIEW.to_le_bytes());
+        put(
+            entry + DEBUG_POINTER_TO_RAW_DATA,
+            &(RSDS as u32).to_le_bytes(),
+        );
+        put(RSDS, RSDS_SIGNATURE);
+        put(RSDS + 4, &GUID);
+        put(RSDS + 20, &3u32.to_le_bytes());
+        put(RSDS + RSDS_HEADER_SIZE, pdb_path);
+        data
+    }
+
+    #[test]
+    fn reads_codeview() {
+        let codeview = codeview(&image(b"x.pdb\0")).unwrap().unwrap();
+        assert_eq!(codeview.guid, GUID);
+        assert_eq!(codeview.age, 3);
+        assert_eq!(codeview.age_offset, RSDS + 20);
+    }
+
+    #[test]
+    fn measures_rsds_record() {
+        let data = image(b"x.pdb\0");
+        assert_eq!(rsds_size(&data, 0x200).unwrap(), Some(24 + 6));
+    }
+
+    #[test]
+    fn unterminated_pdb_path() {
+        let data = image(&[b'a'; 0x400 - RSDS - RSDS_HEADER_SIZE]);
+        assert_eq!(rsds_size(&data, 0x200).unwrap(), None);
+        assert_eq!(codeview(&data).unwrap().unwrap().age, 3);
+    }
+
+    #[test]
+    fn checksum_folds_carries() {
+        // Two words overflowing 16 bits, then the length, skipping the checksum field itself
+        let data = [0xff, 0xff, 0x02, 0x00, 0xaa, 0xaa, 0xaa, 0xaa];
+        assert_eq!(image_checksum(&data, 4), 0x0002 + data.len() as u32);
+        // An odd trailing byte counts as the low half of a word
+        assert_eq!(image_checksum(&[0x01, 0x00, 0x05], 100), 0x0006 + 3);
+    }
+
+    #[test]
+    fn patches_age_and_checksum() {
+        let mut data = image(b"x.pdb\0");
+        let checksum_offset = 0x40 + COFF_HEADER_SIZE + OPTIONAL_CHECKSUM;
+        data[checksum_offset..checksum_offset + 4].copy_from_slice(&1u32.to_le_bytes());
+        let path = std::env::temp_dir().join(format!("pdbgen_age_{}.exe", std::process::id()));
+        fs::write(&path, &data).unwrap();
+
+        patch_codeview_age(&path, 7).unwrap();
+        let patched = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let codeview = codeview(&patched).unwrap().unwrap();
+        assert_eq!(codeview.age, 7);
+        assert_eq!(
+            u32_at(&patched, checksum_offset).unwrap(),
+            image_checksum(&patched, checksum_offset)
+        );
+    }
+
+    #[test]
+    fn no_debug_directory() {
+        let mut data = image(b"x.pdb\0");
+        data[RSDS..RSDS + 4].copy_from_slice(b"NB10");
+        assert_eq!(codeview(&data).unwrap(), None);
+    }
+}
@@ -0,0 +1,422 @@
+//! Named streams of a committed PDB
+//!
+//! The PDB info stream (stream 1) follows its 28 byte header with a map from stream name to
+//! stream index: a buffer of NUL terminated names, then a hash table of (name offset, stream)
+//! pairs keyed by a 16 bit hash of the name, then the feature codes. Adding a name means
+//! rebuilding the table, since it may have to grow.
+//!
+//! The `/names` stream is the string table other streams refer to names by offset into: a
+//! header, a buffer of NUL terminated strings starting with the empty one, a hash table of
+//! offsets without any occupancy bits (0 marks an empty bucket), then the number of strings.
+
+use anyhow::{Context as _, Result, ensure};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::model::PdbInfo;
+use crate::msf::{self, Editor};
+
+const INFO_STREAM: usize = 1;
+/// Version, signature, age and GUID
+const HEADER_SIZE: usize = 28;
+// Offsets within the header
+const SIGNATURE: usize = 4;
+const AGE: usize = 8;
+const GUID: usize = 12;
+
+/// Named stream holding the string table
+const NAMES_STREAM: &str = "/names";
+const NAMES_SIGNATURE: u32 = 0xeffe_effe;
+/// String table hash version, picking [`hash_string_v1`] or [`hash_string_v2`]
+const NAMES_VERSION: u32 = 1;
+
+/// Identity of the PDB at `path`: the GUID, age and signature an image has to ask for
+pub fn read_pdb_info(path: &Path) -> Result<PdbInfo> {
+    let header = msf::read_stream(path, INFO_STREAM, 0, HEADER_SIZE)?;
+    let u32_at = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+    Ok(PdbInfo {
+        age: u32_at(AGE),
+        timestamp: u32_at(SIGNATURE),
+        guid: header[GUID..GUID + 16].try_into().unwrap(),
+    })
+}
+
+/// Store `data` in a new stream of the PDB and register it under `name`
+pub(crate) fn add_named_stream(msf: &mut Editor, name: &str, data: Vec<u8>) -> Result<()> {
+    let len = msf.stream_len(INFO_STREAM)?;
+    let info = msf.read_stream(INFO_STREAM, 0, len as usize)?;
+    let mut map = NamedStreams::parse(&info).context("failed to parse the named stream map")?;
+    ensure!(
+        !map.entries.iter().any(|(existing, _)| existing == name),
+        "PDB already has a stream named {name}"
+    );
+
+    let stream = msf.add_stream(data)?;
+    map.entries.push((name.to_string(), stream as u32));
+    msf.replace_stream(INFO_STREAM, map.write(&info[..HEADER_SIZE]))
+}
+
+/// Offsets of `names` in the PDB's string table, adding the ones it lacks and the table itself
+/// if the PDB has none
+pub(crate) fn intern_names(msf: &mut Editor, names: &[&str]) -> Result<Vec<u32>> {
+    let len = msf.stream_len(INFO_STREAM)?;
+    let info = msf.read_stream(INFO_STREAM, 0, len as usize)?;
+    let map = NamedStreams::parse(&info).context("failed to parse the named stream map")?;
+    let stream = map
+        .entries
+        .iter()
+        .find(|(name, _)| name == NAMES_STREAM)
+        .map(|&(_, stream)| stream as usize);
+
+    let mut table = match stream {
+        Some(stream) => {
+            let len = msf.stream_len(stream)?;
+            StringTable::parse(&msf.read_stream(stream, 0, len as usize)?)
+                .context("failed to parse the /names string table")?
+        }
+        None => StringTable::default(),
+    };
+    let offsets = names.iter().map(|name| table.intern(name)).collect();
+
+    match stream {
+        Some(stream) => msf.replace_stream(stream, table.write())?,
+        None => add_named_stream(msf, NAMES_STREAM, table.write())?,
+    }
+    Ok(offsets)
+}
+
+struct NamedStreams {
+    /// Names and stream indices in the order they were found
+    entries: Vec<(String, u32)>,
+    capacity: u32,
+    /// Feature codes and anything else following the hash table, kept as is
+    tail: Vec<u8>,
+}
+
+impl NamedStreams {
+    fn parse(info: &[u8]) -> Result<Self> {
+        let mut reader = Reader {
+            data: info,
+            position: HEADER_SIZE,
+        };
+        let strings_len = reader.u32()? as usize;
+        let strings = reader.bytes(strings_len)?;
+
+        let _size = reader.u32()?;
+        let capacity = reader.u32()?;
+        let present = reader.bit_vector()?;
+        let _deleted = reader.bit_vector()?;
+
+        let mut entries = Vec::new();
+        for bucket in 0..capacity {
+            let word = present.get(bucket as usize / 32).copied().unwrap_or(0);
+            if word & (1 << (bucket % 32)) == 0 {
+                continue;
+            }
+            let offset = reader.u32()? as usize;
+            let stream = reader.u32()?;
+            let name = strings
+                .get(offset..)
+                .and_then(|rest| rest.split(|&b| b == 0).next())
+                .context("name offset outside the string buffer")?;
+            entries.push((String::from_utf8_lossy(name).into_owned(), stream));
+        }
+
+        Ok(Self {
+            entries,
+            capacity,
+            tail: info[reader.position..].to_vec(),
+        })
+    }
+
+    /// The info stream with `header` followed by this map
+    fn write(&self, header: &[u8]) -> Vec<u8> {
+        let mut strings = Vec::new();
+        let mut offsets = Vec::with_capacity(self.entries.len());
+        for (name, _) in &self.entries {
+            offsets.push(strings.len() as u32);
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+
+        // Grown the way the reference implementation does, keeping the load under 2/3
+        let mut capacity = self.capacity.max(1);
+        while self.entries.len() as u32 >= capacity * 2 / 3 + 1 {
+            capacity *= 2;
+        }
+        let mut buckets: Vec<Option<usize>> = vec![None; capacity as usize];
+        for (i, (name, _)) in self.entries.iter().enumerate() {
+            let mut bucket = hash_name(name) as usize % capacity as usize;
+            while buckets[bucket].is_some() {
+                bucket = (bucket + 1) % capacity as usize;
+            }
+            buckets[bucket] = Some(i);
+        }
+
+        // Sparse bit vectors only store words up to the last set bit
+        let last = buckets
+            .iter()
+            .rposition(Option::is_some)
+            .map_or(0, |b| b + 1);
+        let mut present = vec![0u32; last.div_ceil(32)];
+        for (bucket, entry) in buckets.iter().enumerate() {
+            if entry.is_some() {
+                present[bucket / 32] |= 1 << (bucket % 32);
+            }
+        }
+
+        let mut out = header.to_vec();
+        out.extend((strings.len() as u32).to_le_bytes());
+        out.extend(&strings);
+        out.extend((self.entries.len() as u32).to_le_bytes());
+        out.extend(capacity.to_le_bytes());
+        out.extend((present.len() as u32).to_le_bytes());
+        out.extend(present.iter().flat_map(|word| word.to_le_bytes()));
+        // No deleted buckets
+        out.extend(0u32.to_le_bytes());
+        for &i in buckets.iter().flatten() {
+            out.extend(offsets[i].to_le_bytes());
+            out.extend(self.entries[i].1.to_le_bytes());
+        }
+        out.extend(&self.tail);
+        out
+    }
+}
+
+/// The string table in the `/names` stream
+struct StringTable {
+    version: u32,
+    /// NUL terminated strings, starting with the empty string at offset 0
+    buffer: Vec<u8>,
+    /// Offset of every string in the hash table, by string
+    offsets: HashMap<String, u32>,
+}
+
+impl Default for StringTable {
+    fn default() -> Self {
+        Self {
+            version: NAMES_VERSION,
+            buffer: vec![0],
+            offsets: HashMap::new(),
+        }
+    }
+}
+
+impl StringTable {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader { data, position: 0 };
+        ensure!(
+            reader.u32()? == NAMES_SIGNATURE,
+            "string table signature mismatch"
+        );
+        let version = reader.u32()?;
+        ensure!(
+            matches!(version, 1 | 2),
+            "unknown string table hash version {version}"
+        );
+        let buffer_len = reader.u32()? as usize;
+        let buffer = reader.bytes(buffer_len)?.to_vec();
+        let buckets = reader.u32()?;
+
+        let mut offsets = HashMap::new();
+        for _ in 0..buckets {
+            let offset = reader.u32()?;
+            if offset == 0 {
+                continue;
+            }
+            let name = buffer
+                .get(offset as usize..)
+                .and_then(|rest| rest.split(|&b| b == 0).next())
+                .context("string offset outside the buffer")?;
+            offsets.insert(String::from_utf8_lossy(name).into_owned(), offset);
+        }
+
+        Ok(Self {
+            version,
+            buffer,
+            offsets,
+        })
+    }
+
+    /// Offset of `name`, appending it to the buffer if it isn't there yet
+    fn intern(&mut self, name: &str) -> u32 {
+        if name.is_empty() {
+            return 0;
+        }
+        if let Some(&offset) = self.offsets.get(name) {
+            return offset;
+        }
+        let offset = self.buffer.len() as u32;
+        self.buffer.extend_from_slice(name.as_bytes());
+        self.buffer.push(0);
+        self.offsets.insert(name.to_string(), offset);
+        offset
+    }
+
+    fn write(&self) -> Vec<u8> {
+        // Linear probing needs free buckets, so keep the load under 3/4
+        let capacity = (self.offsets.len() * 4 / 3 + 1).max(1);
+        let mut buckets = vec![0u32; capacity];
+        let mut entries: Vec<_> = self.offsets.iter().collect();
+        entries.sort_unstable_by_key(|&(_, &offset)| offset);
+        for (name, &offset) in entries {
+            let hash = match self.version {
+                1 => hash_string_v1(name),
+                _ => hash_string_v2(name),
+            };
+            let mut bucket = hash as usize % capacity;
+            while buckets[bucket] != 0 {
+                bucket = (bucket + 1) % capacity;
+            }
+            buckets[bucket] = offset;
+        }
+
+        let mut out = Vec::new();
+        out.extend(NAMES_SIGNATURE.to_le_bytes());
+        out.extend(self.version.to_le_bytes());
+        out.extend((self.buffer.len() as u32).to_le_bytes());
+        out.extend(&self.buffer);
+        out.extend((capacity as u32).to_le_bytes());
+        out.extend(buckets.iter().flat_map(|offset| offset.to_le_bytes()));
+        out.extend((self.offsets.len() as u32).to_le_bytes());
+        out
+    }
+}
+
+/// The 16 bit hash the named stream map is keyed by
+fn hash_name(name: &str) -> u16 {
+    hash_string_v1(name) as u16
+}
+
+/// `hashStringV1`, the hash of the named stream map and version 1 string tables
+fn hash_string_v1(name: &str) -> u32 {
+    let bytes = name.as_bytes();
+    let mut chunks = bytes.chunks_exact(4);
+    let mut hash = chunks.by_ref().fold(0u32, |hash, word| {
+        hash ^ u32::from_le_bytes(word.try_into().unwrap())
+    });
+    let mut rest = chunks.remainder();
+    if rest.len() >= 2 {
+        hash ^= u16::from_le_bytes([rest[0], rest[1]]) as u32;
+        rest = &rest[2..];
+    }
+    if let Some(&byte) = rest.first() {
+        hash ^= byte as u32;
+    }
+    hash |= 0x2020_2020;
+    hash ^= hash >> 11;
+    hash ^ (hash >> 16)
+}
+
+/// `hashStringV2`, the hash of version 2 string tables
+fn hash_string_v2(name: &str) -> u32 {
+    let bytes = name.as_bytes();
+    let mut chunks = bytes.chunks_exact(4);
+    let mix = |hash: u32, value: u32| {
+        let hash = hash.wrapping_add(value);
+        let hash = hash.wrapping_add(hash << 10);
+        hash ^ (hash >> 6)
+    };
+    let hash = chunks.by_ref().fold(0xb170_a1bf, |hash, word| {
+        mix(hash, u32::from_le_bytes(word.try_into().unwrap()))
+    });
+    let hash = chunks
+        .remainder()
+        .iter()
+        .fold(hash, |hash, &byte| mix(hash, byte as u32));
+    hash.wrapping_mul(1_664_525).wrapping_add(1_013_904_223)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + len)
+            .context("info stream ends early")?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn bit_vector(&mut self) -> Result<Vec<u32>> {
+        let words = self.u32()?;
+        (0..words).map(|_| self.u32()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_names_like_the_reference_implementation() {
+        // Nothing to fold in, leaving the case bits of every byte
+        assert_eq!(hash_string_v1(""), 0x2024_0400);
+        // The case bits are set whatever the input, so names hash case insensitively
+        assert_eq!(hash_string_v1("/names"), hash_string_v1("/NAMES"));
+        assert_eq!(hash_name("/names"), 0xfc21);
+        assert_ne!(hash_string_v2("/names"), hash_string_v2("/NAMES"));
+    }
+
+    #[test]
+    fn round_trips_the_named_stream_map() {
+        let header = [7; HEADER_SIZE];
+        let names = [
+            "/names",
+            "/LinkInfo",
+            "/src/headerblock",
+            "pdbgen/manifest",
+            "a",
+            "b",
+        ];
+        let map = NamedStreams {
+            entries: names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.to_string(), i as u32 + 5))
+                .collect(),
+            capacity: 1,
+            // The VC140 feature code
+            tail: 20140508u32.to_le_bytes().to_vec(),
+        };
+        let info = map.write(&header);
+        assert_eq!(info[..HEADER_SIZE], header);
+
+        let parsed = NamedStreams::parse(&info).unwrap();
+        let mut entries = parsed.entries.clone();
+        entries.sort();
+        let mut expected = map.entries.clone();
+        expected.sort();
+        assert_eq!(entries, expected);
+        // Grown until the table is less than 2/3 full
+        assert_eq!(parsed.capacity, 16);
+        assert_eq!(parsed.tail, map.tail);
+    }
+
+    #[test]
+    fn interns_names_in_the_string_table() {
+        for version in [1, 2] {
+            let mut table = StringTable {
+                version,
+                ..Default::default()
+            };
+            assert_eq!(table.intern("KERNEL32.dll"), 1);
+            assert_eq!(table.intern("USER32.dll"), 14);
+            assert_eq!(table.intern("KERNEL32.dll"), 1);
+            assert_eq!(table.intern(""), 0);
+
+            let parsed = StringTable::parse(&table.write()).unwrap();
+            assert_eq!(parsed.version, version);
+            assert_eq!(parsed.buffer, table.buffer);
+            assert_eq!(parsed.offsets, table.offsets);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Translating RVAs of an unpacked or dumped layout to the layout of the image
+//!
+//! A view of a memory dump or of an image unpacked by hand can place code at other RVAs than
+//! the section table of the image a debugger loads. Rather than describing the difference with
+//! OMAP, which few consumers besides DIA honour, every RVA in the model is moved to where it
+//! lives in the image before emission.
+
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+use crate::Report;
+use crate::model::{Function, Model, Section};
+use crate::reader::ViewSegment;
+
+/// `size` bytes at RVA `source` in the view that live at RVA `target` in the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LayoutRange {
+    pub source: u32,
+    pub target: u32,
+    pub size: u32,
+}
+
+impl LayoutRange {
+    fn translate(&self, rva: u32) -> Option<u32> {
+        let offset = rva.checked_sub(self.source)?;
+        match offset < self.size {
+            true => self.target.checked_add(offset),
+            false => None,
+        }
+    }
+}
+
+/// Ranges mapping the view's layout to the image's, derived from where the view's segments are
+/// backed in the file and where the section table places those file offsets
+pub(crate) fn derive(
+    segments: &[ViewSegment],
+    base: u64,
+    sections: &[Section],
+) -> Vec<LayoutRange> {
+    let mut ranges = Vec::new();
+    for segment in segments {
+        let Some(source_start) = segment
+            .range
+            .start
+            .checked_sub(base)
+            .and_then(|rva| u32::try_from(rva).ok())
+        else {
+            continue;
+        };
+        let Some(file_end) = segment.data_offset.checked_add(segment.data_length) else {
+            continue;
+        };
+        let file = segment.data_offset..file_end;
+        for section in sections {
+            let raw = section.pointer_to_raw_data as u64
+                ..section.pointer_to_raw_data as u64 + section.size_of_raw_data as u64;
+            let start = file.start.max(raw.start);
+            let end = file.end.min(raw.end);
+            if start >= end {
+                continue;
+            }
+            // A range running past the end of the 32 bit address space describes nothing in the
+            // image, so it is dropped rather than wrapped
+            let offset = |from: u64, to: u64| u32::try_from(to - from).ok();
+            let range = (|| {
+                let size = offset(start, end)?;
+                let source = source_start.checked_add(offset(file.start, start)?)?;
+                let target = section
+                    .virtual_address
+                    .checked_add(offset(raw.start, start)?)?;
+                source.checked_add(size)?;
+                target.checked_add(size)?;
+                Some(LayoutRange {
+                    source,
+                    target,
+                    size,
+                })
+            })();
+            ranges.extend(range);
+        }
+    }
+    ranges
+}
+
+/// Whether `ranges` move anything at all
+pub(crate) fn is_identity(ranges: &[LayoutRange]) -> bool {
+    ranges.iter().all(|range| range.source == range.target)
+}
+
+/// The RVA `rva` of the view's layout has in the image's, `None` if `ranges` don't cover it.
+/// Empty `ranges` leave every RVA where it is.
+pub(crate) fn translate(ranges: &[LayoutRange], rva: u32) -> Option<u32> {
+    match ranges.is_empty() {
+        true => Some(rva),
+        false => ranges.iter().find_map(|range| range.translate(rva)),
+    }
+}
+
+/// Move `functions` from the view's layout to the image's, dropping those `ranges` don't
+/// cover. Returns how many were dropped.
+pub(crate) fn relocate_functions(functions: &mut Vec<Function>, ranges: &[LayoutRange]) -> usize {
+    if ranges.is_empty() {
+        return 0;
+    }
+    // A range has to stay contiguous, so both ends must fall in the same mapping
+    let translate_range = |range: &Range<u32>| {
+        ranges.iter().find_map(|mapping| {
+            let start = mapping.translate(range.start)?;
+            let end = match range.is_empty() {
+                true => start,
+                false => mapping.translate(range.end - 1)?.checked_add(1)?,
+            };
+            Some(start..end)
+        })
+    };
+
+    let before = functions.len();
+    functions.retain_mut(|function| {
+        let Some(translated) = function
+            .ranges
+            .iter()
+            .map(translate_range)
+            .collect::<Option<Vec<_>>>()
+        else {
+            return false;
+        };
+        function.ranges = translated;
+        function.call_sites.retain_mut(|site| {
+            translate(ranges, site.rva)
+                .map(|rva| site.rva = rva)
+                .is_some()
+        });
+        function.heap_alloc_sites.retain_mut(|site| {
+            translate(ranges, site.rva)
+                .map(|rva| site.rva = rva)
+                .is_some()
+        });
+        function.blocks = function
+            .blocks
+            .iter()
+            .filter_map(|&b| translate(ranges, b))
+            .collect();
+        function.blocks.sort_unstable();
+        function.calls = function
+            .calls
+            .iter()
+            .filter_map(|&call| translate(ranges, call))
+            .collect();
+        function.calls.sort_unstable();
+        function.calls.dedup();
+        true
+    });
+    before - functions.len()
+}
+
+/// Move the RVAs of everything in `model` but its functions, which
+/// [`relocate_functions`] moved while they were collected, from the view's layout to the
+/// image's, dropping what `ranges` don't cover
+pub(crate) fn relocate(model: &mut Model, ranges: &[LayoutRange], report: &mut Report) {
+    let mut unmapped = relocate_functions(&mut model.unwind_functions, ranges);
+    model
+        .publics
+        .retain_mut(|public| match translate(ranges, public.rva) {
+            Some(rva) => {
+                public.rva = rva;
+                true
+            }
+            None => {
+                unmapped += 1;
+                false
+            }
+        });
+    model
+        .vtables
+        .retain_mut(|vtable| match translate(ranges, vtable.rva) {
+            Some(rva) => {
+                vtable.rva = rva;
+                true
+            }
+            None => {
+                unmapped += 1;
+                false
+            }
+        });
+
+    if unmapped > 0 {
+        report.warn(format!(
+            "Dropped {unmapped} symbol(s) at addresses the layout mapping doesn't cover"
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Public;
+
+    fn range(source: u32, target: u32, size: u32) -> LayoutRange {
+        LayoutRange {
+            source,
+            target,
+            size,
+        }
+    }
+
+    #[test]
+    fn derives_ranges_from_file_offsets() {
+        // The dump maps the file's .text at 0x5000, the image at 0x1000
+        let segments = [ViewSegment {
+            range: 0x14000_5000..0x14000_5800,
+            data_offset: 0x400,
+            data_length: 0x800,
+        }];
+        let sections = [Section {
+            virtual_address: 0x1000,
+            pointer_to_raw_data: 0x600,
+            size_of_raw_data: 0x1000,
+            ..Default::default()
+        }];
+        assert_eq!(
+            derive(&segments, 0x14000_0000, &sections),
+            [range(0x5200, 0x1000, 0x600)]
+        );
+    }
+
+    #[test]
+    fn derive_skips_overflowing_ranges() {
+        let segments = [ViewSegment {
+            range: 0x1000..0x2000,
+            data_offset: 0,
+            data_length: 0x1000,
+        }];
+        let sections = [Section {
+            virtual_address: u32::MAX - 0x10,
+            pointer_to_raw_data: 0x800,
+            size_of_raw_data: 0x800,
+            ..Default::default()
+        }];
+        assert_eq!(derive(&segments, 0, &sections), []);
+    }
+
+    #[test]
+    fn translates_within_ranges() {
+        let ranges = [range(0x5000, 0x1000, 0x100)];
+        assert_eq!(translate(&ranges, 0x5010), Some(0x1010));
+        assert_eq!(translate(&ranges, 0x5100), None);
+        assert_eq!(translate(&ranges, 0x4fff), None);
+        assert_eq!(translate(&[], 0x4fff), Some(0x4fff));
+        assert_eq!(translate(&[range(0, u32::MAX, 0x10)], 1), None);
+    }
+
+    #[test]
+    fn relocates_functions() {
+        let ranges = [range(0x5000, 0x1000, 0x100), range(0x6000, 0x2000, 0x100)];
+        let mut functions = vec![
+            Function {
+                ranges: vec![0x5010..0x5020],
+                blocks: vec![0x5018, 0x5010, 0x7000],
+                ..Default::default()
+            },
+            // Straddles two mappings, so it can't stay contiguous
+            Function {
+                ranges: vec![0x50f0..0x6010],
+                ..Default::default()
+            },
+        ];
+        assert_eq!(relocate_functions(&mut functions, &ranges), 1);
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].ranges, [0x1010..0x1020]);
+        assert_eq!(functions[0].blocks, [0x1010, 0x1018]);
+    }
+
+    #[test]
+    fn relocates_publics() {
+        let mut model = Model {
+            publics: vec![
+                Public {
+                    rva: 0x5004,
+                    ..Default::default()
+                },
+                Public {
+                    rva: 0x9000,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut report = Report::default();
+        relocate(&mut model, &[range(0x5000, 0x1000, 0x100)], &mut report);
+        assert_eq!(model.publics.len(), 1);
+        assert_eq!(model.publics[0].rva, 0x1004);
+    }
+}
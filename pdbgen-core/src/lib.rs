@@ -0,0 +1,216 @@
+//! Binary Ninja independent PDB generation
+//!
+//! Frontends either implement [`reader::ViewReader`] and let [`collect_model`] gather a
+//! [`Model`] describing the image, or build the model themselves, then hand it to [`write_pdb`].
+
+mod cache;
+mod clr;
+mod collect;
+mod dbi;
+mod delta;
+mod demangle;
+mod emit;
+mod hash;
+mod image;
+mod info;
+mod intern;
+mod layout;
+mod literals;
+pub mod model;
+pub mod msf;
+mod names;
+mod options;
+mod progress;
+pub mod reader;
+pub mod registers;
+mod report;
+mod types;
+mod validate;
+mod xref;
+
+use anyhow::{Context as _, Result};
+use pdb::{FallibleIterator as _, PDB, SymbolData};
+use pdb_sdk::builders::PdbBuilder;
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+pub use cache::Cache;
+pub use collect::{collect_model, image_rvas};
+pub use delta::merge_delta;
+pub use emit::build_pdb;
+pub use image::{
+    CodeView, patch_codeview_age, read_codeview, strip_codeview, sync_debug_directory,
+};
+pub use info::read_pdb_info;
+pub use intern::Interner;
+pub use layout::LayoutRange;
+pub use model::Model;
+pub use options::{
+    DbiHeader, DuplicateNames, ItaniumNames, LongNamePolicy, ModuleLayout, NameForm,
+    NamePrecedence, NameSanitization, Options, RustNames, StringLiteralNames, SymbolType,
+};
+pub use progress::{Cancelled, Eta, Progress};
+pub use report::{Report, TypeIssue};
+pub use types::TypeKey;
+pub use validate::validate_pdb;
+
+/// Marker embedded in an S_ENVBLOCK of every generated PDB
+pub const CANARY: &str = "pdbgen_canary";
+
+/// Name of the stream [`Options::manifest_stream`] adds
+pub const MANIFEST_STREAM: &str = "pdbgen/manifest";
+
+/// Size of the buffer used when writing the MSF to disk
+const OUTPUT_BUFFER_SIZE: usize = 1 << 20;
+
+/// Build a PDB from `model` and write it to `path`
+pub fn write_pdb(
+    model: &Model,
+    options: &Options,
+    path: &Path,
+    report: &mut Report,
+    progress: &mut dyn Progress,
+) -> Result<()> {
+    let start = Instant::now();
+    let builder = build_pdb(model, options, report, progress)?;
+    report.time("Symbol emission", start);
+    progress.check_cancelled()?;
+
+    info!("Writing PDB to: {}", path.display());
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create the directory {}", parent.display()))?;
+    }
+
+    // Write next to the destination and rename into place so an interrupted run never leaves a
+    // truncated MSF where a debugger would pick it up
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let start = Instant::now();
+    let result = commit(builder, model, &tmp_path, options);
+    report.time("MSF commit", start);
+
+    // Validate before the rename so a broken PDB never replaces a working one
+    let result = result.and_then(|()| {
+        if options.validate {
+            let start = Instant::now();
+            validate_pdb(&tmp_path, model)?;
+            report.time("Validation", start);
+        }
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("failed to move PDB into place at {}", path.display()))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result?;
+
+    info!("PDB written successfully to: {}", path.display());
+
+    Ok(())
+}
+
+fn commit(builder: PdbBuilder, model: &Model, path: &Path, options: &Options) -> Result<()> {
+    // Stream pages straight to disk through a large buffer rather than staging the whole MSF
+    let file =
+        fs::File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut output = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, file);
+    builder.commit(&mut output)?;
+    output.into_inner()?.sync_all()?;
+
+    // Every change pdb-sdk can't make goes through one editor, so a huge MSF is copied once
+    // at most rather than once per change
+    let mut msf = msf::Editor::open(path)?;
+    if !options.dbi_header.is_empty() {
+        dbi::patch_header(&mut msf, &options.dbi_header)?;
+    }
+    if options.exception_stream && !model.exception_data.is_empty() {
+        dbi::add_debug_stream(
+            &mut msf,
+            dbi::DebugStream::Exception,
+            model.exception_data.clone(),
+        )?;
+    }
+    if options.manifest_stream {
+        info::add_named_stream(&mut msf, MANIFEST_STREAM, manifest(model, options)?)?;
+    }
+    if options.cross_module_references {
+        xref::add_cross_references(&mut msf, model)?;
+    }
+    if options.page_size != msf::DEFAULT_PAGE_SIZE {
+        info!("Re-paging PDB to {} byte pages", options.page_size);
+        msf.set_page_size(options.page_size)?;
+    }
+    msf.commit()
+}
+
+/// Where a symbol store keeps the PDB for `image`, `<store>/<name>.pdb/<GUID><age>/<name>.pdb`,
+/// the layout `srv*` symbol paths and symstore use
+pub fn symbol_store_path(store: &Path, image: &Path, info: &model::PdbInfo) -> PathBuf {
+    let mut name = image.file_stem().unwrap_or_default().to_owned();
+    name.push(".pdb");
+    store.join(&name).join(info.symbol_key()).join(&name)
+}
+
+/// JSON contents of the [`MANIFEST_STREAM`]
+fn manifest(model: &Model, options: &Options) -> Result<Vec<u8>> {
+    #[derive(serde::Serialize)]
+    struct Manifest<'a> {
+        generator: &'static str,
+        version: &'static str,
+        #[serde(skip_serializing_if = "str::is_empty")]
+        frontend: &'a str,
+        /// Unix time, left out of deterministic builds
+        #[serde(skip_serializing_if = "Option::is_none")]
+        generated: Option<u64>,
+        options: &'a Options,
+    }
+
+    let generated = match options.deterministic {
+        true => None,
+        false => Some(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        ),
+    };
+    Ok(serde_json::to_vec_pretty(&Manifest {
+        generator: "binja_pdbgen",
+        version: env!("CARGO_PKG_VERSION"),
+        frontend: &model.frontend,
+        generated,
+        options,
+    })?)
+}
+
+/// Check if a PDB file contains a global symbol with an EnvBlock containing "pdbgen_canary"
+/// If so the PDB was generated by this tool and allow replacing
+pub fn is_generated_pdb(pdb_path: &Path) -> Result<bool> {
+    let file = fs::File::open(pdb_path)?;
+
+    let mut pdb =
+        PDB::open(file).with_context(|| format!("Failed to parse PDB file: {pdb_path:?}"))?;
+
+    let global_symbols = pdb.global_symbols()?;
+    let mut symbols = global_symbols.iter();
+
+    while let Some(symbol) = symbols.next()? {
+        if let Ok(SymbolData::EnvBlock(env_block)) = symbol.parse() {
+            for entry in &env_block.entries {
+                if entry.to_string().contains(CANARY) {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    Ok(false)
+}
@@ -0,0 +1,171 @@
+//! Public names for string constants, so references to them read as the string in a debugger
+
+use std::fmt::Write as _;
+
+use crate::options::StringLiteralNames;
+
+/// MSVC mangles at most this many bytes of a literal into its name
+const MAX_MANGLED_BYTES: usize = 32;
+/// Wide literals get twice the bytes, so the same number of characters
+const MAX_MANGLED_WIDE_BYTES: usize = 64;
+/// Characters of the literal kept in a plain name
+const MAX_PLAIN_CHARS: usize = 64;
+/// Characters with a single digit encoding, `?0` through `?9`
+const SPECIAL_CHARS: [u8; 10] = [
+    b',', b'/', b'\\', b':', b'.', b' ', b'\n', b'\t', b'\'', b'-',
+];
+
+/// Name for the literal whose code units (without terminator) are `data`, `width` bytes each
+pub(crate) fn literal_name(style: StringLiteralNames, data: &[u8], width: usize) -> Option<String> {
+    match style {
+        StringLiteralNames::Off => None,
+        StringLiteralNames::Mangled => Some(mangled_name(data, width)),
+        StringLiteralNames::Plain => Some(plain_name(data, width)),
+    }
+}
+
+/// The `??_C@_` name MSVC gives a string literal, which undecorates to `` `string' ``
+///
+/// Follows clang's MicrosoftMangle: kind, byte length including the terminator, JamCRC of those
+/// bytes, then the leading bytes with identifier characters kept and the rest escaped.
+fn mangled_name(data: &[u8], width: usize) -> String {
+    let wide = width == 2;
+    let mut bytes = data.to_vec();
+    bytes.resize(data.len() + width, 0);
+
+    let mut name = String::from("??_C@_");
+    name.push(if wide { '1' } else { '0' });
+    mangle_number(&mut name, bytes.len() as u64);
+    mangle_number(&mut name, jam_crc(&bytes) as u64);
+
+    let max = if wide {
+        MAX_MANGLED_WIDE_BYTES
+    } else {
+        MAX_MANGLED_BYTES
+    };
+    for i in 0..bytes.len().min(max) {
+        // Wide literals are mangled one big endian code unit at a time
+        let byte = match wide {
+            true => bytes[i ^ 1],
+            false => bytes[i],
+        };
+        mangle_byte(&mut name, byte);
+    }
+    name.push('@');
+    name
+}
+
+fn mangle_number(name: &mut String, value: u64) {
+    match value {
+        0 => name.push_str("A@"),
+        1..=10 => {
+            let _ = write!(name, "{}", value - 1);
+        }
+        _ => {
+            let digits = (64 - value.leading_zeros()).div_ceil(4);
+            for i in (0..digits).rev() {
+                name.push((b'A' + ((value >> (i * 4)) & 0xf) as u8) as char);
+            }
+            name.push('@');
+        }
+    }
+}
+
+fn mangle_byte(name: &mut String, byte: u8) {
+    if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$' {
+        name.push(byte as char);
+    } else if (byte & 0x7f).is_ascii_alphabetic() {
+        name.push('?');
+        name.push((byte & 0x7f) as char);
+    } else if let Some(index) = SPECIAL_CHARS.iter().position(|&c| c == byte) {
+        let _ = write!(name, "?{index}");
+    } else {
+        name.push_str("?$");
+        name.push((b'A' + (byte >> 4)) as char);
+        name.push((b'A' + (byte & 0xf)) as char);
+    }
+}
+
+/// CRC-32 without the final inversion, as MSVC uses for literal names
+fn jam_crc(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(u32::MAX, |crc, &b| {
+        (0..8).fold(crc ^ b as u32, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xedb8_8320,
+            _ => crc >> 1,
+        })
+    })
+}
+
+/// The literal itself in quotes, escaped and cut short
+fn plain_name(data: &[u8], width: usize) -> String {
+    let text: String = match width {
+        2 => char::decode_utf16(
+            data.chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]])),
+        )
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect(),
+        4 => data
+            .chunks_exact(4)
+            .map(|unit| u32::from_le_bytes(unit.try_into().unwrap()))
+            .map(|c| char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect(),
+        _ => String::from_utf8_lossy(data).into_owned(),
+    };
+
+    let mut name = String::from("\"");
+    let mut chars = text.chars();
+    for c in chars.by_ref().take(MAX_PLAIN_CHARS) {
+        name.extend(c.escape_debug());
+    }
+    if chars.next().is_some() {
+        name.push_str("...");
+    }
+    name.push('"');
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn mangles_narrow_literals() {
+        assert_eq!(mangled_name(b"", 1), "??_C@_00CNPNBAHC@?$AA@");
+        assert_eq!(mangled_name(b"hello", 1), "??_C@_05CJBACGMB@hello?$AA@");
+    }
+
+    #[test]
+    fn mangles_wide_literals() {
+        assert_eq!(mangled_name(&wide(""), 2), "??_C@_11LOCGONAA@?$AA?$AA@");
+        assert_eq!(
+            mangled_name(&wide("\n"), 2),
+            "??_C@_13LBAGMAIH@?$AA?6?$AA?$AA@"
+        );
+    }
+
+    #[test]
+    fn escapes_literal_bytes() {
+        assert_eq!(mangled_name(b"%s\n", 1), "??_C@_03OFAPEBGM@?$CFs?6?$AA@");
+        assert_eq!(mangled_name(b".", 1), "??_C@_01LFCBOECM@?4?$AA@");
+    }
+
+    #[test]
+    fn mangles_only_the_leading_bytes() {
+        let name = mangled_name(&[b'a'; 40], 1);
+        assert!(name.starts_with("??_C@_0CJ@"));
+        assert!(name.ends_with(&format!("@{}@", "a".repeat(MAX_MANGLED_BYTES))));
+    }
+
+    #[test]
+    fn quotes_plain_names() {
+        assert_eq!(plain_name(b"a\"b\n", 1), r#""a\"b\n""#);
+        assert_eq!(plain_name(&wide("hi"), 2), "\"hi\"");
+        let long = plain_name(&[b'x'; MAX_PLAIN_CHARS + 1], 1);
+        assert!(long.ends_with("x...\""));
+    }
+}
@@ -0,0 +1,363 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Everything needed to produce a PDB, independent of where it was collected from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Model {
+    pub info: PdbInfo,
+    /// COFF Machine field of the image, which selects the register numbering
+    pub machine: u16,
+    pub sections: Vec<Section>,
+    pub functions: Vec<Function>,
+    /// Functions the exception directory describes but the frontend did not find, see
+    /// [`crate::Options::include_unwind_functions`]
+    pub unwind_functions: Vec<Function>,
+    /// Raw contents of the exception directory, see [`crate::Options::exception_stream`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exception_data: Vec<u8>,
+    /// Named addresses without a known extent, emitted as public symbols only
+    pub publics: Vec<Public>,
+    /// Virtual function tables, emitted as typed data, see [`crate::Options::vtables`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub vtables: Vec<Vtable>,
+    /// Named types emitted to the TPI with their layout, see
+    /// [`crate::reader::ViewReader::types`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub types: Vec<TypeDefinition>,
+    /// Program that produced the model, see [`crate::reader::ViewReader::frontend`]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub frontend: String,
+    /// File name of the image, see [`crate::reader::ViewReader::image_name`]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub image_name: String,
+}
+
+impl Model {
+    /// Read a model previously written by [`Model::to_json`] or produced by an external tool
+    pub fn from_json(reader: impl Read) -> Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn to_json(&self, writer: impl Write) -> Result<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+/// Values that tie the PDB to its executable (from the CodeView debug directory entry)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PdbInfo {
+    pub age: u32,
+    pub timestamp: u32,
+    pub guid: [u8; 16],
+}
+
+impl PdbInfo {
+    /// GUID and age the way symbol servers key PDBs, `<GUID in hex, no dashes><age in hex>`
+    pub fn symbol_key(&self) -> String {
+        let g = &self.guid;
+        let data1 = u32::from_le_bytes([g[0], g[1], g[2], g[3]]);
+        let data2 = u16::from_le_bytes([g[4], g[5]]);
+        let data3 = u16::from_le_bytes([g[6], g[7]]);
+        let data4: String = g[8..].iter().map(|b| format!("{b:02X}")).collect();
+        format!("{data1:08X}{data2:04X}{data3:04X}{data4}{:X}", self.age)
+    }
+}
+
+const IMAGE_SCN_MEM_DISCARDABLE: u32 = 0x0200_0000;
+
+/// A PE section header, in the order it appears in the image
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Section {
+    pub name: String,
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub size_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub pointer_to_relocations: u32,
+    pub pointer_to_line_numbers: u32,
+    pub number_of_relocations: u16,
+    pub number_of_line_numbers: u16,
+    pub characteristics: u32,
+}
+
+impl Section {
+    /// Size the section occupies once mapped
+    ///
+    /// Some packers and hand-built images leave VirtualSize zero, in which case the loader maps
+    /// SizeOfRawData bytes instead.
+    pub fn size(&self) -> u32 {
+        match self.virtual_size {
+            0 => self.size_of_raw_data,
+            size => size,
+        }
+    }
+
+    /// RVA range covered by the section
+    pub fn range(&self) -> Range<u32> {
+        self.virtual_address..self.virtual_address.saturating_add(self.size())
+    }
+
+    /// Whether the loader may discard the section after initialization (IMAGE_SCN_MEM_DISCARDABLE)
+    pub fn is_discardable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_DISCARDABLE != 0
+    }
+
+    /// Section name as the fixed 8 byte, NUL padded field of the section header
+    pub fn raw_name(&self) -> [u8; 8] {
+        let mut name = [0u8; 8];
+        let len = self.name.len().min(name.len());
+        name[..len].copy_from_slice(&self.name.as_bytes()[..len]);
+        name
+    }
+}
+
+/// A function and the RVA ranges it occupies
+///
+/// Non-contiguous functions are emitted as one procedure per range, with every range after
+/// the first receiving a `_partN` suffix.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Function {
+    /// Shared with every other function of the same name, see [`crate::Interner`]
+    pub name: Arc<str>,
+    pub ranges: Vec<Range<u32>>,
+    #[serde(skip_serializing_if = "FunctionKind::is_procedure")]
+    pub kind: FunctionKind,
+    /// Frontend-defined grouping, used by [`crate::ModuleLayout::Component`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub component: Option<Arc<str>>,
+    /// Further names emitted as public symbols at the function's start
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<Arc<str>>,
+    /// Static library the function was recognized as coming from, or the `Import:<library>`
+    /// module of an import thunk, which becomes its module
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<Arc<str>>,
+    /// Where the parameters live on the first instruction, see
+    /// [`crate::Options::parameter_locations`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<Parameter>,
+    /// Length of the first instruction, which [`Function::parameters`] describe
+    #[serde(skip_serializing_if = "is_zero")]
+    pub entry_length: u32,
+    /// Indirect calls with a known target signature, see [`crate::Options::call_site_types`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub call_sites: Vec<CallSite>,
+    /// Calls to heap allocators, see [`crate::Options::heap_alloc_sites`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub heap_alloc_sites: Vec<HeapAllocSite>,
+    /// RVAs the function calls directly, sorted, see
+    /// [`crate::Options::cross_module_references`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<u32>,
+    /// Frame layout, see [`crate::Options::frame_info`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame: Option<Frame>,
+    /// RVAs of the function's basic blocks, see [`crate::Options::basic_block_labels`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<u32>,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// Which symbol record a function is emitted as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FunctionKind {
+    /// A regular function, emitted as S_GPROC32
+    #[default]
+    Procedure,
+    /// A stub that only jumps elsewhere, such as an import thunk, emitted as S_THUNK32
+    Thunk,
+}
+
+impl FunctionKind {
+    fn is_procedure(&self) -> bool {
+        *self == FunctionKind::Procedure
+    }
+}
+
+/// A function parameter and where the caller left it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Parameter {
+    pub name: Arc<str>,
+    /// Size of the value in bytes
+    pub size: u32,
+    pub kind: ValueKind,
+    pub location: Location,
+}
+
+/// An indirect call and the signature of what it calls
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CallSite {
+    /// RVA of the call instruction
+    pub rva: u32,
+    /// None for functions returning nothing
+    pub return_type: Option<ValueType>,
+    pub parameters: Vec<ValueType>,
+}
+
+/// How a function lays out its stack frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Frame {
+    /// Bytes of locals below the return address
+    pub size: u32,
+    /// Whether the prologue sets up a frame pointer (ebp, rbp or x29) that locals and
+    /// parameters are addressed from
+    pub frame_pointer: bool,
+    /// Whether the function checks a /GS stack cookie before returning
+    pub security_cookie: bool,
+}
+
+/// A call to a heap allocator
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeapAllocSite {
+    /// RVA of the call instruction
+    pub rva: u32,
+    /// Length of the call instruction
+    pub length: u16,
+    /// What the returned pointer was typed to point at, if known
+    pub allocated: Option<ValueType>,
+}
+
+/// Size and kind of a value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValueType {
+    /// Size in bytes
+    pub size: u32,
+    pub kind: ValueKind,
+}
+
+/// How a value is displayed, which together with its size picks a primitive type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueKind {
+    #[default]
+    Integer,
+    Float,
+    Pointer,
+}
+
+/// Where a value lives
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Location {
+    /// A register, by the name the frontend's architecture gives it
+    Register(String),
+    /// A stack slot, as an offset from the stack pointer on entry (the return address is at 0
+    /// on x86)
+    Stack(i64),
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Self::Stack(0)
+    }
+}
+
+/// A public symbol at a single RVA
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Public {
+    pub name: Arc<str>,
+    pub rva: u32,
+    /// Whether the address is code rather than data
+    pub function: bool,
+}
+
+/// A table of function pointers, such as a C++ vftable
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Vtable {
+    pub name: Arc<str>,
+    pub rva: u32,
+    /// Size of a pointer in the image, 4 or 8
+    pub slot_size: u32,
+    pub slot_count: u32,
+}
+
+/// A named type and its layout
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TypeDefinition {
+    pub name: Arc<str>,
+    pub kind: TypeDefinitionKind,
+}
+
+/// What a [`TypeDefinition`] defines
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TypeDefinitionKind {
+    /// Emitted as LF_STRUCTURE
+    Structure { size: u32, members: Vec<Member> },
+    /// Emitted as LF_UNION
+    Union { size: u32, members: Vec<Member> },
+    /// Emitted as LF_ENUM over an integer of `size` bytes
+    Enumeration { size: u32, members: Vec<Enumerator> },
+    /// Another name for a type, which CodeView only records as an S_UDT
+    Alias(TypeReference),
+}
+
+impl Default for TypeDefinitionKind {
+    fn default() -> Self {
+        Self::Alias(TypeReference::default())
+    }
+}
+
+/// A data member of a structure or union
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Member {
+    pub name: Arc<str>,
+    /// Offset in bytes from the start of the structure
+    pub offset: u32,
+    #[serde(rename = "type")]
+    pub ty: TypeReference,
+}
+
+/// A named value of an enumeration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Enumerator {
+    pub name: Arc<str>,
+    pub value: u64,
+}
+
+/// The type of a member or alias
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TypeReference {
+    /// A primitive, or an array of bytes of its size if CodeView has none, `void` if empty
+    Value(ValueType),
+    /// A [`TypeDefinition`] by name, or an opaque structure if the model has none
+    Named(Arc<str>),
+    /// A pointer of `size` bytes
+    Pointer {
+        target: Box<TypeReference>,
+        size: u32,
+    },
+    Array {
+        element: Box<TypeReference>,
+        count: u32,
+    },
+}
+
+impl Default for TypeReference {
+    fn default() -> Self {
+        Self::Value(ValueType::default())
+    }
+}
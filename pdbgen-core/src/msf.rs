@@ -0,0 +1,733 @@
+//! Just enough of the MSF container format to re-page, patch and extend a PDB committed by
+//! pdb-sdk
+//!
+//! An MSF is a sequence of fixed size blocks. Block 0 holds the superblock, blocks 1 and 2 of
+//! every `block_size` blocks are reserved for the free block map, and a stream directory lists
+//! the size and blocks of every stream. Larger blocks raise the limits on file and directory
+//! size that very large PDBs run into at the default 4K.
+
+use anyhow::{Context as _, Result, bail, ensure};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 32] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0";
+
+/// Page size pdb-sdk commits with
+pub const DEFAULT_PAGE_SIZE: u32 = 4096;
+
+/// Page sizes accepted by current Microsoft tooling (link.exe /PDBPAGESIZE)
+pub const PAGE_SIZES: [u32; 4] = [4096, 8192, 16384, 32768];
+
+/// Marks a stream slot that exists in the directory but has no data
+const NIL_STREAM: u32 = u32::MAX;
+
+struct SuperBlock {
+    block_size: u32,
+    num_blocks: u32,
+    num_directory_bytes: u32,
+    block_map_addr: u32,
+}
+
+struct StreamEntry {
+    /// `None` for nil streams
+    size: Option<u32>,
+    blocks: Vec<u32>,
+}
+
+/// Rewrite the MSF at `path` in place to use `page_size` byte blocks
+pub fn repage(path: &Path, page_size: u32) -> Result<()> {
+    let mut msf = Editor::open(path)?;
+    msf.set_page_size(page_size)?;
+    msf.commit()
+}
+
+/// Size in bytes of stream `stream` of the MSF at `path`, 0 for nil streams
+pub fn stream_len(path: &Path, stream: usize) -> Result<u32> {
+    Editor::open(path)?.stream_len(stream)
+}
+
+/// Read `len` bytes of stream `stream` at `offset` from the MSF at `path`
+pub fn read_stream(path: &Path, stream: usize, offset: u32, len: usize) -> Result<Vec<u8>> {
+    Editor::open(path)?.read_stream(stream, offset, len)
+}
+
+/// Changes to a committed MSF, queued up and applied by [`Editor::commit`] so the file is
+/// copied at most once however many streams are added, replaced or patched
+///
+/// Reads see the queued changes. Patches alone are written in place without a copy.
+pub struct Editor {
+    path: PathBuf,
+    superblock: SuperBlock,
+    streams: Vec<StreamEntry>,
+    page_size: u32,
+    /// New contents of existing streams
+    replaced: BTreeMap<usize, Vec<u8>>,
+    /// Streams to add after the existing ones
+    appended: Vec<Vec<u8>>,
+    /// Byte ranges to overwrite in streams that are otherwise kept, in the order given
+    patches: BTreeMap<usize, Vec<(u32, Vec<u8>)>>,
+}
+
+impl Editor {
+    /// Read the stream directory of the MSF at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = BufReader::new(
+            fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?,
+        );
+        let superblock = read_superblock(&mut file)?;
+        let streams = read_directory(&mut file, &superblock)?;
+        Ok(Self {
+            path: path.to_owned(),
+            page_size: superblock.block_size,
+            superblock,
+            streams,
+            replaced: BTreeMap::new(),
+            appended: Vec::new(),
+            patches: BTreeMap::new(),
+        })
+    }
+
+    /// Number of streams, including the ones added so far
+    pub fn num_streams(&self) -> usize {
+        self.streams.len() + self.appended.len()
+    }
+
+    /// Contents of `stream` if it was replaced or added
+    fn pending(&self, stream: usize) -> Option<&Vec<u8>> {
+        match stream.checked_sub(self.streams.len()) {
+            Some(added) => self.appended.get(added),
+            None => self.replaced.get(&stream),
+        }
+    }
+
+    fn pending_mut(&mut self, stream: usize) -> Option<&mut Vec<u8>> {
+        match stream.checked_sub(self.streams.len()) {
+            Some(added) => self.appended.get_mut(added),
+            None => self.replaced.get_mut(&stream),
+        }
+    }
+
+    /// Size in bytes of stream `stream`, 0 for nil streams
+    pub fn stream_len(&self, stream: usize) -> Result<u32> {
+        if let Some(data) = self.pending(stream) {
+            return Ok(data.len() as u32);
+        }
+        let entry = stream_range(&self.streams, stream, 0, 0)?;
+        Ok(entry.size.unwrap_or(0))
+    }
+
+    /// Read `len` bytes of stream `stream` at `offset`
+    pub fn read_stream(&self, stream: usize, offset: u32, len: usize) -> Result<Vec<u8>> {
+        if let Some(data) = self.pending(stream) {
+            return data
+                .get(offset as usize..)
+                .and_then(|rest| rest.get(..len))
+                .map(<[u8]>::to_vec)
+                .with_context(|| {
+                    format!("{len} bytes at {offset} run past the end of stream {stream}")
+                });
+        }
+
+        let entry = stream_range(&self.streams, stream, offset, len)?;
+        let mut file = BufReader::new(fs::File::open(&self.path)?);
+        let mut data = vec![0; len];
+        let block_size = self.superblock.block_size as usize;
+        let mut position = offset as usize;
+        let mut filled = 0;
+        while filled < len {
+            let block = entry.blocks[position / block_size];
+            let within = position % block_size;
+            let chunk = (len - filled).min(block_size - within);
+            file.seek(SeekFrom::Start(
+                block as u64 * block_size as u64 + within as u64,
+            ))?;
+            file.read_exact(&mut data[filled..filled + chunk])?;
+            position += chunk;
+            filled += chunk;
+        }
+        if let Some(patches) = self.patches.get(&stream) {
+            overlay(&mut data, offset as u64, patches);
+        }
+        Ok(data)
+    }
+
+    /// Add a stream holding `data` after the existing ones, returning its index
+    pub fn add_stream(&mut self, data: Vec<u8>) -> Result<u16> {
+        // Stream indices are 16 bit everywhere the DBI refers to them
+        let index = u16::try_from(self.num_streams())
+            .context("MSF has too many streams to reference another")?;
+        ensure!(
+            u32::try_from(data.len()).is_ok(),
+            "stream too large for an MSF"
+        );
+        self.appended.push(data);
+        Ok(index)
+    }
+
+    /// Replace the contents of stream `stream` with `data`, which may change its size
+    pub fn replace_stream(&mut self, stream: usize, data: Vec<u8>) -> Result<()> {
+        ensure!(
+            u32::try_from(data.len()).is_ok(),
+            "stream too large for an MSF"
+        );
+        if let Some(pending) = self.pending_mut(stream) {
+            *pending = data;
+            return Ok(());
+        }
+        ensure!(stream < self.streams.len(), "MSF has no stream {stream}");
+        self.patches.remove(&stream);
+        self.replaced.insert(stream, data);
+        Ok(())
+    }
+
+    /// Overwrite `data.len()` bytes of stream `stream` at `offset`
+    pub fn patch_stream(&mut self, stream: usize, offset: u32, data: &[u8]) -> Result<()> {
+        if let Some(pending) = self.pending_mut(stream) {
+            let target = pending
+                .get_mut(offset as usize..)
+                .and_then(|rest| rest.get_mut(..data.len()))
+                .with_context(|| {
+                    format!(
+                        "{} bytes at {offset} run past the end of stream {stream}",
+                        data.len()
+                    )
+                })?;
+            target.copy_from_slice(data);
+            return Ok(());
+        }
+        stream_range(&self.streams, stream, offset, data.len())?;
+        self.patches
+            .entry(stream)
+            .or_default()
+            .push((offset, data.to_vec()));
+        Ok(())
+    }
+
+    /// Write the MSF with `page_size` byte blocks
+    pub fn set_page_size(&mut self, page_size: u32) -> Result<()> {
+        ensure!(
+            PAGE_SIZES.contains(&page_size),
+            "unsupported MSF page size {page_size}, expected one of {PAGE_SIZES:?}"
+        );
+        self.page_size = page_size;
+        Ok(())
+    }
+
+    /// Apply every queued change to the file
+    pub fn commit(self) -> Result<()> {
+        if self.page_size == self.superblock.block_size
+            && self.replaced.is_empty()
+            && self.appended.is_empty()
+        {
+            return self.patch_in_place();
+        }
+        self.rewrite()
+    }
+
+    /// Overwrite the patched bytes where they are, since no stream moves
+    fn patch_in_place(self) -> Result<()> {
+        if self.patches.is_empty() {
+            return Ok(());
+        }
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.path)?;
+        let block_size = self.superblock.block_size as usize;
+        for (&stream, patches) in &self.patches {
+            let entry = &self.streams[stream];
+            for (offset, data) in patches {
+                let mut position = *offset as usize;
+                let mut data = data.as_slice();
+                while !data.is_empty() {
+                    let block = entry.blocks[position / block_size];
+                    let within = position % block_size;
+                    let len = data.len().min(block_size - within);
+                    file.seek(SeekFrom::Start(
+                        block as u64 * block_size as u64 + within as u64,
+                    ))?;
+                    file.write_all(&data[..len])?;
+                    position += len;
+                    data = &data[len..];
+                }
+            }
+        }
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Copy the MSF into a new file with every change applied, then move it over the original
+    fn rewrite(self) -> Result<()> {
+        let mut input = BufReader::new(fs::File::open(&self.path)?);
+
+        let mut out_path = self.path.as_os_str().to_owned();
+        out_path.push(".rewrite");
+        let out_path = PathBuf::from(out_path);
+
+        let result = (|| {
+            let output = BufWriter::new(
+                fs::File::create(&out_path)
+                    .with_context(|| format!("failed to create {}", out_path.display()))?,
+            );
+            let mut writer = MsfWriter::new(output, self.page_size)?;
+
+            let mut new_streams = Vec::with_capacity(self.num_streams());
+            for (index, stream) in self.streams.iter().enumerate() {
+                if let Some(data) = self.replaced.get(&index) {
+                    new_streams.push(writer.write_stream(data)?);
+                    continue;
+                }
+                let patches = self.patches.get(&index).map_or(&[][..], Vec::as_slice);
+                let blocks =
+                    copy_stream(&mut input, &self.superblock, stream, patches, &mut writer)
+                        .with_context(|| format!("failed to copy stream {index}"))?;
+                new_streams.push(StreamEntry {
+                    size: stream.size,
+                    blocks,
+                });
+            }
+            for data in &self.appended {
+                new_streams.push(writer.write_stream(data)?);
+            }
+
+            writer.finish(&new_streams)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&out_path);
+        }
+        result?;
+
+        drop(input);
+        fs::rename(&out_path, &self.path).with_context(|| {
+            format!(
+                "failed to move the rewritten MSF to {}",
+                self.path.display()
+            )
+        })
+    }
+}
+
+/// Apply the parts of `patches` that overlap `data`, which holds stream bytes from `at`
+fn overlay(data: &mut [u8], at: u64, patches: &[(u32, Vec<u8>)]) {
+    let end = at + data.len() as u64;
+    for (offset, bytes) in patches {
+        let offset = *offset as u64;
+        let start = offset.max(at);
+        let stop = (offset + bytes.len() as u64).min(end);
+        if start < stop {
+            data[(start - at) as usize..(stop - at) as usize]
+                .copy_from_slice(&bytes[(start - offset) as usize..(stop - offset) as usize]);
+        }
+    }
+}
+
+/// The directory entry of `stream`, checking that `len` bytes at `offset` lie inside it
+fn stream_range(
+    streams: &[StreamEntry],
+    stream: usize,
+    offset: u32,
+    len: usize,
+) -> Result<&StreamEntry> {
+    let entry = streams
+        .get(stream)
+        .with_context(|| format!("MSF has no stream {stream}"))?;
+    let size = entry.size.unwrap_or(0);
+    ensure!(
+        offset as u64 + len as u64 <= size as u64,
+        "{len} bytes at {offset} run past the end of stream {stream} ({size} bytes)"
+    );
+    Ok(entry)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_superblock(reader: &mut (impl Read + Seek)) -> Result<SuperBlock> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut magic = [0; 32];
+    reader.read_exact(&mut magic)?;
+    ensure!(&magic == MAGIC, "not an MSF 7.00 file");
+
+    let block_size = read_u32(reader)?;
+    let _free_block_map_block = read_u32(reader)?;
+    let num_blocks = read_u32(reader)?;
+    let num_directory_bytes = read_u32(reader)?;
+    let _unknown = read_u32(reader)?;
+    let block_map_addr = read_u32(reader)?;
+
+    ensure!(
+        block_size.is_power_of_two() && block_size >= 512,
+        "invalid MSF block size {block_size}"
+    );
+
+    Ok(SuperBlock {
+        block_size,
+        num_blocks,
+        num_directory_bytes,
+        block_map_addr,
+    })
+}
+
+fn read_directory(
+    reader: &mut (impl Read + Seek),
+    superblock: &SuperBlock,
+) -> Result<Vec<StreamEntry>> {
+    let block_size = superblock.block_size as u64;
+    let num_directory_blocks = superblock
+        .num_directory_bytes
+        .div_ceil(superblock.block_size);
+
+    reader.seek(SeekFrom::Start(
+        superblock.block_map_addr as u64 * block_size,
+    ))?;
+    let directory_blocks = (0..num_directory_blocks)
+        .map(|_| read_u32(reader))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut directory = Vec::with_capacity(superblock.num_directory_bytes as usize);
+    for block in directory_blocks {
+        ensure!(
+            block < superblock.num_blocks,
+            "directory block {block} out of range"
+        );
+        let len = (superblock.num_directory_bytes as usize - directory.len())
+            .min(superblock.block_size as usize);
+        reader.seek(SeekFrom::Start(block as u64 * block_size))?;
+        let start = directory.len();
+        directory.resize(start + len, 0);
+        reader.read_exact(&mut directory[start..])?;
+    }
+
+    let mut directory = directory.as_slice();
+    let num_streams = read_u32(&mut directory)?;
+    let sizes = (0..num_streams)
+        .map(|_| read_u32(&mut directory))
+        .collect::<Result<Vec<_>>>()?;
+
+    sizes
+        .into_iter()
+        .map(|size| {
+            let size = (size != NIL_STREAM).then_some(size);
+            let num_blocks = size.unwrap_or(0).div_ceil(superblock.block_size);
+            let blocks = (0..num_blocks)
+                .map(|_| read_u32(&mut directory))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(StreamEntry { size, blocks })
+        })
+        .collect()
+}
+
+/// Copy one stream from the source MSF into `writer` with `patches` applied, returning its
+/// blocks in the new file
+fn copy_stream<W: Write + Seek>(
+    input: &mut (impl Read + Seek),
+    superblock: &SuperBlock,
+    stream: &StreamEntry,
+    patches: &[(u32, Vec<u8>)],
+    writer: &mut MsfWriter<W>,
+) -> Result<Vec<u32>> {
+    let Some(size) = stream.size else {
+        return Ok(Vec::new());
+    };
+
+    let source_block_size = superblock.block_size as usize;
+    let dest_block_size = writer.block_size as usize;
+
+    let mut remaining = size as usize;
+    let mut block = vec![0; source_block_size];
+    let mut pending = Vec::with_capacity(source_block_size + dest_block_size);
+    let mut blocks = Vec::new();
+
+    for &source_block in &stream.blocks {
+        if remaining == 0 {
+            break;
+        }
+        ensure!(
+            source_block < superblock.num_blocks,
+            "stream block {source_block} out of range"
+        );
+        let len = remaining.min(source_block_size);
+        input.seek(SeekFrom::Start(
+            source_block as u64 * superblock.block_size as u64,
+        ))?;
+        input.read_exact(&mut block[..len])?;
+        overlay(
+            &mut block[..len],
+            (size as usize - remaining) as u64,
+            patches,
+        );
+        remaining -= len;
+
+        pending.extend_from_slice(&block[..len]);
+        while pending.len() >= dest_block_size {
+            blocks.push(writer.write_block(&pending[..dest_block_size])?);
+            pending.drain(..dest_block_size);
+        }
+    }
+    if remaining != 0 {
+        bail!("stream is {remaining} bytes shorter than its recorded size");
+    }
+    if !pending.is_empty() {
+        blocks.push(writer.write_block(&pending)?);
+    }
+
+    Ok(blocks)
+}
+
+/// Writes blocks sequentially, skipping over the free block map slots of every interval
+struct MsfWriter<W> {
+    output: W,
+    block_size: u32,
+    num_blocks: u32,
+}
+
+impl<W: Write + Seek> MsfWriter<W> {
+    fn new(output: W, block_size: u32) -> Result<Self> {
+        let mut writer = Self {
+            output,
+            block_size,
+            num_blocks: 0,
+        };
+        // Superblock and the first pair of free block maps, filled in by finish
+        for _ in 0..3 {
+            writer.write_raw_block(&[])?;
+        }
+        Ok(writer)
+    }
+
+    fn is_fpm_block(&self, index: u32) -> bool {
+        matches!(index % self.block_size, 1 | 2)
+    }
+
+    fn write_raw_block(&mut self, data: &[u8]) -> Result<()> {
+        self.output.write_all(data)?;
+        let padding = self.block_size as usize - data.len();
+        self.output.write_all(&vec![0; padding])?;
+        self.num_blocks = self
+            .num_blocks
+            .checked_add(1)
+            .context("MSF exceeds the maximum block count")?;
+        Ok(())
+    }
+
+    /// Write up to one block of data, returning the index it was placed at
+    fn write_block(&mut self, data: &[u8]) -> Result<u32> {
+        while self.is_fpm_block(self.num_blocks) {
+            self.write_raw_block(&[])?;
+        }
+        let index = self.num_blocks;
+        self.write_raw_block(data)?;
+        Ok(index)
+    }
+
+    /// Write a whole stream, returning its directory entry
+    fn write_stream(&mut self, data: &[u8]) -> Result<StreamEntry> {
+        let size = u32::try_from(data.len()).context("stream too large for an MSF")?;
+        let blocks = data
+            .chunks(self.block_size as usize)
+            .map(|chunk| self.write_block(chunk))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(StreamEntry {
+            size: Some(size),
+            blocks,
+        })
+    }
+
+    fn finish(mut self, streams: &[StreamEntry]) -> Result<()> {
+        let mut directory = Vec::new();
+        directory.extend((streams.len() as u32).to_le_bytes());
+        for stream in streams {
+            directory.extend(stream.size.unwrap_or(NIL_STREAM).to_le_bytes());
+        }
+        for stream in streams {
+            for block in &stream.blocks {
+                directory.extend(block.to_le_bytes());
+            }
+        }
+
+        let directory_blocks = directory
+            .chunks(self.block_size as usize)
+            .map(|chunk| self.write_block(chunk))
+            .collect::<Result<Vec<_>>>()?;
+        let block_map: Vec<u8> = directory_blocks
+            .iter()
+            .flat_map(|b| b.to_le_bytes())
+            .collect();
+        ensure!(
+            block_map.len() <= self.block_size as usize,
+            "stream directory does not fit a {} byte page, use a larger page size",
+            self.block_size
+        );
+        let block_map_addr = self.write_block(&block_map)?;
+
+        // Every interval that has started needs its free block map slots present in the file
+        while self.is_fpm_block(self.num_blocks) {
+            self.write_raw_block(&[])?;
+        }
+        let num_blocks = self.num_blocks;
+        let block_size = self.block_size as u64;
+
+        // One bit per block, set when free. Everything we wrote is in use.
+        let intervals = num_blocks.div_ceil(self.block_size) as usize;
+        let mut fpm = vec![0xffu8; intervals * self.block_size as usize];
+        fpm[..num_blocks as usize / 8].fill(0);
+        if num_blocks % 8 != 0 {
+            fpm[num_blocks as usize / 8] = 0xff << (num_blocks % 8);
+        }
+        for (interval, chunk) in fpm.chunks(self.block_size as usize).enumerate() {
+            let first = interval as u64 * block_size;
+            for fpm_block in [first + 1, first + 2] {
+                self.output.seek(SeekFrom::Start(fpm_block * block_size))?;
+                self.output.write_all(chunk)?;
+            }
+        }
+
+        self.output.seek(SeekFrom::Start(0))?;
+        self.output.write_all(MAGIC)?;
+        for value in [
+            self.block_size,
+            1, // free block map in use
+            num_blocks,
+            directory.len() as u32,
+            0,
+            block_map_addr,
+        ] {
+            self.output.write_all(&value.to_le_bytes())?;
+        }
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes of a stream that differ from one block and stream to the next
+    fn pattern(seed: u8, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u8).wrapping_mul(7).wrapping_add(seed))
+            .collect()
+    }
+
+    /// Streams that start, end and cross 4K blocks at different points, with a nil stream
+    fn streams() -> Vec<Option<Vec<u8>>> {
+        vec![
+            Some(pattern(1, 5000)),
+            Some(pattern(2, 100)),
+            None,
+            Some(Vec::new()),
+            Some(pattern(3, 70000)),
+        ]
+    }
+
+    /// Write an MSF holding `streams` to a file in the temp directory
+    fn write_msf(name: &str, page_size: u32, streams: &[Option<Vec<u8>>]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("pdbgen-msf-{}-{name}.pdb", std::process::id()));
+        let output = BufWriter::new(fs::File::create(&path).unwrap());
+        let mut writer = MsfWriter::new(output, page_size).unwrap();
+        let entries: Vec<_> = streams
+            .iter()
+            .map(|stream| match stream {
+                Some(data) => writer.write_stream(data).unwrap(),
+                None => StreamEntry {
+                    size: None,
+                    blocks: Vec::new(),
+                },
+            })
+            .collect();
+        writer.finish(&entries).unwrap();
+        path
+    }
+
+    /// Page size and every stream of the MSF at `path`
+    fn read_msf(path: &Path) -> (u32, Vec<Option<Vec<u8>>>) {
+        let msf = Editor::open(path).unwrap();
+        let streams = (0..msf.num_streams())
+            .map(|stream| {
+                let size = msf.streams[stream].size?;
+                Some(msf.read_stream(stream, 0, size as usize).unwrap())
+            })
+            .collect();
+        (msf.superblock.block_size, streams)
+    }
+
+    #[test]
+    fn repages_between_every_page_size() {
+        for from in PAGE_SIZES {
+            for to in PAGE_SIZES {
+                let path = write_msf(&format!("repage-{from}-{to}"), from, &streams());
+                repage(&path, to).unwrap();
+                assert_eq!(
+                    read_msf(&path),
+                    (to, streams()),
+                    "{from} to {to} byte pages"
+                );
+                fs::remove_file(&path).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn applies_edits_in_one_pass() {
+        let path = write_msf("edits", DEFAULT_PAGE_SIZE, &streams());
+        let mut msf = Editor::open(&path).unwrap();
+        // Across the first block boundary, then partly over the first patch
+        msf.patch_stream(0, 4090, &[0xaa; 10]).unwrap();
+        msf.patch_stream(0, 4098, &[0xbb; 4]).unwrap();
+        msf.replace_stream(1, pattern(4, 9000)).unwrap();
+        assert_eq!(msf.add_stream(pattern(5, 10)).unwrap(), 5);
+        msf.patch_stream(5, 8, &[0xcc; 2]).unwrap();
+        msf.set_page_size(8192).unwrap();
+        assert_eq!(msf.stream_len(1).unwrap(), 9000);
+        assert_eq!(
+            msf.read_stream(0, 4096, 4).unwrap(),
+            [0xaa, 0xaa, 0xbb, 0xbb]
+        );
+        msf.commit().unwrap();
+
+        let mut expected = streams();
+        let first = expected[0].as_mut().unwrap();
+        first[4090..4100].fill(0xaa);
+        first[4098..4102].fill(0xbb);
+        expected[1] = Some(pattern(4, 9000));
+        let mut added = pattern(5, 10);
+        added[8..].fill(0xcc);
+        expected.push(Some(added));
+        assert_eq!(read_msf(&path), (8192, expected));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn patches_in_place() {
+        let path = write_msf("patch", DEFAULT_PAGE_SIZE, &streams());
+        let len = fs::metadata(&path).unwrap().len();
+        let mut msf = Editor::open(&path).unwrap();
+        msf.patch_stream(4, 65530, &[0xdd; 8]).unwrap();
+        msf.commit().unwrap();
+
+        let mut expected = streams();
+        expected[4].as_mut().unwrap()[65530..65538].fill(0xdd);
+        assert_eq!(read_msf(&path), (DEFAULT_PAGE_SIZE, expected));
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_patches_past_the_end() {
+        let path = write_msf("bounds", DEFAULT_PAGE_SIZE, &streams());
+        let mut msf = Editor::open(&path).unwrap();
+        assert!(msf.patch_stream(1, 98, &[0; 4]).is_err());
+        assert!(msf.patch_stream(2, 0, &[0]).is_err());
+        assert!(msf.replace_stream(9, Vec::new()).is_err());
+        assert!(msf.set_page_size(2048).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
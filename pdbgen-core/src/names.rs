@@ -0,0 +1,217 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use crate::hash::fnv1a;
+use crate::model::Function;
+use crate::options::{DuplicateNames, LongNamePolicy, NameSanitization};
+use crate::{Options, Report};
+
+/// How many colliding names are listed by [`DuplicateNames::Report`]
+const REPORTED_DUPLICATES: usize = 10;
+
+/// Applies [`Options::duplicate_names`], [`Options::name_sanitization`] and
+/// [`Options::long_names`] to every emitted symbol name
+pub(crate) struct NameRules {
+    /// Names shared by functions at different addresses, only filled in when suffixing
+    duplicates: HashSet<Arc<str>>,
+    sanitization: NameSanitization,
+    policy: LongNamePolicy,
+    max_len: usize,
+    sanitized: usize,
+    shortened: usize,
+}
+
+impl NameRules {
+    pub(crate) fn new(options: &Options, functions: &[&Function], report: &mut Report) -> Self {
+        let duplicates = match options.duplicate_names {
+            DuplicateNames::Keep => HashSet::new(),
+            policy => {
+                let duplicates = find_duplicates(functions);
+                if policy == DuplicateNames::Report && !duplicates.is_empty() {
+                    let mut examples: Vec<_> = duplicates.iter().map(|n| &**n).collect();
+                    examples.sort_unstable();
+                    examples.truncate(REPORTED_DUPLICATES);
+                    report.warn(format!(
+                        "{} name(s) are shared by functions at different addresses, e.g. {}",
+                        duplicates.len(),
+                        examples.join(", ")
+                    ));
+                }
+                match policy {
+                    DuplicateNames::Suffix => duplicates,
+                    _ => HashSet::new(),
+                }
+            }
+        };
+
+        Self {
+            duplicates,
+            sanitization: options.name_sanitization,
+            policy: options.long_names,
+            max_len: options.max_name_length,
+            sanitized: 0,
+            shortened: 0,
+        }
+    }
+
+    /// Name of `function` before part suffixes, with its RVA appended if the name collides
+    pub(crate) fn base_name<'a>(&self, function: &'a Function) -> Cow<'a, str> {
+        match function.ranges.first() {
+            Some(range) if self.duplicates.contains(&function.name) => {
+                Cow::Owned(format!("{}@{:x}", function.name, range.start))
+            }
+            _ => Cow::Borrowed(&function.name),
+        }
+    }
+
+    pub(crate) fn apply<'a>(&mut self, name: Cow<'a, str>) -> Cow<'a, str> {
+        let name = self.sanitize(name);
+        self.shorten(name)
+    }
+
+    fn sanitize<'a>(&mut self, name: Cow<'a, str>) -> Cow<'a, str> {
+        // Names are stored NUL terminated, so an embedded NUL would cut them short in any mode
+        let needs_rewrite = |c: char| match self.sanitization {
+            NameSanitization::Raw => c == '\0',
+            _ => c.is_control() || c == char::REPLACEMENT_CHARACTER,
+        };
+        if !name.chars().any(needs_rewrite) {
+            return name;
+        }
+        self.sanitized += 1;
+
+        let mut sanitized = String::with_capacity(name.len());
+        for c in name.chars() {
+            if !needs_rewrite(c) {
+                sanitized.push(c);
+            } else if self.sanitization == NameSanitization::Replace {
+                sanitized.push('_');
+            } else {
+                let _ = write!(sanitized, "{}", c.escape_unicode());
+            }
+        }
+        Cow::Owned(sanitized)
+    }
+
+    fn shorten<'a>(&mut self, name: Cow<'a, str>) -> Cow<'a, str> {
+        if self.policy == LongNamePolicy::Keep || self.max_len == 0 || name.len() <= self.max_len {
+            return name;
+        }
+        self.shortened += 1;
+        match self.policy {
+            LongNamePolicy::Keep => unreachable!(),
+            LongNamePolicy::Truncate => Cow::Owned(truncate(&name, self.max_len).to_string()),
+            LongNamePolicy::Hash => {
+                let mut suffix = format!("~{:016x}", fnv1a(name.as_bytes()));
+                // Below the suffix's own length only part of the hash fits
+                suffix.truncate(self.max_len);
+                let prefix = truncate(&name, self.max_len - suffix.len());
+                Cow::Owned(format!("{prefix}{suffix}"))
+            }
+        }
+    }
+
+    pub(crate) fn finish(&self, report: &mut Report) {
+        if self.sanitized > 0 {
+            report.warn(format!(
+                "Rewrote {} symbol name(s) containing control or undecodable characters",
+                self.sanitized
+            ));
+        }
+        if self.shortened > 0 {
+            report.warn(format!(
+                "Shortened {} symbol name(s) longer than {} bytes",
+                self.shortened, self.max_len
+            ));
+        }
+    }
+}
+
+/// Names used by more than one function start
+fn find_duplicates(functions: &[&Function]) -> HashSet<Arc<str>> {
+    let mut starts: HashMap<&Arc<str>, u32> = HashMap::new();
+    let mut duplicates = HashSet::new();
+    for function in functions {
+        let Some(start) = function.ranges.first().map(|r| r.start) else {
+            continue;
+        };
+        match starts.get(&function.name) {
+            Some(&first) if first != start => {
+                duplicates.insert(function.name.clone());
+            }
+            Some(_) => {}
+            None => {
+                starts.insert(&function.name, start);
+            }
+        }
+    }
+    duplicates
+}
+
+/// Longest prefix of `name` that fits in `len` bytes without splitting a character
+fn truncate(name: &str, len: usize) -> &str {
+    let mut end = len.min(name.len());
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(policy: LongNamePolicy, max_len: usize) -> NameRules {
+        NameRules {
+            duplicates: HashSet::new(),
+            sanitization: NameSanitization::Escape,
+            policy,
+            max_len,
+            sanitized: 0,
+            shortened: 0,
+        }
+    }
+
+    #[test]
+    fn truncates_on_character_boundaries() {
+        let mut rules = rules(LongNamePolicy::Truncate, 5);
+        assert_eq!(rules.shorten("abcdéf".into()), "abcd");
+        assert_eq!(rules.shorten("abc".into()), "abc");
+        assert_eq!(rules.shortened, 1);
+    }
+
+    #[test]
+    fn hashes_long_names() {
+        let long = "a".repeat(40);
+        let mut rules = rules(LongNamePolicy::Hash, 24);
+        let short = rules.shorten(long.as_str().into());
+        assert_eq!(short.len(), 24);
+        assert!(short.starts_with("aaaaaaa~"));
+        // Names sharing the kept prefix still differ
+        let other = rules.shorten(format!("{long}b").into());
+        assert_ne!(short, other);
+    }
+
+    #[test]
+    fn hashes_within_very_short_limits() {
+        let mut rules = rules(LongNamePolicy::Hash, 8);
+        let short = rules.shorten("a".repeat(40).into());
+        assert_eq!(short.len(), 8);
+        assert!(short.starts_with('~'));
+    }
+
+    #[test]
+    fn keeps_names_without_a_limit() {
+        let long = "a".repeat(4000);
+        assert_eq!(
+            rules(LongNamePolicy::Keep, 24).shorten(long.as_str().into()),
+            long
+        );
+        assert_eq!(
+            rules(LongNamePolicy::Hash, 0).shorten(long.as_str().into()),
+            long
+        );
+    }
+}
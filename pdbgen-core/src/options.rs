@@ -0,0 +1,444 @@
+use serde::{Deserialize, Serialize};
+
+use crate::LayoutRange;
+
+/// Knobs controlling how a [`crate::Model`] is collected and turned into a PDB
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    /// How functions are grouped into modules
+    pub module_layout: ModuleLayout,
+    /// Put functions recognized as C runtime or static library code into modules of their own,
+    /// whatever the layout, so they are kept apart from application code
+    pub library_modules: bool,
+    /// Put import thunks the frontend links to an external library into an `Import:<library>`
+    /// module per library, as the MSVC linker does, whatever the layout other than single
+    pub import_modules: bool,
+    /// Record the functions each module calls in other modules, such as the thunks of an
+    /// `Import:<library>` module, in the cross-scope import and export subsections of the module
+    /// streams, for tools tracing calls between modules
+    pub cross_module_references: bool,
+    /// Trim trailing int3 filler from function ranges while collecting, so code sizes end at the
+    /// last real instruction
+    pub trim_padding: bool,
+    /// Append section headers for sections created in the analysis that the PE header lacks
+    pub user_sections: bool,
+    /// Append a synthetic `.overlay` section header covering functions past the last section, so
+    /// code in overlays or regions mapped by hand can still be addressed
+    pub overlay_section: bool,
+    /// Emit placeholder functions for RUNTIME_FUNCTION entries the frontend did not find, so
+    /// stack walks through that code still land on a symbol
+    pub include_unwind_functions: bool,
+    /// Copy the image's RUNTIME_FUNCTION table into the exception stream the DBI optional debug
+    /// header points at, for consumers that read unwind data from the PDB
+    pub exception_stream: bool,
+    /// Kinds of symbol exported, functions of other kinds are left out and data symbols of a
+    /// listed kind become public symbols
+    pub symbol_types: Vec<SymbolType>,
+    /// Emit vtables the analysis named as typed data with a label per slot naming the function
+    /// it dispatches to
+    pub vtables: bool,
+    /// Describe where each parameter lives on a function's first instruction, so a debugger
+    /// stopped at the entry shows argument values
+    pub parameter_locations: bool,
+    /// Attach the signature of the target to indirect calls the analysis typed, so a debugger
+    /// stopped at the call can show the arguments
+    pub call_site_types: bool,
+    /// Describe each function's frame (size, frame pointer, stack cookie) with S_FRAMEPROC, so
+    /// debuggers walk hand-written or hot-patched code the unwind data doesn't cover reliably
+    pub frame_info: bool,
+    /// Emit a label at every basic block (`func$bb_<rva>`), for coverage and trace tools that
+    /// resolve addresses through PDB symbols
+    pub basic_block_labels: bool,
+    /// Fill in what analysis lacks from debug info the frontend imported earlier (a partial
+    /// vendor PDB, DWARF), such as parameter names and named data, and export the types it
+    /// defines
+    pub debug_info: bool,
+    /// Look up types the analysis only references by name in the frontend's type archives, so
+    /// parameters and call sites typed from a shared type library get their size and kind, and
+    /// export the archive types functions and data refer to with their layout
+    pub type_archives: bool,
+    /// Start every module with an S_ENVBLOCK recording the working directory, the generating
+    /// program and the options, as build provenance tools read from compiler output
+    pub env_block: bool,
+    /// Add a `pdbgen/manifest` named stream recording the generator and frontend versions, the
+    /// time of generation and the options, so a PDB can be traced back to the run that made it
+    pub manifest_stream: bool,
+    /// Mark calls to [`Options::heap_allocators`] with heap allocation site records, for heap
+    /// diagnostics tools that attribute allocations to a call site and type
+    pub heap_alloc_sites: bool,
+    /// Names of the functions that allocate from the heap, matched against raw and short names
+    /// with any import or x86 decoration removed
+    pub heap_allocators: Vec<String>,
+    /// Whether and how string constants the analysis found get public symbols
+    ///
+    /// Off by default since large images can have hundreds of thousands of strings.
+    pub string_literals: StringLiteralNames,
+    /// Leave functions, publics and vtables in sections marked IMAGE_SCN_MEM_DISCARDABLE (INIT in
+    /// drivers, .debug sections) out of the PDB, so it reflects the image as it looks at runtime
+    pub skip_discardable_sections: bool,
+    /// Names of the sections whose functions and symbols are emitted, every section when empty
+    ///
+    /// Section headers are always written so section indices still match the image.
+    pub sections: Vec<String>,
+    /// Where the view's RVAs live in the image, for views of unpacked or dumped images whose
+    /// layout differs from the section table a debugger sees
+    ///
+    /// Every RVA collected is moved through these ranges and symbols outside all of them are
+    /// dropped. Takes precedence over [`Options::derive_layout`].
+    pub layout_ranges: Vec<LayoutRange>,
+    /// Derive [`Options::layout_ranges`] from where the view's segments are backed in the file
+    /// and where the section table maps those file offsets
+    pub derive_layout: bool,
+    /// Split modules with more functions than this into several, 0 disables splitting
+    ///
+    /// With [`ModuleLayout::Chunked`] this is the size of every module.
+    pub max_functions_per_module: usize,
+    /// Name given to each module, see [`Options::object_path`] for the placeholders
+    ///
+    /// A module split in several without `{suffix}` in its name gets the suffix appended.
+    /// Modules the template gives the same name as an earlier one are numbered `_2`, `_3`, ...
+    pub module_name: String,
+    /// Object file path recorded for each module, with `{name}` replaced by the module's
+    /// grouping (its section, component or library), `{section}` by the section it contributes
+    /// to, `{component}` by the component of its first function, `{binary}` by the image's file
+    /// name without extension and `{suffix}` by `_2`, `_3`, ... for every part of a split
+    /// module after the first
+    ///
+    /// Slashes in the replaced values become underscores so they can't add path components.
+    /// Without `{suffix}` the suffix goes ahead of the extension, and paths repeating an
+    /// earlier module's are numbered the same way as names.
+    /// Import modules always follow the linker's naming.
+    pub object_path: String,
+    /// Emit functions in address order regardless of the order the frontend collected them in,
+    /// so identical input always produces a byte-identical PDB
+    ///
+    /// The PDB signature is always derived from the image timestamp rather than the clock.
+    pub deterministic: bool,
+    /// Read the PDB back and check its structure before moving it into place
+    pub validate: bool,
+    /// DBI header fields to write instead of pdb-sdk's defaults, for consumers that check them
+    pub dbi_header: DbiHeader,
+    /// MSF block size, one of [`crate::msf::PAGE_SIZES`]
+    ///
+    /// Pages larger than 4K lift the MSF size limits but need a recent debugger to load.
+    pub page_size: u32,
+    /// Warn once a single module holds more procedure records than this, 0 disables the check
+    pub max_symbols_per_module: usize,
+    /// Warn once the PDB holds more procedure records than this, 0 disables the check
+    pub max_total_symbols: usize,
+    /// Drop functions beyond the symbol limits instead of only warning about them
+    pub truncate_at_symbol_limit: bool,
+    /// What to do about functions at different addresses sharing a name
+    pub duplicate_names: DuplicateNames,
+    /// Which of the names the frontend reports for a symbol is emitted
+    pub name_form: NameForm,
+    /// Which name wins when the user renamed a function the analysis had named
+    pub name_precedence: NamePrecedence,
+    /// Emit the name that lost to [`Options::name_precedence`] as an additional public symbol
+    pub shadowed_name_publics: bool,
+    /// Emit the raw names of loader and analysis defined symbols verbatim, without demangling
+    ///
+    /// Tooling around runtimes whose loaders recover large symbol sets (Go's pclntab, Delphi)
+    /// expects their exact name format.
+    pub raw_auto_names: bool,
+    /// What to do with Itanium mangled names, as MinGW and clang targeting it produce
+    pub itanium_names: ItaniumNames,
+    /// What to do with Rust legacy (`_ZN...17h<hash>E`) and v0 (`_R`) mangled names
+    pub rust_names: RustNames,
+    /// Also emit the mangled name of every demangled Rust function as a public symbol, so
+    /// either form can be looked up
+    pub rust_mangled_publics: bool,
+    /// How control characters and undecodable bytes in symbol names are rewritten
+    pub name_sanitization: NameSanitization,
+    /// What to do with symbol names longer than [`Options::max_name_length`]
+    pub long_names: LongNamePolicy,
+    /// Longest symbol name in bytes before [`Options::long_names`] applies, 0 disables the check
+    ///
+    /// MSVC stops at 4096 and some consumers reject records with longer names.
+    pub max_name_length: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            module_layout: ModuleLayout::default(),
+            library_modules: false,
+            import_modules: false,
+            cross_module_references: false,
+            trim_padding: false,
+            user_sections: false,
+            overlay_section: false,
+            include_unwind_functions: false,
+            exception_stream: false,
+            symbol_types: vec![
+                SymbolType::Function,
+                SymbolType::ImportedFunction,
+                SymbolType::LibraryFunction,
+            ],
+            vtables: false,
+            parameter_locations: false,
+            call_site_types: false,
+            frame_info: false,
+            basic_block_labels: false,
+            debug_info: true,
+            type_archives: true,
+            env_block: false,
+            manifest_stream: false,
+            heap_alloc_sites: false,
+            heap_allocators: HEAP_ALLOCATORS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            string_literals: StringLiteralNames::default(),
+            skip_discardable_sections: false,
+            sections: Vec::new(),
+            layout_ranges: Vec::new(),
+            derive_layout: false,
+            max_functions_per_module: 100_000,
+            module_name: "{name}_module{suffix}".to_string(),
+            object_path: "/fake/path/{name}{suffix}.obj".to_string(),
+            deterministic: false,
+            validate: true,
+            dbi_header: DbiHeader::default(),
+            page_size: crate::msf::DEFAULT_PAGE_SIZE,
+            max_symbols_per_module: 0,
+            max_total_symbols: 5_000_000,
+            truncate_at_symbol_limit: false,
+            duplicate_names: DuplicateNames::default(),
+            name_form: NameForm::default(),
+            name_precedence: NamePrecedence::default(),
+            shadowed_name_publics: false,
+            raw_auto_names: false,
+            itanium_names: ItaniumNames::default(),
+            rust_names: RustNames::default(),
+            rust_mangled_publics: false,
+            name_sanitization: NameSanitization::default(),
+            long_names: LongNamePolicy::default(),
+            max_name_length: 4096,
+        }
+    }
+}
+
+/// The C runtime, C++ and Windows heap allocators
+const HEAP_ALLOCATORS: &[&str] = &[
+    "malloc",
+    "calloc",
+    "realloc",
+    "_malloc_base",
+    "_calloc_base",
+    "_realloc_base",
+    "_aligned_malloc",
+    "operator new",
+    "operator new[]",
+    "??2@YAPEAX_K@Z",
+    "??_U@YAPEAX_K@Z",
+    "??2@YAPAXI@Z",
+    "??_U@YAPAXI@Z",
+    "HeapAlloc",
+    "HeapReAlloc",
+    "RtlAllocateHeap",
+    "LocalAlloc",
+    "GlobalAlloc",
+    "CoTaskMemAlloc",
+    "ExAllocatePool",
+    "ExAllocatePoolWithTag",
+    "ExAllocatePool2",
+];
+
+impl Options {
+    /// Whether functions and symbols in the section named `name` are emitted, see
+    /// [`Options::sections`]
+    pub fn includes_section(&self, name: &str) -> bool {
+        self.sections.is_empty() || self.sections.iter().any(|section| section == name)
+    }
+
+    /// Whether a function named `name` is one of [`Options::heap_allocators`]
+    pub fn is_heap_allocator(&self, name: &str) -> bool {
+        let name = name.strip_prefix("__imp_").unwrap_or(name);
+        // x86 C and stdcall decoration, `_malloc` and `_HeapAlloc@12`
+        let undecorated = name.strip_prefix('_').unwrap_or(name);
+        let undecorated = undecorated
+            .rsplit_once('@')
+            .filter(|(_, bytes)| bytes.parse::<u32>().is_ok())
+            .map_or(undecorated, |(name, _)| name);
+        self.heap_allocators
+            .iter()
+            .any(|allocator| allocator == name || allocator == undecorated)
+    }
+}
+
+/// The form a symbol's name is emitted in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameForm {
+    /// The name as found in the binary, mangled names left mangled
+    Raw,
+    /// The demangled name without parameters or return type
+    #[default]
+    Short,
+    /// The demangled name with its full signature
+    Full,
+}
+
+/// Whose name is emitted for a function both the user and the analysis named
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamePrecedence {
+    #[default]
+    User,
+    Auto,
+}
+
+/// Handling of Itanium (`_Z`) mangled function names
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItaniumNames {
+    /// Use the name the frontend reported unchanged
+    Keep,
+    /// Demangle to the qualified name without parameters
+    #[default]
+    Demangle,
+    /// Demangle, then decorate the way MSVC would so Visual Studio groups methods by class. Only
+    /// global functions, constructors, destructors and const member functions can be told apart,
+    /// other scoped names stay undecorated
+    Msvc,
+}
+
+/// Handling of Rust mangled function names
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RustNames {
+    /// Use the name the frontend reported unchanged
+    Keep,
+    /// Demangle without the legacy hash suffix or v0 crate disambiguators
+    #[default]
+    Demangle,
+    /// Demangle, keeping hashes and crate disambiguators so every name stays unique
+    Full,
+}
+
+/// The kind of symbol that names an address, as Binary Ninja classifies them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolType {
+    #[default]
+    Function,
+    /// Functions imported from another module, such as import thunks
+    ImportedFunction,
+    /// Functions matched against a signature library
+    LibraryFunction,
+    Data,
+    /// Symbols defined outside the image
+    External,
+}
+
+/// Naming of the public symbols emitted for string constants
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StringLiteralNames {
+    /// Emit no symbols for strings
+    #[default]
+    Off,
+    /// `??_C@_...` names as MSVC gives literals, which debuggers undecorate to `` `string' ``
+    ///
+    /// The length and checksum in the name cover the whole literal, so literals past 4 KiB are
+    /// left unnamed rather than read in full.
+    Mangled,
+    /// The string itself in quotes, shortened, so disassembly shows the text at each reference
+    Plain,
+}
+
+/// Grouping of functions into DBI modules
+///
+/// WinDbg loads symbols a module at a time, so fewer, larger modules favour whole-image
+/// lookups, while small modules with stable names diff better between runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleLayout {
+    /// One module for the whole image, never split
+    Single,
+    /// One module per section
+    #[default]
+    Section,
+    /// One module per component assigned in the frontend, falling back to the section for
+    /// functions outside any component
+    Component,
+    /// Modules of [`Options::max_functions_per_module`] functions each in address order
+    Chunked,
+}
+
+/// Handling of symbol names longer than [`Options::max_name_length`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LongNamePolicy {
+    /// Emit the name as is
+    Keep,
+    /// Cut the name off at the limit
+    Truncate,
+    /// Cut the name off and end it with a hash of the full name, so distinct names stay distinct
+    #[default]
+    Hash,
+}
+
+/// Rewriting of symbol names containing characters debuggers may not display or parse
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameSanitization {
+    /// Spell control and replacement characters out as `\u{..}` escapes
+    #[default]
+    Escape,
+    /// Replace control and replacement characters with `_`
+    Replace,
+    /// Emit names as collected, except for NUL which would terminate the name early
+    ///
+    /// Bytes that are not valid UTF-8 were already escaped as `\xNN` when the name was read
+    /// from the analysis, so they stay escaped.
+    Raw,
+}
+
+/// Handling of names shared by functions at different addresses, which make debugger lookups by
+/// name ambiguous
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateNames {
+    /// Emit every function under its own name
+    Keep,
+    /// Emit names unchanged but warn about the collisions
+    #[default]
+    Report,
+    /// Append `@rva` to every function whose name collides
+    Suffix,
+}
+
+/// Optional overrides for the DBI stream header, to mimic a particular toolchain's output
+///
+/// Fields left as `None` keep the value pdb-sdk writes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DbiHeader {
+    /// VersionHeader, 19990903 for every current toolchain
+    pub version: Option<u32>,
+    /// Toolchain version, bit 15 set for the new format with the major version in bits 8-14
+    /// and the minor in bits 0-7
+    pub build_number: Option<u16>,
+    /// Build number of the mspdbXXX.dll that wrote the PDB
+    pub pdb_dll_version: Option<u16>,
+    /// Rebuild number of the mspdbXXX.dll that wrote the PDB
+    pub pdb_dll_rebuild: Option<u16>,
+    /// Bit 0 incrementally linked, bit 1 private symbols stripped, bit 2 conflicting types
+    pub flags: Option<u16>,
+    /// IMAGE_FILE_MACHINE_* of the image
+    pub machine: Option<u16>,
+}
+
+impl DbiHeader {
+    pub fn is_empty(&self) -> bool {
+        self.version.is_none()
+            && self.build_number.is_none()
+            && self.pdb_dll_version.is_none()
+            && self.pdb_dll_rebuild.is_none()
+            && self.flags.is_none()
+            && self.machine.is_none()
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Receives progress updates from long running phases
+pub trait Progress {
+    /// `done` out of `total` items of `phase` have been processed
+    fn update(&mut self, phase: &str, done: usize, total: usize);
+
+    /// Whether the user asked to stop, checked between items
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+
+    fn check_cancelled(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Progress for () {
+    fn update(&mut self, _phase: &str, _done: usize, _total: usize) {}
+}
+
+/// Error returned when generation stops because [`Progress::is_cancelled`] returned true
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cancelled by user")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Minimum time between two messages passed to the sink
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Turns progress updates into "phase: 42% (ETA 1m 05s)" messages
+///
+/// The estimate assumes the remaining items of a phase take as long as the ones already done.
+pub struct Eta<F> {
+    sink: F,
+    phase: String,
+    phase_start: Instant,
+    last_update: Option<Instant>,
+}
+
+impl<F: FnMut(&str)> Eta<F> {
+    pub fn new(sink: F) -> Self {
+        Self {
+            sink,
+            phase: String::new(),
+            phase_start: Instant::now(),
+            last_update: None,
+        }
+    }
+}
+
+impl<F: FnMut(&str)> Progress for Eta<F> {
+    fn update(&mut self, phase: &str, done: usize, total: usize) {
+        let now = Instant::now();
+        if phase != self.phase {
+            self.phase = phase.to_string();
+            self.phase_start = now;
+            self.last_update = None;
+        }
+        if self
+            .last_update
+            .is_some_and(|last| now - last < UPDATE_INTERVAL && done < total)
+        {
+            return;
+        }
+        self.last_update = Some(now);
+
+        let percent = if total == 0 { 100 } else { done * 100 / total };
+        let message = match remaining(now - self.phase_start, done, total) {
+            Some(eta) => format!("{phase}: {percent}% (ETA {})", format_duration(eta)),
+            None => format!("{phase}: {percent}%"),
+        };
+        (self.sink)(&message);
+    }
+}
+
+fn remaining(elapsed: Duration, done: usize, total: usize) -> Option<Duration> {
+    if done == 0 || done >= total {
+        return None;
+    }
+    Some(elapsed.mul_f64((total - done) as f64 / done as f64))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s:02}s"),
+        (h, m, _) => format!("{h}h {m:02}m"),
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::ops::Range;
+
+use crate::model::{Frame, FunctionKind, Parameter, TypeDefinition, ValueType};
+use crate::options::SymbolType;
+
+/// The accesses collection needs from an analyzed image
+///
+/// Implemented by the Binary Ninja plugin for `BinaryView` and by [`MemoryReader`] so the
+/// collection logic can run without a Binary Ninja core.
+pub trait ViewReader {
+    /// Address the image is loaded at, which RVAs are relative to
+    fn start(&self) -> u64;
+
+    /// Read up to `len` bytes at `addr`, returning fewer if the range is not fully backed
+    fn read(&self, addr: u64, len: usize) -> Vec<u8>;
+
+    /// Address of the symbol with the given raw name
+    fn symbol_address(&self, raw_name: &str) -> Option<u64>;
+
+    /// Every function known to the analysis
+    fn functions(&self) -> Vec<FunctionInfo>;
+
+    /// Start addresses of every function containing `addr`
+    fn functions_containing(&self, addr: u64) -> Vec<u64>;
+
+    /// Sections the analysis defines, which may include ones missing from the PE header
+    fn sections(&self) -> Vec<ViewSection>;
+
+    /// String constants the analysis found
+    fn strings(&self) -> Vec<ViewString>;
+
+    /// Named data and external symbols
+    fn data_symbols(&self) -> Vec<ViewSymbol>;
+
+    /// Name and version of the program that did the analysis, for the manifest stream
+    fn frontend(&self) -> String {
+        String::new()
+    }
+
+    /// File name of the image the view was loaded from, for [`crate::Options::module_name`]
+    fn image_name(&self) -> String {
+        String::new()
+    }
+
+    /// Memory the view maps and where it comes from in the file, see
+    /// [`crate::Options::derive_layout`]
+    fn segments(&self) -> Vec<ViewSegment> {
+        Vec::new()
+    }
+
+    /// Named types to emit to the TPI with their layout, together with every type they refer
+    /// to by name
+    fn types(&self) -> Vec<TypeDefinition> {
+        Vec::new()
+    }
+}
+
+/// A function as reported by the analysis, before its ranges are merged
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct FunctionInfo {
+    pub start: u64,
+    pub name: String,
+    /// Name as the symbol was found, before any demangling
+    pub raw_name: String,
+    /// Name including the signature, for languages whose demangled names carry one
+    pub full_name: String,
+    /// Whether the symbol was defined by a loader or analysis rather than the user
+    pub auto: bool,
+    /// Names of the analysis defined symbol a user defined one replaced, if any
+    pub auto_symbol: Option<SymbolNames>,
+    pub symbol_type: SymbolType,
+    pub ranges: Vec<Range<u64>>,
+    /// Innermost component the function was assigned to, if any
+    pub component: Option<String>,
+    /// Library a signature match attributed the function to, if the frontend knows it
+    pub library: Option<String>,
+    /// Library the frontend links an imported function to (`KERNEL32.dll`), if it records one
+    pub import_library: Option<String>,
+    pub kind: FunctionKind,
+    /// Parameters as the calling convention places them on entry
+    pub parameters: Vec<Parameter>,
+    /// Length of the instruction at `start`
+    pub entry_length: u32,
+    /// Indirect calls the analysis knows the target signature of
+    pub call_sites: Vec<CallSiteInfo>,
+    /// Calls to functions matching [`crate::Options::heap_allocators`]
+    pub heap_alloc_sites: Vec<HeapAllocSiteInfo>,
+    /// Targets of the function's calls to constant addresses
+    pub calls: Vec<u64>,
+    /// Frame layout, if the analysis recovered one
+    pub frame: Option<Frame>,
+    /// Start addresses of the function's basic blocks
+    pub blocks: Vec<u64>,
+    /// Types among the above the frontend could only approximate
+    pub type_issues: Vec<TypeIssueInfo>,
+}
+
+/// An indirect call as reported by the analysis
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CallSiteInfo {
+    pub address: u64,
+    pub return_type: Option<ValueType>,
+    pub parameters: Vec<ValueType>,
+}
+
+/// A heap allocator call as reported by the analysis
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct HeapAllocSiteInfo {
+    pub address: u64,
+    /// Length of the call instruction
+    pub length: u16,
+    /// Type the result was assigned to points at, if known
+    pub allocated: Option<ValueType>,
+}
+
+/// A type the frontend could only approximate, as reported by the analysis
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TypeIssueInfo {
+    pub address: u64,
+    /// What was typed, such as `parameter hFile`
+    pub item: String,
+    /// The type as the frontend names it, such as `HANDLE`
+    pub type_name: String,
+    pub reason: String,
+}
+
+/// A section as the analysis sees it
+#[derive(Debug, Clone, Default)]
+pub struct ViewSection {
+    pub name: String,
+    pub range: Range<u64>,
+    pub code: bool,
+    pub writable: bool,
+    /// Created by the user rather than by the loader from the PE header
+    pub user: bool,
+}
+
+/// A range of the view's memory and the part of the file backing it
+#[derive(Debug, Clone, Default)]
+pub struct ViewSegment {
+    pub range: Range<u64>,
+    /// Offset of the backing bytes in the file
+    pub data_offset: u64,
+    /// Number of backing bytes, the rest of the range is zero filled
+    pub data_length: u64,
+}
+
+/// The forms of a symbol's name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SymbolNames {
+    pub name: String,
+    pub raw_name: String,
+    pub full_name: String,
+}
+
+/// A named address that is not a function
+#[derive(Debug, Clone, Default)]
+pub struct ViewSymbol {
+    pub address: u64,
+    pub name: String,
+    pub raw_name: String,
+    pub full_name: String,
+    pub symbol_type: SymbolType,
+}
+
+/// A string constant as the analysis sees it
+#[derive(Debug, Clone, Default)]
+pub struct ViewString {
+    pub address: u64,
+    /// Length in bytes, without a terminator
+    pub length: usize,
+    /// Bytes per code unit, 1 for narrow strings, 2 for UTF-16 and 4 for UTF-32
+    pub width: usize,
+}
+
+/// Decode a name from the analysis, escaping bytes that are not valid UTF-8 as `\xNN` rather
+/// than collapsing them into replacement characters
+pub fn decode_name(bytes: &[u8]) -> String {
+    let mut name = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        name.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            let _ = write!(name, "\\x{byte:02x}");
+        }
+    }
+    name
+}
+
+/// In-memory [`ViewReader`] for exercising collection in tests and tools
+#[derive(Debug, Clone, Default)]
+pub struct MemoryReader {
+    pub start: u64,
+    /// Backed memory regions keyed by start address
+    pub regions: BTreeMap<u64, Vec<u8>>,
+    pub symbols: HashMap<String, u64>,
+    pub functions: Vec<FunctionInfo>,
+    pub sections: Vec<ViewSection>,
+    pub strings: Vec<ViewString>,
+    pub data_symbols: Vec<ViewSymbol>,
+    pub segments: Vec<ViewSegment>,
+    pub types: Vec<TypeDefinition>,
+}
+
+impl ViewReader for MemoryReader {
+    fn start(&self) -> u64 {
+        self.start
+    }
+
+    fn read(&self, addr: u64, len: usize) -> Vec<u8> {
+        let Some((&region_start, data)) = self.regions.range(..=addr).next_back() else {
+            return Vec::new();
+        };
+        let offset = (addr - region_start) as usize;
+        if offset >= data.len() {
+            return Vec::new();
+        }
+        data[offset..data.len().min(offset + len)].to_vec()
+    }
+
+    fn symbol_address(&self, raw_name: &str) -> Option<u64> {
+        self.symbols.get(raw_name).copied()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        self.functions.clone()
+    }
+
+    fn functions_containing(&self, addr: u64) -> Vec<u64> {
+        self.functions
+            .iter()
+            .filter(|f| f.ranges.iter().any(|r| r.contains(&addr)))
+            .map(|f| f.start)
+            .collect()
+    }
+
+    fn sections(&self) -> Vec<ViewSection> {
+        self.sections.clone()
+    }
+
+    fn strings(&self) -> Vec<ViewString> {
+        self.strings.clone()
+    }
+
+    fn data_symbols(&self) -> Vec<ViewSymbol> {
+        self.data_symbols.clone()
+    }
+
+    fn segments(&self) -> Vec<ViewSegment> {
+        self.segments.clone()
+    }
+
+    fn types(&self) -> Vec<TypeDefinition> {
+        self.types.clone()
+    }
+}
@@ -0,0 +1,244 @@
+//! Mapping of register names, as Binary Ninja spells them, to CodeView register codes
+//!
+//! S_REGISTER, S_REGREL32 and the DEFRANGE records identify registers by the per-architecture
+//! CV_HREG_e codes from cvconst.h. Subregisters have codes of their own, so `eax`, `ax` and `al`
+//! all map to different values. AVX-512 registers have no mapping yet.
+
+use serde::{Deserialize, Serialize};
+
+/// Architectures with a CodeView register numbering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    X86,
+    X64,
+    Arm64,
+}
+
+impl Arch {
+    /// The architecture of an image from its COFF Machine field
+    pub fn from_machine(machine: u16) -> Option<Self> {
+        match machine {
+            0x014c => Some(Self::X86),
+            0x8664 => Some(Self::X64),
+            0xaa64 => Some(Self::Arm64),
+            _ => None,
+        }
+    }
+
+    /// Code of the stack pointer, which stack locations are relative to
+    pub fn stack_pointer(self) -> u16 {
+        match self {
+            Self::X86 => 21,
+            Self::X64 => 335,
+            Self::Arm64 => 81,
+        }
+    }
+}
+
+/// CodeView code of the register `name` on `arch`, case insensitive
+pub fn cv_register(arch: Arch, name: &str) -> Option<u16> {
+    let name = name.to_ascii_lowercase();
+    match arch {
+        Arch::X86 => x86(&name),
+        Arch::X64 => x64(&name),
+        Arch::Arm64 => arm64(&name),
+    }
+}
+
+/// `base + n` for names of the form `{prefix}{n}{suffix}` with `n < count`
+fn numbered(name: &str, prefix: &str, suffix: &str, count: u16, base: u16) -> Option<u16> {
+    let digits = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    if digits.is_empty() || (digits.len() > 1 && digits.starts_with('0')) {
+        return None;
+    }
+    let n: u16 = digits.parse().ok()?;
+    (n < count).then_some(base + n)
+}
+
+/// Registers x86 and x64 number identically
+fn x86_common(name: &str) -> Option<u16> {
+    let code = match name {
+        "al" => 1,
+        "cl" => 2,
+        "dl" => 3,
+        "bl" => 4,
+        "ah" => 5,
+        "ch" => 6,
+        "dh" => 7,
+        "bh" => 8,
+        "ax" => 9,
+        "cx" => 10,
+        "dx" => 11,
+        "bx" => 12,
+        "sp" => 13,
+        "bp" => 14,
+        "si" => 15,
+        "di" => 16,
+        "eax" => 17,
+        "ecx" => 18,
+        "edx" => 19,
+        "ebx" => 20,
+        "esp" => 21,
+        "ebp" => 22,
+        "esi" => 23,
+        "edi" => 24,
+        "es" => 25,
+        "cs" => 26,
+        "ss" => 27,
+        "ds" => 28,
+        "fs" => 29,
+        "gs" => 30,
+        "flags" => 32,
+        "eflags" => 34,
+        "gdtr" => 110,
+        "gdtl" => 111,
+        "idtr" => 112,
+        "idtl" => 113,
+        "ldtr" => 114,
+        "tr" => 115,
+        "fpctrl" | "fpcw" => 136,
+        "fpstat" | "fpsw" => 137,
+        "fptag" | "fptw" => 138,
+        "fpip" => 139,
+        "fpcs" => 140,
+        "fpdo" => 141,
+        "fpds" => 142,
+        "isem" => 143,
+        "fpeip" => 144,
+        "fpedo" => 145,
+        "mxcsr" => 211,
+        _ => {
+            return numbered(name, "cr", "", 5, 80)
+                .or_else(|| numbered(name, "dr", "", 8, 90))
+                .or_else(|| numbered(name, "st", "", 8, 128))
+                .or_else(|| numbered(name, "mm", "", 8, 146))
+                .or_else(|| numbered(name, "xmm", "", 8, 154));
+        }
+    };
+    Some(code)
+}
+
+fn x86(name: &str) -> Option<u16> {
+    match name {
+        "ip" => Some(31),
+        "eip" => Some(33),
+        "edxeax" => Some(212),
+        _ => x86_common(name).or_else(|| numbered(name, "ymm", "", 8, 252)),
+    }
+}
+
+fn x64(name: &str) -> Option<u16> {
+    let code = match name {
+        "rip" => 33,
+        "cr8" => 88,
+        "sil" => 324,
+        "dil" => 325,
+        "bpl" => 326,
+        "spl" => 327,
+        "rax" => 328,
+        "rbx" => 329,
+        "rcx" => 330,
+        "rdx" => 331,
+        "rsi" => 332,
+        "rdi" => 333,
+        "rbp" => 334,
+        "rsp" => 335,
+        _ => {
+            return x86_common(name)
+                .or_else(|| numbered(name, "dr", "", 16, 90))
+                .or_else(|| numbered(name, "xmm", "", 16, 154).map(xmm_x64))
+                .or_else(|| numbered(name, "r", "b", 16, 344 - 8).filter(|&c| c >= 344))
+                .or_else(|| numbered(name, "r", "w", 16, 352 - 8).filter(|&c| c >= 352))
+                .or_else(|| numbered(name, "r", "d", 16, 360 - 8).filter(|&c| c >= 360))
+                .or_else(|| numbered(name, "r", "", 16, 336 - 8).filter(|&c| c >= 336))
+                .or_else(|| numbered(name, "ymm", "", 16, 368));
+        }
+    };
+    Some(code)
+}
+
+/// xmm8 and up were added after the x86 range and continue at 252
+fn xmm_x64(code: u16) -> u16 {
+    match code {
+        154..=161 => code,
+        _ => code - 162 + 252,
+    }
+}
+
+fn arm64(name: &str) -> Option<u16> {
+    let code = match name {
+        "wzr" => 41,
+        "fp" | "x29" => 79,
+        "lr" | "x30" => 80,
+        "sp" => 81,
+        "xzr" => 82,
+        "pc" => 83,
+        "nzcv" => 90,
+        "cpsr" => 91,
+        "fpsr" => 220,
+        "fpcr" => 221,
+        _ => {
+            return numbered(name, "w", "", 31, 10)
+                .or_else(|| numbered(name, "x", "", 29, 50))
+                .or_else(|| numbered(name, "s", "", 32, 100))
+                .or_else(|| numbered(name, "d", "", 32, 140))
+                .or_else(|| numbered(name, "q", "", 32, 180))
+                .or_else(|| numbered(name, "b", "", 32, 230))
+                .or_else(|| numbered(name, "h", "", 32, 270))
+                .or_else(|| numbered(name, "v", "", 32, 310));
+        }
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_x86_registers() {
+        assert_eq!(cv_register(Arch::X86, "EAX"), Some(17));
+        assert_eq!(cv_register(Arch::X86, "esp"), Some(21));
+        assert_eq!(cv_register(Arch::X86, "eip"), Some(33));
+        assert_eq!(cv_register(Arch::X86, "st7"), Some(135));
+        assert_eq!(cv_register(Arch::X86, "xmm7"), Some(161));
+        assert_eq!(cv_register(Arch::X86, "ymm0"), Some(252));
+        assert_eq!(cv_register(Arch::X86, "xmm8"), None);
+        assert_eq!(cv_register(Arch::X86, "rax"), None);
+    }
+
+    #[test]
+    fn numbers_x64_registers() {
+        assert_eq!(cv_register(Arch::X64, "rax"), Some(328));
+        assert_eq!(cv_register(Arch::X64, "rsp"), Some(335));
+        assert_eq!(cv_register(Arch::X64, "eax"), Some(17));
+        assert_eq!(cv_register(Arch::X64, "r8"), Some(336));
+        assert_eq!(cv_register(Arch::X64, "r15"), Some(343));
+        assert_eq!(cv_register(Arch::X64, "r8b"), Some(344));
+        assert_eq!(cv_register(Arch::X64, "r15w"), Some(359));
+        assert_eq!(cv_register(Arch::X64, "r9d"), Some(361));
+        assert_eq!(cv_register(Arch::X64, "xmm0"), Some(154));
+        assert_eq!(cv_register(Arch::X64, "xmm8"), Some(252));
+        assert_eq!(cv_register(Arch::X64, "xmm15"), Some(259));
+        assert_eq!(cv_register(Arch::X64, "ymm15"), Some(383));
+        // Only r8 and up are numbered, and only up to r15
+        assert_eq!(cv_register(Arch::X64, "r7"), None);
+        assert_eq!(cv_register(Arch::X64, "r16"), None);
+        assert_eq!(cv_register(Arch::X64, "r08"), None);
+    }
+
+    #[test]
+    fn numbers_arm64_registers() {
+        assert_eq!(cv_register(Arch::Arm64, "w0"), Some(10));
+        assert_eq!(cv_register(Arch::Arm64, "x0"), Some(50));
+        assert_eq!(cv_register(Arch::Arm64, "x28"), Some(78));
+        assert_eq!(cv_register(Arch::Arm64, "x29"), Some(79));
+        assert_eq!(cv_register(Arch::Arm64, "fp"), Some(79));
+        assert_eq!(cv_register(Arch::Arm64, "LR"), Some(80));
+        assert_eq!(cv_register(Arch::Arm64, "sp"), Some(81));
+        assert_eq!(cv_register(Arch::Arm64, "q31"), Some(211));
+        assert_eq!(cv_register(Arch::Arm64, "x31"), None);
+        assert_eq!(cv_register(Arch::Arm64, "eax"), None);
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Types listed by [`Report::log_summary`], the most common first
+const MAX_SUMMARY_TYPES: usize = 20;
+/// Uses of each type listed by [`Report::log_summary`]
+const MAX_SUMMARY_EXAMPLES: usize = 3;
+
+/// Items that were skipped instead of aborting the whole run
+#[derive(Debug, Default)]
+pub struct Report {
+    pub skipped: Vec<(String, anyhow::Error)>,
+    /// Conditions worth surfacing after the run that didn't cause anything to be skipped outright
+    pub warnings: Vec<String>,
+    /// Types that had to be approximated or left out, so their definitions can be fixed
+    pub type_issues: Vec<TypeIssue>,
+    /// Wall-clock time spent in each phase, in the order the phases ran
+    pub timings: Vec<(&'static str, Duration)>,
+    /// Indices into `type_issues` by RVA
+    type_issues_by_rva: HashMap<u32, Vec<usize>>,
+}
+
+/// A type that could not be converted faithfully
+#[derive(Debug, Clone)]
+pub struct TypeIssue {
+    /// Function the type belongs to
+    pub symbol: String,
+    /// Address of the affected parameter, call or allocation
+    pub rva: u32,
+    /// What was typed, such as `parameter hFile`
+    pub item: String,
+    /// The type as the frontend names it, such as `HANDLE`, or its size and kind if only those
+    /// are known
+    pub type_name: String,
+    /// What happened to the type
+    pub reason: String,
+}
+
+impl Report {
+    pub fn skip(&mut self, item: String, err: anyhow::Error) {
+        warn!("Skipping {item}: {err:#}");
+        self.skipped.push((item, err));
+    }
+
+    pub fn warn(&mut self, message: String) {
+        warn!("{message}");
+        self.warnings.push(message);
+    }
+
+    /// Record `issue` unless one at the same RVA already covers its item, as when emit can't
+    /// encode a value the frontend had already approximated (`call` is covered by
+    /// `call parameter x`)
+    pub fn type_issue(&mut self, issue: TypeIssue) {
+        let indices = self.type_issues_by_rva.entry(issue.rva).or_default();
+        let covered = indices.iter().any(|&i| {
+            let existing = &self.type_issues[i].item;
+            existing
+                .strip_prefix(&issue.item)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+        });
+        if !covered {
+            indices.push(self.type_issues.len());
+            self.type_issues.push(issue);
+        }
+    }
+
+    /// Record that `phase` ran from `start` until now
+    pub fn time(&mut self, phase: &'static str, start: Instant) {
+        self.timings.push((phase, start.elapsed()));
+    }
+
+    pub fn log_summary(&self) {
+        // Each warning was logged as it was raised
+        if !self.warnings.is_empty() {
+            warn!(
+                "{} warning(s) were raised during the run",
+                self.warnings.len()
+            );
+        }
+        if !self.type_issues.is_empty() {
+            self.log_type_issues();
+        }
+        if self.skipped.is_empty() {
+            return;
+        }
+        warn!("{} item(s) were skipped:", self.skipped.len());
+        for (item, err) in &self.skipped {
+            warn!("  {item}: {err:#}");
+        }
+    }
+
+    /// One line per type, with how often it was used and a few of the uses
+    fn log_type_issues(&self) {
+        let mut by_type: BTreeMap<(&str, &str), Vec<&TypeIssue>> = BTreeMap::new();
+        for issue in &self.type_issues {
+            by_type
+                .entry((&issue.type_name, &issue.reason))
+                .or_default()
+                .push(issue);
+        }
+        let mut by_type: Vec<_> = by_type.into_iter().collect();
+        by_type.sort_by_key(|(_, issues)| std::cmp::Reverse(issues.len()));
+
+        warn!(
+            "{} use(s) of {} type(s) could not be converted faithfully:",
+            self.type_issues.len(),
+            by_type.len()
+        );
+        for ((type_name, reason), issues) in by_type.iter().take(MAX_SUMMARY_TYPES) {
+            let examples: Vec<String> = issues
+                .iter()
+                .take(MAX_SUMMARY_EXAMPLES)
+                .map(|issue| format!("{} {} (rva 0x{:x})", issue.symbol, issue.item, issue.rva))
+                .collect();
+            let more = match issues.len().saturating_sub(MAX_SUMMARY_EXAMPLES) {
+                0 => String::new(),
+                n => format!(" and {n} more"),
+            };
+            warn!(
+                "  {type_name}: {reason}, {} use(s) such as {}{more}",
+                issues.len(),
+                examples.join(", ")
+            );
+        }
+        if by_type.len() > MAX_SUMMARY_TYPES {
+            warn!("  and {} more type(s)", by_type.len() - MAX_SUMMARY_TYPES);
+        }
+    }
+
+    pub fn log_timings(&self) {
+        let total: Duration = self.timings.iter().map(|(_, d)| *d).sum();
+        info!("Timing breakdown ({:.3}s total):", total.as_secs_f64());
+        for (phase, duration) in &self.timings {
+            let percent = if total.is_zero() {
+                0.0
+            } else {
+                duration.as_secs_f64() * 100.0 / total.as_secs_f64()
+            };
+            info!(
+                "  {phase:<20} {:>9.3}s {percent:>5.1}%",
+                duration.as_secs_f64()
+            );
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use crate::model::{TypeReference, ValueType};
+use pdb_sdk::codeview::types::TypeIndex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Identity of a type, used to emit each distinct type to the TPI only once
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TypeKey {
+    /// Types the frontend can name stably, such as by Binary Ninja type id or qualified name
+    Named(String),
+    /// Anonymous procedure type with the given signature, `None` returning void
+    Procedure {
+        return_type: Option<ValueType>,
+        parameters: Vec<ValueType>,
+    },
+    /// Array of `count` pointers of `slot_size` bytes, such as a vtable
+    PointerArray { slot_size: u32, count: u32 },
+    /// Record a [`crate::model::TypeDefinition`] member or alias refers to
+    Reference(TypeReference),
+}
+
+/// TPI indices of types that have already been emitted
+#[derive(Debug, Default)]
+pub(crate) struct TypeCache {
+    indices: HashMap<TypeKey, TypeIndex>,
+}
+
+impl TypeCache {
+    /// Index of the type identified by `key`, emitting it with `emit` on first use
+    pub fn get_or_insert_with(
+        &mut self,
+        key: TypeKey,
+        emit: impl FnOnce() -> TypeIndex,
+    ) -> TypeIndex {
+        *self.indices.entry(key).or_insert_with(emit)
+    }
+
+    pub fn get(&self, key: &TypeKey) -> Option<TypeIndex> {
+        self.indices.get(key).copied()
+    }
+
+    pub fn insert(&mut self, key: TypeKey, index: TypeIndex) {
+        self.indices.insert(key, index);
+    }
+}
@@ -0,0 +1,163 @@
+//! Read a written PDB back and check the invariants debuggers rely on
+//!
+//! WinDbg and DIA tend to silently ignore a PDB that breaks these rather than saying why, so
+//! problems are reported here with enough context to trace them back to the model.
+
+use anyhow::{Result, bail};
+use pdb::{FallibleIterator as _, PDB, PdbInternalSectionOffset, SymbolData};
+use std::fs;
+use std::path::Path;
+
+use crate::Model;
+
+/// Only the first few problems are listed, the rest are usually the same mistake repeated
+const MAX_REPORTED_PROBLEMS: usize = 20;
+
+/// Lowest index of a non-primitive type in the TPI stream
+const FIRST_TYPE_INDEX: u32 = 0x1000;
+
+/// Check the PDB at `path` against the model it was built from
+pub fn validate_pdb(path: &Path, model: &Model) -> Result<()> {
+    let mut pdb = PDB::open(fs::File::open(path)?)?;
+    let mut problems = Problems::default();
+
+    let info = pdb.pdb_information()?;
+    if info.age != model.info.age || info.signature != model.info.timestamp {
+        problems.add(format!(
+            "PDB info stream has age {} and signature 0x{:x}, expected {} and 0x{:x}",
+            info.age, info.signature, model.info.age, model.info.timestamp
+        ));
+    }
+
+    let sections = pdb.sections()?.unwrap_or_default();
+    if sections.len() != model.sections.len() {
+        problems.add(format!(
+            "section header stream holds {} sections, the image has {}",
+            sections.len(),
+            model.sections.len()
+        ));
+    }
+
+    let type_count = pdb.type_information()?.len() as u32;
+    let dbi = pdb.debug_information()?;
+
+    let mut contributions = dbi.section_contributions()?;
+    while let Some(contribution) = contributions.next()? {
+        problems.check_extent(
+            model,
+            &format!("section contribution of module {}", contribution.module),
+            contribution.offset,
+            contribution.size,
+        );
+    }
+
+    let mut modules = dbi.modules()?;
+    let mut module_index = 0;
+    while let Some(module) = modules.next()? {
+        let name = module.module_name().into_owned();
+        let Some(module_info) = pdb.module_info(&module)? else {
+            problems.add(format!(
+                "module {module_index} ({name}) has no symbol stream"
+            ));
+            module_index += 1;
+            continue;
+        };
+
+        let mut symbols = module_info.symbols()?;
+        while let Some(symbol) = symbols.next()? {
+            match symbol.parse() {
+                Ok(SymbolData::Procedure(procedure)) => {
+                    let what = format!("procedure {} in module {name}", procedure.name);
+                    problems.check_extent(model, &what, procedure.offset, procedure.len);
+                    let type_index = procedure.type_index.0;
+                    if type_index >= FIRST_TYPE_INDEX + type_count {
+                        problems.add(format!(
+                            "{what} refers to type 0x{type_index:x} but the TPI stream ends at 0x{:x}",
+                            FIRST_TYPE_INDEX + type_count
+                        ));
+                    }
+                }
+                Ok(SymbolData::Thunk(thunk)) => {
+                    let what = format!("thunk {} in module {name}", thunk.name);
+                    problems.check_extent(model, &what, thunk.offset, thunk.len as u32);
+                }
+                _ => {}
+            }
+        }
+        module_index += 1;
+    }
+
+    let globals = pdb.global_symbols()?;
+    let mut symbols = globals.iter();
+    while let Some(symbol) = symbols.next()? {
+        if let Ok(SymbolData::Public(public)) = symbol.parse() {
+            let what = format!("public {}", public.name);
+            problems.check_extent(model, &what, public.offset, 0);
+        }
+    }
+
+    problems.finish()
+}
+
+#[derive(Default)]
+struct Problems {
+    messages: Vec<String>,
+    count: usize,
+}
+
+impl Problems {
+    fn add(&mut self, message: String) {
+        self.count += 1;
+        if self.messages.len() < MAX_REPORTED_PROBLEMS {
+            self.messages.push(message);
+        }
+    }
+
+    /// Check that `size` bytes at `offset` lie inside one of the model's sections
+    fn check_extent(
+        &mut self,
+        model: &Model,
+        what: &str,
+        offset: PdbInternalSectionOffset,
+        size: u32,
+    ) {
+        let index = offset.section as usize;
+        let Some(section) = index.checked_sub(1).and_then(|i| model.sections.get(i)) else {
+            self.add(format!(
+                "{what} refers to section {index}, the image has {}",
+                model.sections.len()
+            ));
+            return;
+        };
+        let end = offset.offset as u64 + size as u64;
+        if end > section.size() as u64 {
+            self.add(format!(
+                "{what} at {index:04x}:{:08x} with size 0x{size:x} runs past the end of section {} (size 0x{:x})",
+                offset.offset,
+                section.name,
+                section.size()
+            ));
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.count == 0 {
+            return Ok(());
+        }
+        let mut message = format!(
+            "generated PDB failed validation with {} problem(s):",
+            self.count
+        );
+        for problem in &self.messages {
+            message.push_str("\n  ");
+            message.push_str(problem);
+        }
+        if self.count > self.messages.len() {
+            message.push_str(&format!(
+                "\n  ... and {} more",
+                self.count - self.messages.len()
+            ));
+        }
+        bail!(message)
+    }
+}
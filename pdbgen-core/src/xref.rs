@@ -0,0 +1,250 @@
+//! Cross-module references in the module streams of a committed PDB
+//!
+//! A module stream holds the module's symbols, its C11 and C13 line information, then its
+//! global references. Among the C13 debug subsections, DEBUG_S_CROSSSCOPEEXPORTS maps the ids
+//! a module exports to type indices, and DEBUG_S_CROSSSCOPEIMPORTS lists the ids a module uses
+//! from others, grouped by the exporting module's name as an offset into the `/names` string
+//! table. pdb-sdk writes no C13 subsections, so these are inserted after commit and the
+//! module's ModInfo record grown to match.
+
+use anyhow::{Context as _, Result, ensure};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tracing::info;
+
+use crate::dbi::{self, ModuleInfo};
+use crate::info::intern_names;
+use crate::model::{Model, Section};
+use crate::msf::Editor;
+
+const DEBUG_S_CROSSSCOPEIMPORTS: u32 = 0xf7;
+const DEBUG_S_CROSSSCOPEEXPORTS: u32 = 0xf8;
+/// First id a module exports, past the primitive range like type indices
+const FIRST_LOCAL_ID: u32 = 0x1000;
+// Kinds of the symbol records a function is emitted as
+const S_THUNK32: u16 = 0x1102;
+const S_LPROC32: u16 = 0x110f;
+const S_GPROC32: u16 = 0x1110;
+/// CV_SIGNATURE_C13 ahead of a module's symbol records
+const SIGNATURE_SIZE: usize = 4;
+
+/// Where a function was emitted
+#[derive(Clone, Copy)]
+struct Procedure {
+    /// Index into the modules
+    module: usize,
+    /// Type of the procedure record, 0 for thunks
+    type_index: u32,
+}
+
+/// Record the direct calls from one module into another that [`Model::functions`] describe
+pub(crate) fn add_cross_references(msf: &mut Editor, model: &Model) -> Result<()> {
+    let modules = dbi::modules(msf)?;
+    let mut procedures = HashMap::new();
+    for (index, module) in modules.iter().enumerate() {
+        let Some(stream) = module.stream else {
+            continue;
+        };
+        let symbols = msf.read_stream(stream, 0, module.symbol_bytes as usize)?;
+        for (rva, type_index) in procedures_in(&symbols, &model.sections) {
+            procedures.entry(rva).or_insert(Procedure {
+                module: index,
+                type_index,
+            });
+        }
+    }
+
+    // Ids by RVA for every exporting module, and the ids every importing module uses by the
+    // module exporting them
+    let mut exports: BTreeMap<usize, BTreeMap<u32, u32>> = BTreeMap::new();
+    let mut imports: BTreeMap<usize, BTreeMap<usize, BTreeSet<u32>>> = BTreeMap::new();
+    for function in &model.functions {
+        let Some(caller) = function
+            .ranges
+            .first()
+            .and_then(|range| procedures.get(&range.start))
+        else {
+            continue;
+        };
+        for call in &function.calls {
+            let Some(callee) = procedures.get(call) else {
+                continue;
+            };
+            if callee.module == caller.module {
+                continue;
+            }
+            let ids = exports.entry(callee.module).or_default();
+            let next = FIRST_LOCAL_ID + ids.len() as u32;
+            let id = *ids.entry(*call).or_insert(next);
+            imports
+                .entry(caller.module)
+                .or_default()
+                .entry(callee.module)
+                .or_default()
+                .insert(id);
+        }
+    }
+    if exports.is_empty() {
+        return Ok(());
+    }
+
+    let names: Vec<&str> = exports.keys().map(|&m| modules[m].name.as_str()).collect();
+    let name_offsets: HashMap<usize, u32> = exports
+        .keys()
+        .copied()
+        .zip(intern_names(msf, &names)?)
+        .collect();
+
+    let mut references = 0;
+    for (index, module) in modules.iter().enumerate() {
+        let mut subsections = Vec::new();
+        if let Some(ids) = exports.get(&index) {
+            let mut pairs: Vec<(u32, u32)> = ids
+                .iter()
+                .map(|(rva, &id)| (id, procedures[rva].type_index))
+                .collect();
+            pairs.sort_unstable();
+            let data: Vec<u8> = pairs
+                .iter()
+                .flat_map(|&(local, global)| [local, global])
+                .flat_map(u32::to_le_bytes)
+                .collect();
+            push_subsection(&mut subsections, DEBUG_S_CROSSSCOPEEXPORTS, &data);
+        }
+        if let Some(by_module) = imports.get(&index) {
+            let mut data = Vec::new();
+            for (exporter, ids) in by_module {
+                data.extend(name_offsets[exporter].to_le_bytes());
+                data.extend((ids.len() as u32).to_le_bytes());
+                data.extend(ids.iter().flat_map(|id| id.to_le_bytes()));
+                references += ids.len();
+            }
+            push_subsection(&mut subsections, DEBUG_S_CROSSSCOPEIMPORTS, &data);
+        }
+        if !subsections.is_empty() {
+            insert_subsections(msf, module, &subsections)
+                .with_context(|| format!("failed to extend module {}", module.name))?;
+        }
+    }
+    info!(
+        "Recorded {references} cross-module reference(s) into {} module(s)",
+        exports.len()
+    );
+    Ok(())
+}
+
+/// RVA and type index of every procedure and thunk record among a module's symbols
+fn procedures_in(symbols: &[u8], sections: &[Section]) -> Vec<(u32, u32)> {
+    let u16_at = |data: &[u8], at: usize| {
+        Some(u16::from_le_bytes(
+            data.get(at..at + 2)?.try_into().unwrap(),
+        ))
+    };
+    let u32_at = |data: &[u8], at: usize| {
+        Some(u32::from_le_bytes(
+            data.get(at..at + 4)?.try_into().unwrap(),
+        ))
+    };
+
+    let mut procedures = Vec::new();
+    let mut offset = SIGNATURE_SIZE;
+    while let (Some(len), Some(kind)) = (u16_at(symbols, offset), u16_at(symbols, offset + 2)) {
+        let Some(record) = symbols.get(offset + 4..offset + 2 + len as usize) else {
+            break;
+        };
+        // Offsets of the type, address and section fields after the parent, end and next links
+        let fields = match kind {
+            S_GPROC32 | S_LPROC32 => Some((Some(24), 28, 32)),
+            S_THUNK32 => Some((None, 12, 16)),
+            _ => None,
+        };
+        if let Some((type_at, offset_at, section_at)) = fields
+            && let (Some(address), Some(section)) =
+                (u32_at(record, offset_at), u16_at(record, section_at))
+            && let Some(section) = (section as usize)
+                .checked_sub(1)
+                .and_then(|index| sections.get(index))
+        {
+            let type_index = type_at.and_then(|at| u32_at(record, at)).unwrap_or(0);
+            procedures.push((section.virtual_address.wrapping_add(address), type_index));
+        }
+        offset += 2 + len as usize;
+    }
+    procedures
+}
+
+/// Append a C13 subsection of `kind` holding `data`, which is all 32 bit fields and so needs
+/// no padding
+fn push_subsection(subsections: &mut Vec<u8>, kind: u32, data: &[u8]) {
+    subsections.extend(kind.to_le_bytes());
+    subsections.extend((data.len() as u32).to_le_bytes());
+    subsections.extend(data);
+}
+
+/// Add `subsections` after the existing C13 subsections of `module`
+fn insert_subsections(msf: &mut Editor, module: &ModuleInfo, subsections: &[u8]) -> Result<()> {
+    let stream = module.stream.context("module has no stream")?;
+    let len = msf.stream_len(stream)?;
+    let mut data = msf.read_stream(stream, 0, len as usize)?;
+    let end = module.symbol_bytes as usize + module.c11_bytes as usize + module.c13_bytes as usize;
+    ensure!(
+        end <= data.len(),
+        "stream is shorter than its ModInfo record says"
+    );
+    let c13_bytes = u32::try_from(module.c13_bytes as usize + subsections.len())
+        .context("C13 subsections too large")?;
+    data.splice(end..end, subsections.iter().copied());
+    msf.replace_stream(stream, data)?;
+    dbi::set_c13_bytes(msf, module, c13_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A symbol record of `kind` with `fields` after the length and kind
+    fn record(kind: u16, fields: &[u8]) -> Vec<u8> {
+        let mut record = ((fields.len() + 2) as u16).to_le_bytes().to_vec();
+        record.extend(kind.to_le_bytes());
+        record.extend(fields);
+        record
+    }
+
+    #[test]
+    fn finds_procedures() {
+        let sections = [
+            Section {
+                virtual_address: 0x1000,
+                ..Default::default()
+            },
+            Section {
+                virtual_address: 0x8000,
+                ..Default::default()
+            },
+        ];
+        let mut proc_fields = vec![0; 24];
+        proc_fields.extend(0x1004u32.to_le_bytes());
+        proc_fields.extend(0x20u32.to_le_bytes());
+        proc_fields.extend(2u16.to_le_bytes());
+        proc_fields.extend(b"\0f\0");
+        let mut thunk_fields = vec![0; 12];
+        thunk_fields.extend(0x10u32.to_le_bytes());
+        thunk_fields.extend(1u16.to_le_bytes());
+        thunk_fields.extend(b"\0\0\0g\0\0");
+
+        let mut symbols = 4u32.to_le_bytes().to_vec();
+        symbols.extend(record(0x114c, &[1, 2, 3, 4, 5, 6]));
+        symbols.extend(record(S_GPROC32, &proc_fields));
+        symbols.extend(record(0x0006, &[]));
+        symbols.extend(record(S_THUNK32, &thunk_fields));
+        // Refers to a section the image doesn't have
+        thunk_fields[16] = 3;
+        symbols.extend(record(S_THUNK32, &thunk_fields));
+        // Cut short
+        symbols.extend(&record(S_LPROC32, &proc_fields)[..10]);
+
+        assert_eq!(
+            procedures_in(&symbols, &sections),
+            [(0x8020, 0x1004), (0x1010, 0)]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Collection and emission run end to end over a synthetic image, without a Binary Ninja core
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use pdb::{FallibleIterator as _, PDB, SymbolData};
+use pdbgen_core::reader::{FunctionInfo, MemoryReader, ViewSection};
+use pdbgen_core::{
+    LayoutRange, Options, Report, collect_model, image_rvas, is_generated_pdb, read_pdb_info,
+    write_pdb,
+};
+
+const BASE: u64 = 0x1_4000_0000;
+/// Offset of the "PE\0\0" signature, as e_lfanew gives it
+const PE_HEADER: usize = 0x80;
+const OPTIONAL_HEADER: usize = PE_HEADER + 24;
+const OPTIONAL_HEADER_SIZE: usize = 240;
+const SECTION_HEADERS: usize = OPTIONAL_HEADER + OPTIONAL_HEADER_SIZE;
+const PDB_GUID: usize = 0x300;
+const PDB_AGE: usize = 0x310;
+const TEXT_RVA: u32 = 0x1000;
+const TEXT_SIZE: u32 = 0x100;
+const TIMESTAMP: u32 = 0x6502_0304;
+const GUID: [u8; 16] = *b"pdbgen pipeline!";
+const AGE: u32 = 3;
+
+fn put(bytes: &mut [u8], offset: usize, value: &[u8]) {
+    bytes[offset..offset + value.len()].copy_from_slice(value);
+}
+
+/// Headers of a PE32+ x64 image with a single .text section, and the symbols Binary Ninja
+/// defines for them
+fn image() -> MemoryReader {
+    let mut headers = vec![0; 0x400];
+    put(&mut headers, 0, b"MZ");
+    put(&mut headers, 0x3c, &(PE_HEADER as u32).to_le_bytes());
+
+    put(&mut headers, PE_HEADER, b"PE\0\0");
+    put(&mut headers, PE_HEADER + 4, &0x8664u16.to_le_bytes());
+    put(&mut headers, PE_HEADER + 6, &1u16.to_le_bytes());
+    put(&mut headers, PE_HEADER + 8, &TIMESTAMP.to_le_bytes());
+    put(
+        &mut headers,
+        PE_HEADER + 20,
+        &(OPTIONAL_HEADER_SIZE as u16).to_le_bytes(),
+    );
+
+    put(&mut headers, OPTIONAL_HEADER, &0x20bu16.to_le_bytes());
+    // AddressOfEntryPoint, at the start of main
+    put(&mut headers, OPTIONAL_HEADER + 16, &TEXT_RVA.to_le_bytes());
+    // NumberOfRvaAndSizes, every directory left empty
+    put(&mut headers, OPTIONAL_HEADER + 108, &16u32.to_le_bytes());
+
+    put(&mut headers, SECTION_HEADERS, b".text\0\0\0");
+    put(&mut headers, SECTION_HEADERS + 8, &TEXT_SIZE.to_le_bytes());
+    put(&mut headers, SECTION_HEADERS + 12, &TEXT_RVA.to_le_bytes());
+    put(&mut headers, SECTION_HEADERS + 16, &0x200u32.to_le_bytes());
+    put(&mut headers, SECTION_HEADERS + 20, &0x400u32.to_le_bytes());
+    put(
+        &mut headers,
+        SECTION_HEADERS + 36,
+        &0x6000_0020u32.to_le_bytes(),
+    );
+
+    put(&mut headers, PDB_GUID, &GUID);
+    put(&mut headers, PDB_AGE, &AGE.to_le_bytes());
+
+    let text = BASE + TEXT_RVA as u64;
+    let function = |offset: u64, name: &str| FunctionInfo {
+        start: text + offset,
+        name: name.to_string(),
+        raw_name: name.to_string(),
+        full_name: name.to_string(),
+        ranges: vec![text + offset..text + offset + 0x10],
+        entry_length: 1,
+        ..Default::default()
+    };
+
+    MemoryReader {
+        start: BASE,
+        regions: [(BASE, headers), (text, vec![0xc3; TEXT_SIZE as usize])].into(),
+        symbols: [
+            ("__coff_header", PE_HEADER),
+            ("__section_headers", SECTION_HEADERS),
+            ("PDBGuid", PDB_GUID),
+            ("PDBAge", PDB_AGE),
+        ]
+        .into_iter()
+        .map(|(name, offset)| (name.to_string(), BASE + offset as u64))
+        .collect(),
+        functions: vec![function(0, "main"), function(0x20, "helper")],
+        sections: vec![ViewSection {
+            name: ".text".to_string(),
+            range: text..text + TEXT_SIZE as u64,
+            code: true,
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+fn output(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("pdbgen-pipeline-{}-{name}.pdb", std::process::id()))
+}
+
+#[test]
+fn writes_a_pdb_matching_the_image() {
+    let options = Options::default();
+    let mut report = Report::default();
+    let model = collect_model(&image(), &options, None, &mut report, &mut ()).unwrap();
+    assert_eq!(model.machine, 0x8664);
+    assert_eq!(model.functions.len(), 2);
+
+    let path = output("match");
+    write_pdb(&model, &options, &path, &mut report, &mut ()).unwrap();
+
+    let info = read_pdb_info(&path).unwrap();
+    assert_eq!(
+        (info.guid, info.age, info.timestamp),
+        (GUID, AGE, TIMESTAMP)
+    );
+    assert!(is_generated_pdb(&path).unwrap());
+
+    let mut pdb = PDB::open(fs::File::open(&path).unwrap()).unwrap();
+    let mut publics = BTreeSet::new();
+    let global_symbols = pdb.global_symbols().unwrap();
+    let mut symbols = global_symbols.iter();
+    while let Some(symbol) = symbols.next().unwrap() {
+        if let Ok(SymbolData::Public(public)) = symbol.parse() {
+            publics.insert(public.name.to_string().into_owned());
+        }
+    }
+    assert_eq!(publics, BTreeSet::from(["helper".into(), "main".into()]));
+
+    let mut procedures = BTreeSet::new();
+    let dbi = pdb.debug_information().unwrap();
+    let mut modules = dbi.modules().unwrap();
+    while let Some(module) = modules.next().unwrap() {
+        let Some(module_info) = pdb.module_info(&module).unwrap() else {
+            continue;
+        };
+        let mut symbols = module_info.symbols().unwrap();
+        while let Some(symbol) = symbols.next().unwrap() {
+            if let Ok(SymbolData::Procedure(procedure)) = symbol.parse() {
+                procedures.insert(procedure.name.to_string().into_owned());
+            }
+        }
+    }
+    assert_eq!(procedures, publics);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn derives_a_guid_without_a_codeview_entry() {
+    let mut image = image();
+    image.symbols.remove("PDBGuid");
+    image.symbols.remove("PDBAge");
+
+    let options = Options::default();
+    let mut report = Report::default();
+    let model = collect_model(&image, &options, None, &mut report, &mut ()).unwrap();
+    assert_eq!(model.info.age, 1);
+    assert_ne!(model.info.guid, [0; 16]);
+    assert!(!report.warnings.is_empty());
+
+    let again = collect_model(&image, &options, None, &mut Report::default(), &mut ()).unwrap();
+    assert_eq!(again.info.guid, model.info.guid);
+
+    let path = output("derived");
+    write_pdb(&model, &options, &path, &mut report, &mut ()).unwrap();
+    assert_eq!(read_pdb_info(&path).unwrap().guid, model.info.guid);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn moves_view_rvas_to_the_image() {
+    let image = image();
+    assert_eq!(
+        image_rvas(&image, &Options::default(), &[0x1020, 0x9000]).unwrap(),
+        [0x1020, 0x9000]
+    );
+
+    let options = Options {
+        layout_ranges: vec![LayoutRange {
+            source: 0x5000,
+            target: TEXT_RVA,
+            size: TEXT_SIZE,
+        }],
+        ..Default::default()
+    };
+    assert_eq!(
+        image_rvas(&image, &options, &[0x5020, 0x1020]).unwrap(),
+        [0x1020]
+    );
+}
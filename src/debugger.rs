@@ -0,0 +1,105 @@
+//! The module list of a process attached to Binary Ninja's debugger
+//!
+//! The debugger is a plugin of its own with a C API the Rust bindings don't wrap, so the few
+//! entry points needed are looked up in its library when a session command runs. Without the
+//! debugger installed those commands fail with an error instead of pdbgen failing to load.
+
+use anyhow::{Context as _, Result, ensure};
+use binaryninja::binary_view::BinaryView;
+use libloading::{Library, Symbol};
+use std::ffi::{CStr, c_char, c_void};
+use std::path::PathBuf;
+
+#[cfg(windows)]
+const DEBUGGER_CORE: &str = "debuggercore.dll";
+#[cfg(target_os = "macos")]
+const DEBUGGER_CORE: &str = "libdebuggercore.dylib";
+#[cfg(all(unix, not(target_os = "macos")))]
+const DEBUGGER_CORE: &str = "libdebuggercore.so";
+
+/// BNDebugModule, of which only some fields are read
+#[repr(C)]
+#[allow(dead_code)]
+struct RawModule {
+    name: *mut c_char,
+    short_name: *mut c_char,
+    address: u64,
+    size: usize,
+    loaded: bool,
+}
+
+type GetController = unsafe extern "C" fn(*mut c_void) -> *mut c_void;
+type FreeController = unsafe extern "C" fn(*mut c_void);
+type IsConnected = unsafe extern "C" fn(*mut c_void) -> bool;
+type GetModules = unsafe extern "C" fn(*mut c_void, *mut usize) -> *mut RawModule;
+type FreeModules = unsafe extern "C" fn(*mut RawModule, usize);
+
+/// A module loaded in the debugged process
+#[derive(Debug, Clone)]
+pub struct DebugModule {
+    /// Path of the module as the target reports it
+    pub path: PathBuf,
+    /// Address the module is loaded at
+    pub address: u64,
+}
+
+pub struct Debugger {
+    library: Library,
+}
+
+impl Debugger {
+    pub fn load() -> Result<Self> {
+        let path = binaryninja::bundled_plugin_directory()
+            .map(|dir| dir.join(DEBUGGER_CORE))
+            .unwrap_or_else(|_| DEBUGGER_CORE.into());
+        // SAFETY: Binary Ninja already loaded the debugger core, this only takes another
+        // reference to it and runs no initialization
+        let library = unsafe { Library::new(&path) }
+            .with_context(|| format!("failed to load the debugger from {}", path.display()))?;
+        Ok(Self { library })
+    }
+
+    /// Modules loaded in the process debugged through `view`, an error if none is attached
+    pub fn modules(&self, view: &BinaryView) -> Result<Vec<DebugModule>> {
+        // SAFETY: the signatures match the debugger's ffi.h, the controller is freed once and
+        // the module array is only read before being handed back
+        unsafe {
+            let get_controller: Symbol<GetController> =
+                self.library.get(b"BNGetDebuggerController\0")?;
+            let free_controller: Symbol<FreeController> =
+                self.library.get(b"BNDebuggerFreeController\0")?;
+            let is_connected: Symbol<IsConnected> = self.library.get(b"BNDebuggerIsConnected\0")?;
+            let get_modules: Symbol<GetModules> = self.library.get(b"BNDebuggerGetModules\0")?;
+            let free_modules: Symbol<FreeModules> = self.library.get(b"BNDebuggerFreeModules\0")?;
+
+            let controller = get_controller(view.handle as *mut c_void);
+            ensure!(
+                !controller.is_null(),
+                "no debugger controller for this view"
+            );
+
+            let connected = is_connected(controller);
+            let mut modules = Vec::new();
+            if connected {
+                let mut count = 0;
+                let raw = get_modules(controller, &mut count);
+                if !raw.is_null() {
+                    modules = std::slice::from_raw_parts(raw, count)
+                        .iter()
+                        .map(|module| DebugModule {
+                            path: PathBuf::from(
+                                CStr::from_ptr(module.name).to_string_lossy().into_owned(),
+                            ),
+                            address: module.address,
+                        })
+                        .collect();
+                    free_modules(raw, count);
+                }
+            }
+            free_controller(controller);
+
+            ensure!(connected, "the debugger is not attached to a process");
+            Ok(modules)
+        }
+    }
+}
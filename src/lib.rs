@@ -1,32 +1,66 @@
-use anyhow::{Context as _, Result};
+use anyhow::{Context as _, Result, bail, ensure};
 use binaryninja::{
-    binary_view::{BinaryView, BinaryViewBase, BinaryViewExt},
-    command::{self, Command},
-    interaction::{MessageBoxButtonSet, MessageBoxIcon},
-};
-use pdb::{FallibleIterator as _, PDB, SymbolData};
-use pdb_sdk::builders::{ModuleBuilder, PdbBuilder};
-use pdb_sdk::codeview::DataRegionOffset;
-use pdb_sdk::codeview::symbols::{Procedure, ProcedureProperties, SymbolRecord};
-use pdb_sdk::codeview::types::{CallingConvention, FunctionProperties, TypeRecord};
-use pdb_sdk::dbi::{SectionContrib, SectionHeader};
-use pdb_sdk::utils::StrBuf;
-use pdb_sdk::{
-    Guid,
-    codeview::symbols::{Public, PublicProperties},
+    background_task::BackgroundTask,
+    binary_view::{AnalysisState, BinaryView, BinaryViewExt},
+    command::{self, Command, RangeCommand},
+    interaction::{MessageBoxButtonResult, MessageBoxButtonSet, MessageBoxIcon, show_message_box},
+    rc::Ref,
 };
+use pdbgen_core::{Cache, Cancelled, Eta, Model, Options, Progress, Report, collect_model};
+use std::fs;
 use std::io::BufWriter;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::{collections::HashMap, fs};
+use std::thread;
+use std::time::Duration;
 use tracing::{error, info, warn};
 
+use crate::debugger::Debugger;
+use crate::paths::{exe_path, same_file};
+use crate::reader::{BinjaReader, SelectionReader};
+
+mod debugger;
+mod paths;
+mod reader;
+mod settings;
+
+/// How often the state of analysis is checked while waiting for it
+const ANALYSIS_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
 #[unsafe(no_mangle)]
 pub extern "C" fn CorePluginInit() -> bool {
     binaryninja::tracing_init!("pdbgen");
 
+    settings::register();
+
     info!("pdbgen loaded");
 
     command::register_command("Generate PDB", "Generate PDB for .exe", GenPdb {});
+    command::register_command_for_range(
+        "Update PDB for Selection",
+        "Regenerate the PDB with only the selected functions collected again",
+        UpdatePdbForSelection {},
+    );
+    command::register_command(
+        "Export PDB Model as JSON",
+        "Dump the model pdbgen would build a PDB from",
+        ExportModel {},
+    );
+    command::register_command(
+        "Generate PDBs for Debugger Session",
+        "Generate a PDB for every module loaded in the debugged process that has a database",
+        GenSessionPdbs {},
+    );
+    command::register_command(
+        "Sync Debug Directory",
+        "Update the timestamps and CodeView sizes in the executable's debug directory after patching",
+        SyncDebugDirectory {},
+    );
+    command::register_command(
+        "Strip CodeView Debug Entries",
+        "Remove the executable's debug directory entries naming a PDB",
+        StripCodeView {},
+    );
 
     true
 }
@@ -34,457 +68,585 @@ pub extern "C" fn CorePluginInit() -> bool {
 struct GenPdb {}
 impl Command for GenPdb {
     fn action(&self, view: &BinaryView) {
-        match gen_pdb(view) {
-            Ok(_) => info!("PDB generated successfully"),
-            Err(err) => error!("PDB generation failed {err:?}"),
-        };
+        let view = view.to_owned();
+        thread::spawn(move || {
+            let task = BackgroundTask::new("Generating PDB", true);
+            let mut progress = TaskProgress::new(&task, "Generating PDB");
+
+            match gen_pdb(&view, &mut progress) {
+                Ok(_) => info!("PDB generated successfully"),
+                Err(err) if err.is::<Cancelled>() => info!("PDB generation cancelled"),
+                Err(err) => error!("PDB generation failed {err:?}"),
+            };
+
+            task.finish();
+        });
     }
 
     fn valid(&self, view: &BinaryView) -> bool {
-        view.view_type() == "PE"
-            && !view.symbols_by_name("__coff_header").is_empty()
-            && !view.symbols_by_name("PDBGuid").is_empty()
-            && !view.symbols_by_name("PDBAge").is_empty()
+        // PDBGuid and PDBAge are optional so images without a CodeView entry (MinGW) qualify
+        view.view_type() == "PE" && !view.symbols_by_name("__coff_header").is_empty()
     }
 }
 
-#[derive(Debug)]
-struct PdbInfo {
-    age: u32,
-    timestamp: u32,
-    guid: [u8; 16],
-}
+struct UpdatePdbForSelection {}
+impl RangeCommand for UpdatePdbForSelection {
+    fn action(&self, view: &BinaryView, range: Range<u64>) {
+        let view = view.to_owned();
+        thread::spawn(move || {
+            let task = BackgroundTask::new("Updating PDB", true);
+            let mut progress = TaskProgress::new(&task, "Updating PDB");
+
+            match gen_delta_pdb(&view, range, &mut progress) {
+                Ok(()) => info!("PDB updated successfully"),
+                Err(err) if err.is::<Cancelled>() => info!("PDB update cancelled"),
+                Err(err) => error!("PDB update failed {err:?}"),
+            };
+
+            task.finish();
+        });
+    }
 
-fn get_pdbinfo(view: &BinaryView) -> Result<PdbInfo> {
-    let coff_header_sym = view
-        .symbol_by_raw_name("__coff_header")
-        .context("could not find __coff_header symbol")?;
-    let pdb_age_sym = view
-        .symbol_by_raw_name("PDBAge")
-        .context("could not find PDBAge symbol")?;
-    let pdb_guid_sym = view
-        .symbol_by_raw_name("PDBGuid")
-        .context("could not find PDBGuid symbol")?;
-
-    let timestamp_bytes = view.read_vec(coff_header_sym.address() + 0x8, 4);
-    let timestamp = u32::from_le_bytes(timestamp_bytes.try_into().unwrap());
-
-    let age_bytes = view.read_vec(pdb_age_sym.address(), 4);
-    let age = u32::from_le_bytes(age_bytes.try_into().unwrap());
-
-    let guid_bytes = view.read_vec(pdb_guid_sym.address(), 16);
-    let guid = guid_bytes.try_into().unwrap();
-
-    Ok(PdbInfo {
-        age,
-        timestamp,
-        guid,
-    })
+    fn valid(&self, view: &BinaryView, _range: Range<u64>) -> bool {
+        GenPdb {}.valid(view)
+    }
 }
 
-/// Check if a PDB file contains a global symbol with an EnvBlock containing "pdbgen_canary"
-/// If so the PDB was generated by this tool and allow replacing
-fn should_replace_pdb(pdb_path: &Path) -> Result<bool> {
-    let file = fs::File::open(pdb_path)?;
+struct GenSessionPdbs {}
+impl Command for GenSessionPdbs {
+    fn action(&self, view: &BinaryView) {
+        let view = view.to_owned();
+        thread::spawn(move || {
+            let task = BackgroundTask::new("Generating PDBs for debugger session", true);
+            let mut progress = TaskProgress::new(&task, "Generating PDBs for debugger session");
+
+            match gen_session_pdbs(&view, &mut progress) {
+                Ok(count) => info!("Generated PDBs for {count} modules"),
+                Err(err) if err.is::<Cancelled>() => info!("PDB generation cancelled"),
+                Err(err) => error!("PDB generation for the debugger session failed {err:?}"),
+            };
+
+            task.finish();
+        });
+    }
 
-    let mut pdb =
-        PDB::open(file).with_context(|| format!("Failed to parse PDB file: {pdb_path:?}"))?;
+    fn valid(&self, _view: &BinaryView) -> bool {
+        true
+    }
+}
 
-    let global_symbols = pdb.global_symbols()?;
-    let mut symbols = global_symbols.iter();
+/// Shows progress in a background task and forwards its cancel button
+struct TaskProgress<'a> {
+    task: &'a BackgroundTask,
+    eta: Eta<Box<dyn FnMut(&str) + 'a>>,
+}
 
-    while let Some(symbol) = symbols.next()? {
-        if let Ok(SymbolData::EnvBlock(env_block)) = symbol.parse() {
-            for entry in &env_block.entries {
-                if entry.to_string().contains("pdbgen_canary") {
-                    return Ok(true);
-                }
-            }
+impl<'a> TaskProgress<'a> {
+    fn new(task: &'a BackgroundTask, title: &'a str) -> Self {
+        Self {
+            task,
+            eta: Eta::new(Box::new(move |text: &str| {
+                task.set_progress_text(&format!("{title} - {text}"))
+            })),
         }
     }
+}
+
+impl Progress for TaskProgress<'_> {
+    fn update(&mut self, phase: &str, done: usize, total: usize) {
+        self.eta.update(phase, done, total);
+    }
 
-    Ok(false)
+    fn is_cancelled(&self) -> bool {
+        self.task.is_cancelled()
+    }
 }
 
-fn gen_pdb(view: &BinaryView) -> Result<()> {
-    let pdb_info = get_pdbinfo(view)?;
-    info!("PdbInfo = {pdb_info:?}");
+struct ExportModel {}
+impl Command for ExportModel {
+    fn action(&self, view: &BinaryView) {
+        match export_model(view) {
+            Ok(_) => info!("Model exported successfully"),
+            Err(err) => error!("Model export failed {err:?}"),
+        };
+    }
 
-    let filename = view.file().file_path();
-    let filename = filename.to_string_lossy();
-    let exe_path = PathBuf::from(filename.strip_suffix(".bndb").unwrap_or(&filename));
-    let pdb_path = exe_path.with_extension("pdb");
+    fn valid(&self, view: &BinaryView) -> bool {
+        GenPdb {}.valid(view)
+    }
+}
 
-    // Check if PDB already exists and whether we should replace it
-    if pdb_path.exists() {
-        let should_replace = should_replace_pdb(&pdb_path).unwrap_or(false);
-
-        if !should_replace {
-            let result = binaryninja::interaction::show_message_box(
-                "PDB Already Exists",
-                &format!(
-                    "A PDB file already exists at:\n{}\n\nThis PDB was not generated by pdbgen. Do you want to overwrite it?",
-                    pdb_path.display()
-                ),
-                MessageBoxButtonSet::YesNoButtonSet,
-                MessageBoxIcon::WarningIcon,
-            );
-
-            if result != binaryninja::interaction::MessageBoxButtonResult::YesButton {
-                info!("User cancelled PDB generation - file already exists");
-                return Ok(());
-            }
-        }
+struct SyncDebugDirectory {}
+impl Command for SyncDebugDirectory {
+    fn action(&self, view: &BinaryView) {
+        let exe_path = exe_path(view);
+        match pdbgen_core::sync_debug_directory(&exe_path) {
+            Ok(0) => info!(
+                "Debug directory of {} already up to date",
+                exe_path.display()
+            ),
+            Ok(count) => info!(
+                "Updated {count} debug directory entries of {}",
+                exe_path.display()
+            ),
+            Err(err) => error!("Debug directory update failed {err:?}"),
+        };
     }
 
-    let mut builder = PdbBuilder::default();
-    builder.info().guid(Guid(pdb_info.guid));
-    builder.info().age(pdb_info.age);
-    builder.info().signature(pdb_info.timestamp);
-
-    let section_info = build_sections(view, &mut builder)?;
-    build_functions(view, &mut builder, &section_info)?;
-
-    // Add pdbgen_canary marker to identify PDBs generated by this tool
-    builder
-        .dbi()
-        .symbols()
-        .finish_publics()
-        .add(SymbolRecord::EnvBlock {
-            reserved: 0,
-            fields: vec![StrBuf::new(
-                "PDB generated by: binja_pdbgen/pdbgen_canary https://github.com/trumank/binja_pdbgen\0",
-            )],
-        });
+    fn valid(&self, view: &BinaryView) -> bool {
+        GenPdb {}.valid(view)
+    }
+}
+
+struct StripCodeView {}
+impl Command for StripCodeView {
+    fn action(&self, view: &BinaryView) {
+        let exe_path = exe_path(view);
+        let result = show_message_box(
+            "Strip CodeView Debug Entries",
+            &format!(
+                "Remove the debug directory entries naming a PDB from:\n{}\n\nDebuggers will no longer look for a matching PDB on their own. Invalidates any Authenticode signature.",
+                exe_path.display()
+            ),
+            MessageBoxButtonSet::YesNoButtonSet,
+            MessageBoxIcon::WarningIcon,
+        );
+        if result != MessageBoxButtonResult::YesButton {
+            return;
+        }
 
-    info!("Writing PDB to: {}", pdb_path.display());
+        match pdbgen_core::strip_codeview(&exe_path) {
+            Ok(0) => info!("{} has no CodeView debug entries", exe_path.display()),
+            Ok(count) => info!(
+                "Removed {count} CodeView debug entries from {}",
+                exe_path.display()
+            ),
+            Err(err) => error!("Stripping CodeView debug entries failed {err:?}"),
+        };
+    }
 
-    let output = BufWriter::new(fs::File::create(&pdb_path)?);
-    builder.commit(output)?;
+    fn valid(&self, view: &BinaryView) -> bool {
+        GenPdb {}.valid(view)
+    }
+}
 
-    info!("PDB written successfully to: {}", pdb_path.display());
+fn export_model(view: &BinaryView) -> Result<()> {
+    let json_path = exe_path(view).with_extension("pdbgen.json");
+
+    let mut report = Report::default();
+    let options = settings::load_options();
+    let model = collect_model(
+        &BinjaReader(view, &options),
+        &options,
+        None,
+        &mut report,
+        &mut (),
+    )?;
+
+    info!("Writing model to: {}", json_path.display());
+    let output = BufWriter::new(
+        fs::File::create(&json_path)
+            .with_context(|| format!("failed to create {}", json_path.display()))?,
+    );
+    model.to_json(output)?;
+    report.log_summary();
 
     Ok(())
 }
 
-#[derive(Debug)]
-struct SectionInfo {
-    name: String,
-    index: u16,
-    virtual_address: u32,
-    virtual_size: u32,
-    characteristics: u32,
-}
+fn gen_pdb(view: &BinaryView, progress: &mut dyn Progress) -> Result<()> {
+    let exe_path = exe_path(view);
+    let pdb_path = exe_path.with_extension("pdb");
 
-fn build_sections(view: &BinaryView, builder: &mut PdbBuilder) -> Result<Vec<SectionInfo>> {
-    let section_headers_sym = view
-        .symbol_by_raw_name("__section_headers")
-        .context("could not find __section_headers symbol")?;
-
-    let section_headers_array = view
-        .data_variable_at_address(section_headers_sym.address())
-        .context("could not find section headers data variable")?
-        .ty
-        .contents;
-
-    let num_sections = section_headers_array.count();
-
-    let section_header_type = section_headers_array
-        .child_type()
-        .context("could not get section header type")?
-        .contents
-        .get_named_type_reference()
-        .context("could not get named type reference")?
-        .target(view)
-        .context("could not resolve type reference")?;
-
-    let section_header_struct = section_header_type
-        .get_structure()
-        .context("Section_Header is not a structure type")?;
-
-    let member_offsets: std::collections::HashMap<String, u64> = section_header_struct
-        .members()
-        .iter()
-        .map(|m| (m.name.to_string(), m.offset))
-        .collect();
+    let mut report = Report::default();
+
+    let hold = settle_analysis(view, progress)?;
+    let cache_path = Cache::path_for(&pdb_path);
+    let options = settings::load_options();
+    let mut cache =
+        settings::get_bool(settings::INCREMENTAL).then(|| Cache::load(&cache_path, &options));
+    let mut model = collect_model(
+        &BinjaReader(view, &options),
+        &options,
+        cache.as_mut(),
+        &mut report,
+        progress,
+    )?;
+    drop(hold);
+
+    if let Some(cache) = &cache
+        && pdb_path.exists()
+        && cache.is_written(&model, &options)
+        && pdbgen_core::is_generated_pdb(&pdb_path).unwrap_or(false)
+    {
+        info!(
+            "{} already matches the database, leaving it in place",
+            pdb_path.display()
+        );
+        cache.store(&cache_path);
+        return Ok(());
+    }
 
-    let section_headers_addr = section_headers_sym.address();
-    let section_header_size = section_header_type.width();
-
-    let mut sections = Vec::new();
-
-    for i in 0..num_sections {
-        let header_addr = section_headers_addr + i * section_header_size;
-
-        let mut name = [0u8; 8];
-        let name_offset = member_offsets.get("name").context("missing 'name' field")?;
-        let name_bytes = view.read_vec(header_addr + name_offset, 8);
-        name.copy_from_slice(&name_bytes[..8]);
-
-        let virtual_size = read_u32_field(view, header_addr, &member_offsets, "virtualSize")?;
-        let virtual_address = read_u32_field(view, header_addr, &member_offsets, "virtualAddress")?;
-        let size_of_raw_data = read_u32_field(view, header_addr, &member_offsets, "sizeOfRawData")?;
-        let pointer_to_raw_data =
-            read_u32_field(view, header_addr, &member_offsets, "pointerToRawData")?;
-        let pointer_to_relocations =
-            read_u32_field(view, header_addr, &member_offsets, "pointerToRelocations")?;
-        let pointer_to_line_numbers =
-            read_u32_field(view, header_addr, &member_offsets, "pointerToLineNumbers")?;
-        let number_of_relocations =
-            read_u16_field(view, header_addr, &member_offsets, "numberOfRelocations")?;
-        let number_of_line_numbers =
-            read_u16_field(view, header_addr, &member_offsets, "numberOfLineNumbers")?;
-        let characteristics =
-            read_u32_field(view, header_addr, &member_offsets, "characteristics")?;
-
-        let name_str = std::str::from_utf8(&name)
-            .unwrap_or("<invalid>")
-            .trim_end_matches('\0');
-        info!("Adding section: {name_str} (VA: 0x{virtual_address:x}, Size: 0x{virtual_size:x})",);
-
-        sections.push(SectionInfo {
-            name: name_str.to_string(),
-            index: (i as u16) + 1, // Section indices are 1-based in PDB
-            virtual_address,
-            virtual_size,
-            characteristics,
-        });
+    let existing = match pdb_path.exists() {
+        true => resolve_existing_pdb(&pdb_path, &exe_path, &model)?,
+        false => ExistingPdb::Replace,
+    };
+    let bumped_age = match existing {
+        ExistingPdb::Keep => {
+            info!("User cancelled PDB generation - file already exists");
+            return Ok(());
+        }
+        ExistingPdb::Replace => None,
+        ExistingPdb::BumpAge(age) => {
+            model.info.age = age;
+            Some(age)
+        }
+    };
 
-        builder.dbi().add_section_header(SectionHeader {
-            name,
-            virtual_size,
-            virtual_address,
-            size_of_raw_data,
-            pointer_to_raw_data,
-            pointer_to_relocations,
-            pointer_to_line_numbers,
-            number_of_relocations,
-            number_of_line_numbers,
-            characteristics,
-        });
+    pdbgen_core::write_pdb(&model, &options, &pdb_path, &mut report, progress)?;
+    // Only once a PDB asking for the new age is in place, so a failed or cancelled run never
+    // leaves the image pointing at a PDB that doesn't exist
+    if let Some(age) = bumped_age {
+        info!("Bumping the age of {} to {age}", exe_path.display());
+        pdbgen_core::patch_codeview_age(&exe_path, age).with_context(|| {
+            format!(
+                "the PDB was written with age {age} but {} could not be updated to match",
+                exe_path.display()
+            )
+        })?;
+    }
+    if let Some(cache) = &mut cache {
+        cache.set_written(&model, &options);
+        cache.store(&cache_path);
+    }
+    report.log_summary();
+    if settings::get_bool(settings::PROFILE) {
+        report.log_timings();
     }
 
-    Ok(sections)
+    Ok(())
 }
 
-fn merge_function_ranges(
-    ranges: &[std::ops::Range<u64>],
-    max_gap: u64,
-    view: &BinaryView,
-    current_function: &binaryninja::function::Function,
-) -> Vec<std::ops::Range<u64>> {
-    if ranges.is_empty() {
-        return Vec::new();
+/// Wait for auto-analysis to finish or hold it where it is, as
+/// [`settings::INCOMPLETE_ANALYSIS`] says, rather than collect from functions that change
+/// underneath the collection
+///
+/// Analysis stays on hold until the returned guard is dropped, which callers do once the model
+/// is collected.
+fn settle_analysis(view: &BinaryView, progress: &mut dyn Progress) -> Result<Option<AnalysisHold>> {
+    if analysis_settled(view) {
+        return Ok(None);
     }
+    let hold = match settings::get_string(settings::INCOMPLETE_ANALYSIS).as_str() {
+        "snapshot" => {
+            info!("Holding analysis while collecting");
+            Some(AnalysisHold::new(view))
+        }
+        _ => {
+            info!("Waiting for analysis to finish");
+            None
+        }
+    };
 
-    let mut sorted_ranges: Vec<_> = ranges.to_vec();
-    sorted_ranges.sort_by_key(|r| r.start);
-
-    let mut merged = Vec::new();
-    let mut current_start = sorted_ranges[0].start;
-    let mut current_end = sorted_ranges[0].end;
+    // A hold only takes effect once the work already under way is done
+    while !analysis_settled(view) {
+        let analysis = view.analysis_progress();
+        progress.update("Waiting for analysis", analysis.count, analysis.total);
+        progress.check_cancelled()?;
+        thread::sleep(ANALYSIS_POLL_INTERVAL);
+    }
+    if hold.is_some() {
+        warn!(
+            "Analysis is incomplete, generating from the {} function(s) found so far",
+            view.functions().len()
+        );
+    }
+    Ok(hold)
+}
 
-    for range in sorted_ranges.iter().skip(1) {
-        let gap_start = current_end;
-        let gap_end = range.start;
-        let gap_size = gap_end - gap_start;
+/// Keeps analysis on hold, resuming it when dropped
+struct AnalysisHold(Ref<BinaryView>);
 
-        if gap_size <= max_gap {
-            // Check if any other function occupies the gap
-            let mut gap_occupied = false;
-            for addr in gap_start..gap_end {
-                if view
-                    .functions_containing(addr)
-                    .iter()
-                    .any(|f| &*f != current_function)
-                {
-                    gap_occupied = true;
-                    break;
-                }
-            }
+impl AnalysisHold {
+    fn new(view: &BinaryView) -> Self {
+        view.set_analysis_hold(true);
+        Self(view.to_owned())
+    }
+}
 
-            if !gap_occupied {
-                current_end = current_end.max(range.end);
-            } else {
-                merged.push(current_start..current_end);
-                current_start = range.start;
-                current_end = range.end;
-            }
-        } else {
-            merged.push(current_start..current_end);
-            current_start = range.start;
-            current_end = range.end;
-        }
+impl Drop for AnalysisHold {
+    fn drop(&mut self) {
+        self.0.set_analysis_hold(false);
+        self.0.update_analysis();
     }
+}
 
-    merged.push(current_start..current_end);
-    merged
+/// Whether analysis is done, or held by the user and so won't finish by waiting
+fn analysis_settled(view: &BinaryView) -> bool {
+    matches!(
+        view.analysis_progress().state,
+        AnalysisState::IdleState | AnalysisState::HoldState
+    )
 }
 
-fn build_functions(
-    view: &BinaryView,
-    builder: &mut PdbBuilder,
-    sections: &[SectionInfo],
-) -> Result<()> {
-    let void_fn_type = {
-        let tpi = builder.tpi();
-
-        let arg_list = tpi.add(
-            "args",
-            TypeRecord::ArgList {
-                count: 0,
-                arg_list: vec![],
-            },
+/// Update the PDB with the functions in `range`, or the function containing it when it is
+/// empty, collected again
+///
+/// The rest comes from the incremental cache of the last full run, so renaming a function and
+/// stepping into it again takes as long as collecting that function. Without a cache collected
+/// with the current options the whole PDB is generated instead.
+fn gen_delta_pdb(view: &BinaryView, range: Range<u64>, progress: &mut dyn Progress) -> Result<()> {
+    let exe_path = exe_path(view);
+    let pdb_path = exe_path.with_extension("pdb");
+    if pdb_path.exists() && !pdbgen_core::is_generated_pdb(&pdb_path).unwrap_or(false) {
+        bail!(
+            "{} was not generated by pdbgen, run Generate PDB to replace it",
+            pdb_path.display()
         );
+    }
 
-        tpi.add(
-            "void_func",
-            TypeRecord::Procedure {
-                return_type: None,
-                calling_conv: CallingConvention::NearC,
-                properties: FunctionProperties::new(),
-                arg_count: 0,
-                arg_list,
-            },
-        )
-    };
+    let options = settings::load_options();
+    let cache_path = Cache::path_for(&pdb_path);
+    let mut cache = Cache::load(&cache_path, &options);
+    if cache.is_empty() {
+        info!(
+            "No incremental cache collected with the current settings at {}, generating the \
+             whole PDB",
+            cache_path.display()
+        );
+        if !settings::get_bool(settings::INCREMENTAL) {
+            info!("Turn on pdbgen.incremental for later updates to only collect the selection");
+        }
+        return gen_pdb(view, progress);
+    }
 
-    let base_address = view.start();
-    let mut functions_by_section: HashMap<u16, Vec<_>> = HashMap::new();
+    let hold = settle_analysis(view, progress)?;
 
-    let func_iter = view.functions();
-    for function in &func_iter {
-        let func_addr = function.start();
+    let functions: Vec<_> = match range.is_empty() {
+        true => view
+            .functions_containing(range.start)
+            .iter()
+            .map(|f| f.to_owned())
+            .collect(),
+        false => view
+            .functions()
+            .iter()
+            .filter(|f| {
+                f.address_ranges()
+                    .iter()
+                    .any(|r| r.start < range.end && range.start < r.end)
+            })
+            .map(|f| f.to_owned())
+            .collect(),
+    };
+    ensure!(!functions.is_empty(), "no function is selected");
+    let replaced: Vec<u32> = functions
+        .iter()
+        .filter_map(|f| u32::try_from(f.start().wrapping_sub(view.start())).ok())
+        .collect();
 
-        for section in sections {
-            let section_start = base_address + section.virtual_address as u64;
-            let section_end = section_start + section.virtual_size as u64;
+    let mut report = Report::default();
+    let reader = SelectionReader {
+        reader: BinjaReader(view, &options),
+        functions,
+    };
+    // The cache holds functions at their RVAs in the image, which a layout mapping can move
+    let replaced = pdbgen_core::image_rvas(&reader, &options, &replaced)?;
+    let delta = collect_model(&reader, &options, None, &mut report, progress)?;
+    drop(hold);
+    let model = pdbgen_core::merge_delta(&mut cache, delta, &replaced, &options)?;
+
+    pdbgen_core::write_pdb(&model, &options, &pdb_path, &mut report, progress)?;
+    cache.set_written(&model, &options);
+    cache.store(&cache_path);
+    report.log_summary();
+    Ok(())
+}
 
-            if (section_start..section_end).contains(&func_addr) {
-                functions_by_section
-                    .entry(section.index)
-                    .or_default()
-                    .push(function);
-                break;
+/// Generate a PDB for every module of the process the debugger is attached to, returning how
+/// many were written
+///
+/// Modules are matched to the view the command ran in by path, or to a database saved next to
+/// them (`<module>.bndb`). Modules without one are skipped rather than analyzed from scratch,
+/// which for system DLLs would take far longer than the debugging session is worth.
+fn gen_session_pdbs(view: &BinaryView, progress: &mut dyn Progress) -> Result<usize> {
+    let modules = Debugger::load()?.modules(view)?;
+    let options = settings::load_options();
+    let store = settings::get_string(settings::SYMBOL_STORE);
+    let own_path = exe_path(view);
+
+    let mut generated = 0;
+    for module in &modules {
+        progress.check_cancelled()?;
+        let opened = !same_file(&module.path, &own_path);
+        let target = match opened {
+            false => view.to_owned(),
+            true => {
+                let mut database = module.path.as_os_str().to_owned();
+                database.push(".bndb");
+                let database = PathBuf::from(database);
+                if !database.exists() {
+                    info!(
+                        "Skipping {} at {:#x}, no database at {}",
+                        module.path.display(),
+                        module.address,
+                        database.display()
+                    );
+                    continue;
+                }
+                match binaryninja::load(&database) {
+                    Some(target) => target,
+                    None => {
+                        warn!("Failed to open {}", database.display());
+                        continue;
+                    }
+                }
             }
-            warn!("Function 0x{func_addr:x} is not inside any section");
+        };
+
+        let result = gen_module_pdb(&target, &module.path, &store, &options, progress);
+        if opened {
+            target.file().close();
+        }
+        match result {
+            Ok(true) => generated += 1,
+            Ok(false) => {}
+            Err(err) if err.is::<Cancelled>() => return Err(err),
+            Err(err) => error!(
+                "PDB generation for {} failed {err:?}",
+                module.path.display()
+            ),
         }
     }
 
-    for (section_idx, functions) in functions_by_section {
-        let section = sections
-            .iter()
-            .find(|s| s.index == section_idx)
-            .context("section not found")?;
-
-        let section_start = base_address + section.virtual_address as u64;
+    Ok(generated)
+}
 
+/// Write the PDB for one module of a debugger session into `store`, or next to the module if
+/// it is empty. False if a PDB pdbgen didn't generate is already there.
+fn gen_module_pdb(
+    view: &BinaryView,
+    exe_path: &Path,
+    store: &str,
+    options: &Options,
+    progress: &mut dyn Progress,
+) -> Result<bool> {
+    let hold = settle_analysis(view, progress)?;
+    let mut report = Report::default();
+    let model = collect_model(
+        &BinjaReader(view, options),
+        options,
+        None,
+        &mut report,
+        progress,
+    )?;
+    drop(hold);
+
+    let pdb_path = match store.is_empty() {
+        true => exe_path.with_extension("pdb"),
+        false => pdbgen_core::symbol_store_path(Path::new(store), exe_path, &model.info),
+    };
+    // A real PDB for the module is worth more than anything generated
+    if pdb_path.exists() && !pdbgen_core::is_generated_pdb(&pdb_path).unwrap_or(false) {
         info!(
-            "Creating module for section {} with {} functions",
-            section.name,
-            functions.len()
+            "Keeping {}, it was not generated by pdbgen",
+            pdb_path.display()
         );
+        return Ok(false);
+    }
+    pdbgen_core::write_pdb(&model, options, &pdb_path, &mut report, progress)?;
+    report.log_summary();
+    Ok(true)
+}
 
-        let sec_contrib = SectionContrib {
-            i_sect: section_idx,
-            pad1: [0, 0],
-            offset: 0,
-            size: section.virtual_size,
-            characteristics: section.characteristics,
-            i_mod: 0,
-            pad2: [0, 0],
-            data_crc: 0,
-            reloc_crc: 0,
-        };
-
-        let mut module = ModuleBuilder::new(
-            format!("{}_module", section.name),
-            format!("/fake/path/{}.obj", section.name),
-            sec_contrib,
-        );
+/// What to do about a PDB already where the new one goes
+enum ExistingPdb {
+    Keep,
+    Replace,
+    /// Replace it, with the new PDB and the image both asking for this age
+    BumpAge(u32),
+}
 
-        for function in functions {
-            let func_name = function.symbol().short_name();
-            let func_name = func_name.to_string_lossy();
-
-            let original_ranges: Vec<_> = function
-                .address_ranges()
-                .iter()
-                .map(|r| r.start..r.end)
-                .collect();
-            let merged_ranges = merge_function_ranges(&original_ranges, 16, view, &function);
-
-            for (i, range) in merged_ranges.iter().enumerate() {
-                let func_start = range.start;
-                let func_size = range.end - range.start;
-                let func_offset = (func_start - section_start) as u32;
-                let func_name = if i == 0 {
-                    func_name.clone()
-                } else {
-                    format!("{func_name}_part{}", i + 1).into()
-                };
-
-                // info!(
-                //     "  Adding function: 0x{func_start:x} {func_name} at offset 0x{func_offset:x} (size: 0x{func_size:x})"
-                // );
-
-                // add to module
-                let proc_idx = module.symbols.len();
-                module.add_symbol(SymbolRecord::GlobalProc(Procedure {
-                    parent: None,
-                    end: 0.into(),
-                    next: None,
-                    code_size: func_size as u32,
-                    dbg_start_offset: 0,
-                    dbg_end_offset: 0,
-                    function_type: void_fn_type,
-                    code_offset: DataRegionOffset::new(func_offset, section_idx),
-                    properties: ProcedureProperties::new(),
-                    name: StrBuf::new(func_name.clone()),
-                }));
-                let end_idx = module.add_symbol(SymbolRecord::ProcEnd);
-                match &mut module.symbols[proc_idx] {
-                    SymbolRecord::GlobalProc(proc) => proc.end = end_idx,
-                    _ => unreachable!(),
-                }
+/// Decide what to do about a PDB already at `pdb_path`
+///
+/// A PDB for another build of the image (a different GUID or age) is why debuggers silently
+/// load no symbols, so rather than replacing it without comment the user picks between matching
+/// the image as it is and bumping the age of both, which also keeps symbol caches holding the
+/// old PDB from serving it for the new image.
+fn resolve_existing_pdb(pdb_path: &Path, exe_path: &Path, model: &Model) -> Result<ExistingPdb> {
+    let existing = match pdbgen_core::read_pdb_info(pdb_path) {
+        Ok(existing) => Some(existing),
+        Err(err) => {
+            warn!("Failed to read the GUID and age of the existing PDB: {err:#}");
+            None
+        }
+    };
+    let stale = existing
+        .filter(|existing| existing.guid != model.info.guid || existing.age != model.info.age);
 
-                // add to publics table
-                builder.dbi().symbols().add(Public {
-                    properties: PublicProperties::new().with_is_function(true),
-                    offset: DataRegionOffset::new(func_offset, section_idx),
-                    name: StrBuf::new(func_name),
-                });
-            }
+    let Some(existing) = stale else {
+        if pdbgen_core::is_generated_pdb(pdb_path).unwrap_or(false) {
+            return Ok(ExistingPdb::Replace);
         }
+        let result = show_message_box(
+            "PDB Already Exists",
+            &format!(
+                "A PDB file already exists at:\n{}\n\nThis PDB was not generated by pdbgen. Do you want to overwrite it?",
+                pdb_path.display()
+            ),
+            MessageBoxButtonSet::YesNoButtonSet,
+            MessageBoxIcon::WarningIcon,
+        );
+        return Ok(replace_if(result));
+    };
 
-        builder.dbi().add_module(module);
+    let mut text = format!(
+        "The PDB at:\n{}\n\nis for a different build than the image ({} instead of {}).",
+        pdb_path.display(),
+        existing.symbol_key(),
+        model.info.symbol_key(),
+    );
+
+    // Only the build that was analyzed can be patched to match
+    let on_disk = pdbgen_core::read_codeview(exe_path)
+        .ok()
+        .flatten()
+        .filter(|codeview| codeview.guid == model.info.guid && codeview.age == model.info.age);
+    if on_disk.is_none() {
+        text.push_str("\n\nReplace it with a PDB matching the image?");
+        let result = show_message_box(
+            "Stale PDB",
+            &text,
+            MessageBoxButtonSet::YesNoButtonSet,
+            MessageBoxIcon::WarningIcon,
+        );
+        return Ok(replace_if(result));
     }
 
-    Ok(())
-}
-
-fn read_u32_field(
-    view: &BinaryView,
-    base_addr: u64,
-    offsets: &HashMap<String, u64>,
-    field_name: &str,
-) -> Result<u32> {
-    let offset = offsets
-        .get(field_name)
-        .context(format!("missing '{field_name}' field"))?;
-    let bytes = view.read_vec(base_addr + offset, 4);
-    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    let age = existing.age.max(model.info.age) + 1;
+    text.push_str(&format!(
+        "\n\nYes: replace it with a PDB matching the image.\n\
+         No: bump the age to {age} in both the new PDB and {}, so nothing holding the old PDB \
+         mistakes it for this build. Invalidates any Authenticode signature on the image.\n\
+         Cancel: leave both alone.",
+        exe_path.display(),
+    ));
+    let result = show_message_box(
+        "Stale PDB",
+        &text,
+        MessageBoxButtonSet::YesNoCancelButtonSet,
+        MessageBoxIcon::WarningIcon,
+    );
+    Ok(match result {
+        MessageBoxButtonResult::YesButton => ExistingPdb::Replace,
+        MessageBoxButtonResult::NoButton => ExistingPdb::BumpAge(age),
+        _ => ExistingPdb::Keep,
+    })
 }
 
-fn read_u16_field(
-    view: &BinaryView,
-    base_addr: u64,
-    offsets: &HashMap<String, u64>,
-    field_name: &str,
-) -> Result<u16> {
-    let offset = offsets
-        .get(field_name)
-        .context(format!("missing '{field_name}' field"))?;
-    let bytes = view.read_vec(base_addr + offset, 2);
-    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+fn replace_if(result: MessageBoxButtonResult) -> ExistingPdb {
+    match result {
+        MessageBoxButtonResult::YesButton => ExistingPdb::Replace,
+        _ => ExistingPdb::Keep,
+    }
 }
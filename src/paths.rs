@@ -0,0 +1,26 @@
+//! Paths derived from where the database or image lives
+//!
+//! Everything goes through `Path` rather than strings, so UNC shares (`\\server\share`),
+//! verbatim long paths (`\\?\C:\...`) and names that aren't ASCII survive intact.
+
+use binaryninja::binary_view::BinaryView;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path of the executable the view was loaded from, the database's path without `.bndb`
+pub fn exe_path(view: &BinaryView) -> PathBuf {
+    let path = view.file().file_path();
+    match path.extension() {
+        Some(extension) if extension.eq_ignore_ascii_case("bndb") => path.with_extension(""),
+        _ => path,
+    }
+}
+
+/// Whether two paths name the same file, letting the file system settle differences in case,
+/// links and `\\?\` prefixes when both exist
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
@@ -0,0 +1,994 @@
+use binaryninja::architecture::{Architecture, Register, RegisterId};
+use binaryninja::binary_view::{BinaryView, BinaryViewBase, BinaryViewExt, StringType};
+use binaryninja::component::Component;
+use binaryninja::function::Function;
+use binaryninja::medium_level_il::MediumLevelILLiftedInstructionKind;
+use binaryninja::qualified_name::QualifiedName;
+use binaryninja::rc::Ref;
+use binaryninja::section::Semantics;
+use binaryninja::symbol::SymbolType as BnSymbolType;
+use binaryninja::type_archive::TypeArchive;
+use binaryninja::types::{Structure, StructureType, Type, TypeClass};
+use binaryninja::variable::{RegisterValueType, Variable, VariableSourceType};
+use pdbgen_core::model::{
+    Enumerator, Frame, FunctionKind, Location, Member, Parameter, TypeDefinition,
+    TypeDefinitionKind, TypeReference, ValueKind, ValueType,
+};
+use pdbgen_core::reader::{
+    CallSiteInfo, FunctionInfo, HeapAllocSiteInfo, SymbolNames, TypeIssueInfo, ViewReader,
+    ViewSection, ViewSegment, ViewString, ViewSymbol, decode_name,
+};
+use pdbgen_core::{Options, SymbolType};
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use tracing::{info, warn};
+
+/// Function metadata key naming the library a signature match attributed the function to
+///
+/// Nothing in Binary Ninja sets it: the signature matcher, sigkit and WARP leave only the name
+/// and LibraryFunctionSymbol type of a match behind, so it comes from user scripts or plugins.
+const LIBRARY_METADATA: &str = "library";
+/// Longest chain of named type references followed before giving up on a type
+const MAX_REFERENCE_DEPTH: usize = 16;
+
+/// Exposes a `BinaryView` to the collection logic in pdbgen-core, skipping the lookups the
+/// options don't need
+pub struct BinjaReader<'a>(pub &'a BinaryView, pub &'a Options);
+
+impl BinjaReader<'_> {
+    /// What the analysis knows about each of `functions`
+    pub fn function_infos(&self, functions: &[Ref<Function>]) -> Vec<FunctionInfo> {
+        let components = component_paths(self.0);
+        // The names only end up in the parameter records parameter_locations emits
+        let debug_parameters = match self.1.debug_info && self.1.parameter_locations {
+            true => debug_parameter_names(self.0),
+            false => HashMap::new(),
+        };
+        let import_libraries = match self.1.import_modules {
+            true => import_libraries(self.0),
+            false => HashMap::new(),
+        };
+        let archives = match self.1.type_archives {
+            true => type_archives(self.0),
+            false => Vec::new(),
+        };
+
+        // Each name and range lookup is a round trip into the core, so spread them over threads
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = functions.len().div_ceil(threads).max(1);
+        thread::scope(|scope| {
+            let workers: Vec<_> = functions
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(|| {
+                        chunk
+                            .iter()
+                            .map(|f| {
+                                function_info(
+                                    f,
+                                    &components,
+                                    &debug_parameters,
+                                    &import_libraries,
+                                    &archives,
+                                    self.1,
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("function collection thread panicked"))
+                .collect()
+        })
+    }
+}
+
+impl ViewReader for BinjaReader<'_> {
+    fn start(&self) -> u64 {
+        self.0.start()
+    }
+
+    fn read(&self, addr: u64, len: usize) -> Vec<u8> {
+        self.0.read_vec(addr, len)
+    }
+
+    fn symbol_address(&self, raw_name: &str) -> Option<u64> {
+        self.0.symbol_by_raw_name(raw_name).map(|sym| sym.address())
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        let functions: Vec<Ref<Function>> =
+            self.0.functions().iter().map(|f| f.to_owned()).collect();
+        self.function_infos(&functions)
+    }
+
+    fn functions_containing(&self, addr: u64) -> Vec<u64> {
+        self.0
+            .functions_containing(addr)
+            .iter()
+            .map(|function| function.start())
+            .collect()
+    }
+
+    fn sections(&self) -> Vec<ViewSection> {
+        self.0
+            .sections()
+            .iter()
+            .map(|section| ViewSection {
+                name: section.name().to_string(),
+                range: section.start()..section.end(),
+                code: section.semantics() == Semantics::ReadOnlyCode,
+                writable: section.semantics() == Semantics::ReadWriteData,
+                user: !section.auto_defined(),
+            })
+            .collect()
+    }
+
+    fn strings(&self) -> Vec<ViewString> {
+        self.0
+            .strings()
+            .iter()
+            .map(|string| ViewString {
+                address: string.start,
+                length: string.length,
+                width: match string.ty {
+                    StringType::Utf16String => 2,
+                    StringType::Utf32String => 4,
+                    _ => 1,
+                },
+            })
+            .collect()
+    }
+
+    fn data_symbols(&self) -> Vec<ViewSymbol> {
+        let mut symbols: Vec<ViewSymbol> = self
+            .0
+            .symbols()
+            .iter()
+            .filter_map(|symbol| {
+                let symbol_type = match symbol.sym_type() {
+                    BnSymbolType::Data => SymbolType::Data,
+                    BnSymbolType::External | BnSymbolType::ImportedData => SymbolType::External,
+                    _ => return None,
+                };
+                Some(ViewSymbol {
+                    address: symbol.address(),
+                    name: decode_name(symbol.short_name().to_bytes()),
+                    raw_name: decode_name(symbol.raw_name().to_bytes()),
+                    full_name: decode_name(symbol.full_name().to_bytes()),
+                    symbol_type,
+                })
+            })
+            .collect();
+
+        // Named data from imported debug info that never became a symbol in the view
+        if self.1.debug_info {
+            let named: HashSet<u64> = symbols.iter().map(|symbol| symbol.address).collect();
+            for variable in self.0.debug_info().data_variables(None) {
+                if variable.name.is_empty() || named.contains(&variable.address) {
+                    continue;
+                }
+                let name = variable.name.to_string();
+                symbols.push(ViewSymbol {
+                    address: variable.address,
+                    name: name.clone(),
+                    raw_name: name.clone(),
+                    full_name: name,
+                    symbol_type: SymbolType::Data,
+                });
+            }
+        }
+        symbols
+    }
+
+    fn frontend(&self) -> String {
+        format!("Binary Ninja {}", binaryninja::version())
+    }
+
+    fn image_name(&self) -> String {
+        let path = crate::paths::exe_path(self.0);
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn segments(&self) -> Vec<ViewSegment> {
+        self.0
+            .segments()
+            .iter()
+            .filter_map(|segment| {
+                let backing = segment.parent_backing()?;
+                Some(ViewSegment {
+                    range: segment.address_range(),
+                    data_offset: backing.start,
+                    data_length: backing.end - backing.start,
+                })
+            })
+            .collect()
+    }
+
+    fn types(&self) -> Vec<TypeDefinition> {
+        let archives = match self.1.type_archives {
+            true => type_archives(self.0),
+            false => Vec::new(),
+        };
+        let mut exporter = TypeExporter::new(self.0, &archives);
+
+        // Everything imported debug info (DWARF from a MinGW build, a partial vendor PDB)
+        // defines is exported, not only what the analysis ended up using
+        if self.1.debug_info {
+            for named in self.0.debug_info().types(None) {
+                exporter.define(named.name.to_string(), named.ty.contents.clone());
+            }
+        }
+
+        // Archive types make it into the view through the signatures and data typed with them
+        if !archives.is_empty() {
+            for function in self.0.functions().iter() {
+                exporter.visit(&function.function_type());
+            }
+            for variable in self.0.data_variables().iter() {
+                exporter.visit(&variable.ty.contents);
+            }
+        }
+        exporter.finish()
+    }
+}
+
+/// A [`BinjaReader`] reporting only some of the view's functions, for updating the PDB after
+/// a few functions changed
+pub struct SelectionReader<'a> {
+    pub reader: BinjaReader<'a>,
+    pub functions: Vec<Ref<Function>>,
+}
+
+impl ViewReader for SelectionReader<'_> {
+    fn start(&self) -> u64 {
+        self.reader.start()
+    }
+
+    fn read(&self, addr: u64, len: usize) -> Vec<u8> {
+        self.reader.read(addr, len)
+    }
+
+    fn symbol_address(&self, raw_name: &str) -> Option<u64> {
+        self.reader.symbol_address(raw_name)
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        self.reader.function_infos(&self.functions)
+    }
+
+    fn functions_containing(&self, addr: u64) -> Vec<u64> {
+        self.reader.functions_containing(addr)
+    }
+
+    fn sections(&self) -> Vec<ViewSection> {
+        self.reader.sections()
+    }
+
+    fn strings(&self) -> Vec<ViewString> {
+        self.reader.strings()
+    }
+
+    fn data_symbols(&self) -> Vec<ViewSymbol> {
+        self.reader.data_symbols()
+    }
+
+    fn frontend(&self) -> String {
+        self.reader.frontend()
+    }
+
+    fn image_name(&self) -> String {
+        self.reader.image_name()
+    }
+
+    fn segments(&self) -> Vec<ViewSegment> {
+        self.reader.segments()
+    }
+
+    fn types(&self) -> Vec<TypeDefinition> {
+        self.reader.types()
+    }
+}
+
+fn function_info(
+    function: &Function,
+    components: &HashMap<u64, String>,
+    debug_parameters: &HashMap<u64, Vec<String>>,
+    import_libraries: &HashMap<String, String>,
+    archives: &[Ref<TypeArchive>],
+    options: &Options,
+) -> FunctionInfo {
+    let mut types = TypeConverter::new(function.view(), archives);
+    let parameters = match options.parameter_locations {
+        true => {
+            let debug_names = debug_parameters.get(&function.start());
+            parameters(function, debug_names.map_or(&[], Vec::as_slice), &mut types)
+        }
+        false => Vec::new(),
+    };
+    FunctionInfo {
+        start: function.start(),
+        name: decode_name(function.symbol().short_name().to_bytes()),
+        raw_name: decode_name(function.symbol().raw_name().to_bytes()),
+        full_name: decode_name(function.symbol().full_name().to_bytes()),
+        auto: function.symbol().auto_defined(),
+        auto_symbol: auto_symbol(function),
+        symbol_type: match function.symbol().sym_type() {
+            BnSymbolType::ImportedFunction => SymbolType::ImportedFunction,
+            BnSymbolType::LibraryFunction => SymbolType::LibraryFunction,
+            BnSymbolType::Data => SymbolType::Data,
+            BnSymbolType::External => SymbolType::External,
+            _ => SymbolType::Function,
+        },
+        ranges: function
+            .address_ranges()
+            .iter()
+            .map(|r| r.start..r.end)
+            .collect(),
+        component: components.get(&function.start()).cloned(),
+        library: function
+            .query_metadata(LIBRARY_METADATA)
+            .and_then(|metadata| metadata.get_string())
+            .map(|library| library.to_string()),
+        import_library: match options.import_modules {
+            true => import_libraries
+                .get(import_name(&decode_name(
+                    function.symbol().raw_name().to_bytes(),
+                )))
+                .cloned(),
+            false => None,
+        },
+        kind: match function.is_thunk() {
+            true => FunctionKind::Thunk,
+            false => FunctionKind::Procedure,
+        },
+        entry_length: match parameters.is_empty() {
+            true => 0,
+            false => instruction_length(function, function.start()),
+        },
+        parameters,
+        call_sites: match options.call_site_types {
+            true => call_sites(function, &mut types),
+            false => Vec::new(),
+        },
+        heap_alloc_sites: match options.heap_alloc_sites {
+            true => heap_alloc_sites(function, options, &mut types),
+            false => Vec::new(),
+        },
+        calls: match options.cross_module_references {
+            true => direct_calls(function)
+                .into_iter()
+                .map(|(_, target, _)| target)
+                .collect(),
+            false => Vec::new(),
+        },
+        frame: options.frame_info.then(|| frame(function)).flatten(),
+        blocks: match options.basic_block_labels {
+            true => function
+                .basic_blocks()
+                .iter()
+                .map(|block| block.start())
+                .collect(),
+            false => Vec::new(),
+        },
+        type_issues: types.issues,
+    }
+}
+
+/// Parameters of a function where its calling convention places them on entry, named from
+/// `debug_names` where analysis only has generated names
+fn parameters(
+    function: &Function,
+    debug_names: &[String],
+    types: &mut TypeConverter,
+) -> Vec<Parameter> {
+    let arch = function.arch();
+    function
+        .parameter_variables()
+        .contents
+        .iter()
+        .enumerate()
+        .filter_map(|(index, variable)| {
+            let location = match variable.ty {
+                VariableSourceType::RegisterVariableSourceType => Location::Register(
+                    arch.register_from_id(RegisterId(variable.storage as u32))?
+                        .name()
+                        .to_string(),
+                ),
+                VariableSourceType::StackVariableSourceType => Location::Stack(variable.storage),
+                VariableSourceType::FlagVariableSourceType => return None,
+            };
+            let ty = function.variable_type(variable)?.contents;
+            let mut name = function.variable_name(variable).to_string();
+            if let Some(debug_name) = debug_names.get(index).filter(|n| !n.is_empty())
+                && is_generated_parameter_name(&name)
+            {
+                name = debug_name.clone();
+            }
+            let value = types.convert(&ty, function.start(), || format!("parameter {name}"));
+            Some(Parameter {
+                name: name.into(),
+                size: value.size,
+                kind: value.kind,
+                location,
+            })
+        })
+        .collect()
+}
+
+/// Whether a parameter name is one analysis made up: `arg1` by index, or `arg_1c` by stack
+/// offset. Only the offset form is hex, so a real `argc` is kept.
+fn is_generated_parameter_name(name: &str) -> bool {
+    let Some(rest) = name.strip_prefix("arg") else {
+        return false;
+    };
+    match rest.strip_prefix('_') {
+        Some(offset) => !offset.is_empty() && offset.chars().all(|c| c.is_ascii_hexdigit()),
+        None => !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()),
+    }
+}
+
+/// Parameter names of every function the view's imported debug info gives a type, keyed by
+/// function start
+fn debug_parameter_names(view: &BinaryView) -> HashMap<u64, Vec<String>> {
+    view.debug_info()
+        .functions(None)
+        .into_iter()
+        .filter_map(|function| {
+            let parameters = function.type_?.parameters().ok()?;
+            let names = parameters.iter().map(|p| p.name.to_string()).collect();
+            Some((function.address, names))
+        })
+        .collect()
+}
+
+/// Type archives attached to the view that are currently open
+fn type_archives(view: &BinaryView) -> Vec<Ref<TypeArchive>> {
+    view.attached_type_archives()
+        .into_iter()
+        .filter_map(|(id, path)| {
+            let archive = view.type_archive_by_id(&id);
+            if archive.is_none() {
+                warn!("Type archive {path} is attached but not open, its types are not resolved");
+            }
+            archive
+        })
+        .collect()
+}
+
+/// Library of every symbol the view links to one through an external location, keyed by
+/// [`import_name`], so an import thunk finds the library of the IAT slot it jumps through
+fn import_libraries(view: &BinaryView) -> HashMap<String, String> {
+    view.external_locations()
+        .iter()
+        .filter_map(|location| {
+            let library = location.library()?;
+            let symbol = location.source_symbol();
+            let raw_name = decode_name(symbol.raw_name().to_bytes());
+            Some((
+                import_name(&raw_name).to_string(),
+                library.name().to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// Name of an import with the `__imp_` prefix of its IAT slot removed
+fn import_name(raw_name: &str) -> &str {
+    raw_name.strip_prefix("__imp_").unwrap_or(raw_name)
+}
+
+/// Indirect calls whose target expression the analysis typed as a function pointer
+fn call_sites(function: &Function, types: &mut TypeConverter) -> Vec<CallSiteInfo> {
+    let Ok(mlil) = function.medium_level_il() else {
+        return Vec::new();
+    };
+    function
+        .call_sites()
+        .iter()
+        .filter_map(|site| {
+            let call = mlil.instruction_at(site.address)?.lift();
+            let MediumLevelILLiftedInstructionKind::Call(call) = call.kind else {
+                return None;
+            };
+            // Calls to a constant address already land on a symbol with its own type
+            if matches!(
+                call.dest.kind,
+                MediumLevelILLiftedInstructionKind::ConstPtr(_)
+                    | MediumLevelILLiftedInstructionKind::ImportedAddress(_)
+            ) {
+                return None;
+            }
+            let target = call.dest.expr_type()?.contents.target().ok()?.contents;
+            if target.type_class() != TypeClass::FunctionTypeClass {
+                return None;
+            }
+            let return_type = target
+                .return_value()
+                .ok()
+                .map(|ty| ty.contents)
+                .filter(|ty| ty.type_class() != TypeClass::VoidTypeClass)
+                .map(|ty| types.convert(&ty, site.address, || "call return value".to_string()));
+            let parameters = target
+                .parameters()
+                .ok()?
+                .iter()
+                .map(|parameter| {
+                    types.convert(&parameter.ty.contents, site.address, || {
+                        format!("call parameter {}", parameter.name)
+                    })
+                })
+                .collect();
+            Some(CallSiteInfo {
+                address: site.address,
+                return_type,
+                parameters,
+            })
+        })
+        .collect()
+}
+
+/// Turns analysis types into [`ValueType`]s, noting each one it can only approximate
+struct TypeConverter<'a> {
+    view: Ref<BinaryView>,
+    /// Where named types missing from the view are looked up, see [`Options::type_archives`]
+    archives: &'a [Ref<TypeArchive>],
+    issues: Vec<TypeIssueInfo>,
+}
+
+impl<'a> TypeConverter<'a> {
+    fn new(view: Ref<BinaryView>, archives: &'a [Ref<TypeArchive>]) -> Self {
+        Self {
+            view,
+            archives,
+            issues: Vec::new(),
+        }
+    }
+
+    /// Size and kind of `ty`, typing `item` at `address`
+    fn convert(&mut self, ty: &Type, address: u64, item: impl FnOnce() -> String) -> ValueType {
+        let resolved = self.resolve(ty);
+        if let Some(reason) = approximation(&resolved) {
+            self.issues.push(TypeIssueInfo {
+                address,
+                item: item(),
+                type_name: ty.to_string(),
+                reason: reason.to_string(),
+            });
+        }
+        value_type(&resolved)
+    }
+
+    /// The type a chain of named type references ends at, or the last reference that doesn't
+    /// resolve
+    fn resolve(&self, ty: &Type) -> Ref<Type> {
+        let mut ty = ty.to_owned();
+        for _ in 0..MAX_REFERENCE_DEPTH {
+            if ty.type_class() != TypeClass::NamedTypeReferenceClass {
+                break;
+            }
+            let Some(next) = ty
+                .get_named_type_reference()
+                .ok()
+                .and_then(|reference| self.named_type(reference.name()))
+            else {
+                break;
+            };
+            ty = next;
+        }
+        ty
+    }
+
+    fn named_type(&self, name: QualifiedName) -> Option<Ref<Type>> {
+        named_type(&self.view, self.archives, name)
+    }
+}
+
+/// The type called `name` in the view, or failing that in the first archive defining it
+fn named_type(
+    view: &BinaryView,
+    archives: &[Ref<TypeArchive>],
+    name: QualifiedName,
+) -> Option<Ref<Type>> {
+    view.type_by_name(name.clone()).or_else(|| {
+        archives
+            .iter()
+            .find_map(|archive| archive.get_type_by_name(name.clone()))
+    })
+}
+
+/// Turns debug info types and the archive types the view refers to by name, and every named
+/// type those are built from, into [`TypeDefinition`]s
+struct TypeExporter<'a> {
+    view: &'a BinaryView,
+    archives: &'a [Ref<TypeArchive>],
+    /// Names whose references have been looked through
+    visited: HashSet<String>,
+    /// Names already queued for export
+    exported: HashSet<String>,
+    pending: Vec<(String, Ref<Type>)>,
+    definitions: Vec<TypeDefinition>,
+}
+
+impl<'a> TypeExporter<'a> {
+    fn new(view: &'a BinaryView, archives: &'a [Ref<TypeArchive>]) -> Self {
+        Self {
+            view,
+            archives,
+            visited: HashSet::new(),
+            exported: HashSet::new(),
+            pending: Vec::new(),
+            definitions: Vec::new(),
+        }
+    }
+
+    /// Queue the archive types `ty` refers to by name, looking through the view's own types
+    /// for more
+    fn visit(&mut self, ty: &Type) {
+        let mut names = Vec::new();
+        named_references(ty, &mut names);
+        for name in names {
+            if !self.visited.insert(name.to_string()) {
+                continue;
+            }
+            match self.view.type_by_name(name.clone()) {
+                Some(view_type) => self.visit(&view_type),
+                None => self.export(name),
+            }
+        }
+    }
+
+    /// Definitions of every queued type and what they depend on
+    fn finish(mut self) -> Vec<TypeDefinition> {
+        while let Some((name, ty)) = self.pending.pop() {
+            let definition = self.definition(name, &ty);
+            self.definitions.push(definition);
+        }
+        if !self.definitions.is_empty() {
+            info!(
+                "Exporting {} type(s) from debug info and type archives",
+                self.definitions.len()
+            );
+        }
+        self.definitions
+    }
+
+    /// Queue the type called `name` for export, wherever it is defined
+    fn export(&mut self, name: QualifiedName) {
+        let key = name.to_string();
+        if self.exported.contains(&key) {
+            return;
+        }
+        if let Some(ty) = named_type(self.view, self.archives, name) {
+            self.define(key, ty);
+        } else {
+            self.exported.insert(key);
+        }
+    }
+
+    /// Queue `ty` for export as `name`, unless a type of that name already is
+    fn define(&mut self, name: String, ty: Ref<Type>) {
+        if self.exported.insert(name.clone()) {
+            self.pending.push((name, ty));
+        }
+    }
+
+    fn definition(&mut self, name: String, ty: &Type) -> TypeDefinition {
+        let kind = match ty.type_class() {
+            TypeClass::StructureTypeClass => ty
+                .get_structure()
+                .ok()
+                .map(|structure| self.structure(&structure)),
+            TypeClass::EnumerationTypeClass => {
+                ty.get_enumeration()
+                    .ok()
+                    .map(|enumeration| TypeDefinitionKind::Enumeration {
+                        size: ty.width() as u32,
+                        members: enumeration
+                            .members()
+                            .iter()
+                            .map(|member| Enumerator {
+                                name: member.name.as_str().into(),
+                                value: member.value,
+                            })
+                            .collect(),
+                    })
+            }
+            _ => None,
+        };
+        TypeDefinition {
+            name: name.into(),
+            kind: kind.unwrap_or_else(|| TypeDefinitionKind::Alias(self.reference(ty))),
+        }
+    }
+
+    fn structure(&mut self, structure: &Structure) -> TypeDefinitionKind {
+        let members = structure
+            .members()
+            .iter()
+            .map(|member| Member {
+                name: member.name.as_str().into(),
+                offset: member.offset as u32,
+                ty: self.reference(&member.ty.contents),
+            })
+            .collect();
+        let size = structure.width() as u32;
+        match structure.structure_type() {
+            StructureType::UnionStructureType => TypeDefinitionKind::Union { size, members },
+            _ => TypeDefinitionKind::Structure { size, members },
+        }
+    }
+
+    /// How a member or alias refers to `ty`, queueing the named types it uses
+    fn reference(&mut self, ty: &Type) -> TypeReference {
+        match ty.type_class() {
+            TypeClass::NamedTypeReferenceClass => match ty.get_named_type_reference() {
+                Ok(reference) => {
+                    let name = reference.name();
+                    let key = name.to_string();
+                    self.export(name);
+                    TypeReference::Named(key.into())
+                }
+                Err(_) => TypeReference::Value(value_type(ty)),
+            },
+            TypeClass::PointerTypeClass => TypeReference::Pointer {
+                target: Box::new(match ty.target() {
+                    Ok(target) => self.reference(&target.contents),
+                    Err(_) => TypeReference::Value(ValueType::default()),
+                }),
+                size: ty.width() as u32,
+            },
+            TypeClass::ArrayTypeClass => match ty.element_type() {
+                Ok(element) => TypeReference::Array {
+                    element: Box::new(self.reference(&element.contents)),
+                    count: ty.count() as u32,
+                },
+                Err(_) => bytes(ty),
+            },
+            // CodeView has no inline records, so anonymous structures only keep their size
+            TypeClass::StructureTypeClass => bytes(ty),
+            _ => TypeReference::Value(value_type(ty)),
+        }
+    }
+}
+
+/// An array of bytes as large as `ty`
+fn bytes(ty: &Type) -> TypeReference {
+    TypeReference::Array {
+        element: Box::new(TypeReference::Value(ValueType {
+            size: 1,
+            kind: ValueKind::Integer,
+        })),
+        count: ty.width() as u32,
+    }
+}
+
+/// Names `ty` refers to through pointers, arrays, members and signatures
+fn named_references(ty: &Type, names: &mut Vec<QualifiedName>) {
+    match ty.type_class() {
+        TypeClass::NamedTypeReferenceClass => {
+            if let Ok(reference) = ty.get_named_type_reference() {
+                names.push(reference.name());
+            }
+        }
+        TypeClass::PointerTypeClass => {
+            if let Ok(target) = ty.target() {
+                named_references(&target.contents, names);
+            }
+        }
+        TypeClass::ArrayTypeClass => {
+            if let Ok(element) = ty.element_type() {
+                named_references(&element.contents, names);
+            }
+        }
+        TypeClass::StructureTypeClass => {
+            if let Ok(structure) = ty.get_structure() {
+                for member in structure.members() {
+                    named_references(&member.ty.contents, names);
+                }
+            }
+        }
+        TypeClass::FunctionTypeClass => {
+            if let Ok(return_value) = ty.return_value() {
+                named_references(&return_value.contents, names);
+            }
+            for parameter in ty.parameters().unwrap_or_default() {
+                named_references(&parameter.ty.contents, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// How [`value_type`] falls short of describing `ty`, if it does
+fn approximation(ty: &Type) -> Option<&'static str> {
+    match ty.type_class() {
+        TypeClass::IntegerTypeClass
+        | TypeClass::BoolTypeClass
+        | TypeClass::FloatTypeClass
+        | TypeClass::PointerTypeClass
+        | TypeClass::EnumerationTypeClass
+        | TypeClass::WideCharTypeClass => None,
+        TypeClass::StructureTypeClass => Some("structure described as an integer of its size"),
+        TypeClass::ArrayTypeClass => Some("array described as an integer of its size"),
+        TypeClass::NamedTypeReferenceClass => {
+            Some("named type reference that doesn't resolve in the view or its type archives")
+        }
+        _ => Some("no equivalent, described as an integer of its size"),
+    }
+}
+
+/// Size and kind of a value of type `ty`
+fn value_type(ty: &Type) -> ValueType {
+    ValueType {
+        size: ty.width() as u32,
+        kind: match ty.type_class() {
+            TypeClass::FloatTypeClass => ValueKind::Float,
+            TypeClass::PointerTypeClass => ValueKind::Pointer,
+            _ => ValueKind::Integer,
+        },
+    }
+}
+
+/// Direct calls to a function [`Options::is_heap_allocator`] recognizes by its raw or short name
+fn heap_alloc_sites(
+    function: &Function,
+    options: &Options,
+    types: &mut TypeConverter,
+) -> Vec<HeapAllocSiteInfo> {
+    let view = function.view();
+    direct_calls(function)
+        .into_iter()
+        .filter_map(|(address, target, output)| {
+            let symbol = view.symbol_by_address(target)?;
+            let names = [symbol.raw_name(), symbol.short_name()];
+            if !names
+                .iter()
+                .any(|name| options.is_heap_allocator(&decode_name(name.to_bytes())))
+            {
+                return None;
+            }
+
+            let allocated = output
+                .first()
+                .and_then(|output| function.variable_type(output))
+                .and_then(|ty| ty.contents.target().ok())
+                .map(|target| {
+                    types.convert(&target.contents, address, || "heap allocation".to_string())
+                });
+            Some(HeapAllocSiteInfo {
+                address,
+                length: u16::try_from(instruction_length(function, address)).ok()?,
+                allocated,
+            })
+        })
+        .collect()
+}
+
+/// Address, target and output variables of every call to a constant address
+fn direct_calls(function: &Function) -> Vec<(u64, u64, Vec<Variable>)> {
+    let Ok(mlil) = function.medium_level_il() else {
+        return Vec::new();
+    };
+    function
+        .call_sites()
+        .iter()
+        .filter_map(|site| {
+            let call = mlil.instruction_at(site.address)?.lift();
+            let MediumLevelILLiftedInstructionKind::Call(call) = call.kind else {
+                return None;
+            };
+            let target = match call.dest.kind {
+                MediumLevelILLiftedInstructionKind::ConstPtr(op)
+                | MediumLevelILLiftedInstructionKind::ImportedAddress(op) => op.constant,
+                _ => return None,
+            };
+            Some((site.address, target, call.output))
+        })
+        .collect()
+}
+
+/// Frame layout as analysis sees it, on the architectures CodeView encodes frames for
+fn frame(function: &Function) -> Option<Frame> {
+    let arch = function.arch();
+    let frame_registers: &[&str] = match arch.name().as_str() {
+        "x86" => &["ebp"],
+        "x86_64" => &["rbp"],
+        "aarch64" => &["x29", "fp"],
+        _ => return None,
+    };
+    let start = function.start();
+    let entry_block = function
+        .basic_blocks()
+        .iter()
+        .find(|block| block.start() == start)?
+        .to_owned();
+
+    // A frame pointer holds a stack address once the entry block has run
+    let frame_pointer = frame_registers
+        .iter()
+        .find_map(|name| arch.register_by_name(name))
+        .is_some_and(|register| {
+            function
+                .register_value_at(entry_block.end(), register.id(), None)
+                .state
+                == RegisterValueType::StackFrameOffset
+        });
+    let lowest = function
+        .stack_layout()
+        .iter()
+        .map(|variable| variable.variable.storage)
+        .min()
+        .unwrap_or(0);
+
+    let view = function.view();
+    let security_cookie = direct_calls(function).iter().any(|&(_, target, _)| {
+        view.symbol_by_address(target).is_some_and(|symbol| {
+            // __security_check_cookie, or @__security_check_cookie@4 on x86
+            decode_name(symbol.raw_name().to_bytes())
+                .trim_start_matches(['_', '@'])
+                .starts_with("security_check_cookie")
+        })
+    });
+
+    Some(Frame {
+        size: u32::try_from(lowest.min(0).unsigned_abs()).unwrap_or(u32::MAX),
+        frame_pointer,
+        security_cookie,
+    })
+}
+
+/// Length of the instruction at `address`, 0 if it doesn't decode
+fn instruction_length(function: &Function, address: u64) -> u32 {
+    let arch = function.arch();
+    let bytes = function.view().read_vec(address, arch.max_instr_len());
+    arch.instruction_info(&bytes, address)
+        .map_or(0, |info| info.length as u32)
+}
+
+/// Names of the analysis symbol at a function the user renamed
+fn auto_symbol(function: &Function) -> Option<SymbolNames> {
+    // Only user names can shadow another, which saves the lookup for most functions
+    if function.symbol().auto_defined() {
+        return None;
+    }
+    let start = function.start();
+    let symbols = function.view().symbols_in_range(start..start + 1);
+    let auto = symbols
+        .iter()
+        .find(|symbol| symbol.auto_defined() && symbol.address() == start)?;
+    Some(SymbolNames {
+        name: decode_name(auto.short_name().to_bytes()),
+        raw_name: decode_name(auto.raw_name().to_bytes()),
+        full_name: decode_name(auto.full_name().to_bytes()),
+    })
+}
+
+/// Path of the innermost component each function belongs to, keyed by function start
+fn component_paths(view: &BinaryView) -> HashMap<u64, String> {
+    let mut paths = HashMap::new();
+    let Some(root) = view.root_component() else {
+        return paths;
+    };
+
+    let mut pending: Vec<(Ref<Component>, String)> = root
+        .components()
+        .iter()
+        .map(|c| (c.to_owned(), c.name().to_string()))
+        .collect();
+    while let Some((component, path)) = pending.pop() {
+        for function in component.functions().iter() {
+            paths.insert(function.start(), path.clone());
+        }
+        // Children are visited later and overwrite their parent's claim
+        for child in component.components().iter() {
+            pending.push((child.to_owned(), format!("{path}/{}", child.name())));
+        }
+    }
+    paths
+}
@@ -0,0 +1,817 @@
+use binaryninja::settings::Settings;
+use pdbgen_core::{
+    DbiHeader, DuplicateNames, ItaniumNames, LayoutRange, LongNamePolicy, ModuleLayout, NameForm,
+    NamePrecedence, NameSanitization, Options, RustNames, StringLiteralNames, SymbolType, msf,
+};
+use tracing::warn;
+
+pub const INCREMENTAL: &str = "pdbgen.incremental";
+pub const SYMBOL_STORE: &str = "pdbgen.symbolStore";
+pub const INCOMPLETE_ANALYSIS: &str = "pdbgen.incompleteAnalysis";
+pub const MODULE_LAYOUT: &str = "pdbgen.moduleLayout";
+pub const LIBRARY_MODULES: &str = "pdbgen.libraryModules";
+pub const IMPORT_MODULES: &str = "pdbgen.importModules";
+pub const CROSS_MODULE_REFERENCES: &str = "pdbgen.crossModuleReferences";
+pub const TRIM_PADDING: &str = "pdbgen.trimPadding";
+pub const USER_SECTIONS: &str = "pdbgen.userSections";
+pub const OVERLAY_SECTION: &str = "pdbgen.overlaySection";
+pub const INCLUDE_UNWIND_FUNCTIONS: &str = "pdbgen.includeUnwindFunctions";
+pub const EXCEPTION_STREAM: &str = "pdbgen.exceptionStream";
+pub const SYMBOL_TYPES: &str = "pdbgen.symbolTypes";
+pub const VTABLES: &str = "pdbgen.vtables";
+pub const PARAMETER_LOCATIONS: &str = "pdbgen.parameterLocations";
+pub const CALL_SITE_TYPES: &str = "pdbgen.callSiteTypes";
+pub const HEAP_ALLOC_SITES: &str = "pdbgen.heapAllocSites";
+pub const HEAP_ALLOCATORS: &str = "pdbgen.heapAllocators";
+pub const FRAME_INFO: &str = "pdbgen.frameInfo";
+pub const BASIC_BLOCK_LABELS: &str = "pdbgen.basicBlockLabels";
+pub const DEBUG_INFO: &str = "pdbgen.debugInfo";
+pub const TYPE_ARCHIVES: &str = "pdbgen.typeArchives";
+pub const ENV_BLOCK: &str = "pdbgen.envBlock";
+pub const MANIFEST_STREAM: &str = "pdbgen.manifestStream";
+pub const STRING_LITERALS: &str = "pdbgen.stringLiterals";
+pub const SKIP_DISCARDABLE_SECTIONS: &str = "pdbgen.skipDiscardableSections";
+pub const SECTIONS: &str = "pdbgen.sections";
+pub const LAYOUT_RANGES: &str = "pdbgen.layoutRanges";
+pub const DERIVE_LAYOUT: &str = "pdbgen.deriveLayout";
+pub const MAX_FUNCTIONS_PER_MODULE: &str = "pdbgen.maxFunctionsPerModule";
+pub const MODULE_NAME: &str = "pdbgen.moduleName";
+pub const OBJECT_PATH: &str = "pdbgen.objectPath";
+pub const PROFILE: &str = "pdbgen.profile";
+pub const DETERMINISTIC: &str = "pdbgen.deterministic";
+pub const VALIDATE: &str = "pdbgen.validate";
+pub const DBI_VERSION: &str = "pdbgen.dbiVersion";
+pub const DBI_BUILD_NUMBER: &str = "pdbgen.dbiBuildNumber";
+pub const DBI_PDB_DLL_VERSION: &str = "pdbgen.dbiPdbDllVersion";
+pub const DBI_PDB_DLL_REBUILD: &str = "pdbgen.dbiPdbDllRebuild";
+pub const DBI_FLAGS: &str = "pdbgen.dbiFlags";
+pub const DBI_MACHINE: &str = "pdbgen.dbiMachine";
+pub const PAGE_SIZE: &str = "pdbgen.pageSize";
+pub const MAX_SYMBOLS_PER_MODULE: &str = "pdbgen.maxSymbolsPerModule";
+pub const MAX_TOTAL_SYMBOLS: &str = "pdbgen.maxTotalSymbols";
+pub const TRUNCATE_AT_SYMBOL_LIMIT: &str = "pdbgen.truncateAtSymbolLimit";
+pub const DUPLICATE_NAMES: &str = "pdbgen.duplicateNames";
+pub const NAME_FORM: &str = "pdbgen.nameForm";
+pub const NAME_PRECEDENCE: &str = "pdbgen.namePrecedence";
+pub const SHADOWED_NAME_PUBLICS: &str = "pdbgen.shadowedNamePublics";
+pub const RAW_AUTO_NAMES: &str = "pdbgen.rawAutoNames";
+pub const ITANIUM_NAMES: &str = "pdbgen.itaniumNames";
+pub const RUST_NAMES: &str = "pdbgen.rustNames";
+pub const RUST_MANGLED_PUBLICS: &str = "pdbgen.rustMangledPublics";
+pub const NAME_SANITIZATION: &str = "pdbgen.nameSanitization";
+pub const LONG_NAME_POLICY: &str = "pdbgen.longNamePolicy";
+pub const MAX_NAME_LENGTH: &str = "pdbgen.maxNameLength";
+
+pub fn register() {
+    let settings = Settings::new();
+    settings.register_group("pdbgen", "PDB Generator");
+
+    settings.register_setting_json(
+        INCREMENTAL,
+        r#"{
+            "title": "Incremental Regeneration",
+            "type": "boolean",
+            "default": false,
+            "description": "Cache collected functions per module next to the PDB and only recollect modules whose functions changed since the last run. An unchanged database leaves the PDB in place, any change still rebuilds the whole PDB."
+        }"#,
+    );
+    settings.register_setting_json(
+        SYMBOL_STORE,
+        r#"{
+            "title": "Symbol Store",
+            "type": "string",
+            "default": "",
+            "description": "Directory PDBs for a debugger session are written to, laid out like a symbol server (<name>.pdb/<GUID><age>/<name>.pdb) so it can be added to the debugger's symbol path. Empty writes each PDB next to its module."
+        }"#,
+    );
+    settings.register_setting_json(
+        INCOMPLETE_ANALYSIS,
+        r#"{
+            "title": "Incomplete Analysis",
+            "type": "string",
+            "default": "wait",
+            "enum": ["wait", "snapshot"],
+            "enumDescriptions": [
+                "Wait for analysis to finish, showing its progress, before collecting anything.",
+                "Hold analysis once the work under way is done and generate from the functions found so far, resuming analysis after collection."
+            ],
+            "description": "What generating a PDB does while auto-analysis is still running, so the PDB isn't collected from a function set that changes underneath it."
+        }"#,
+    );
+    settings.register_setting_json(
+        MODULE_LAYOUT,
+        r#"{
+            "title": "Module Layout",
+            "type": "string",
+            "default": "section",
+            "enum": ["single", "section", "component", "chunked"],
+            "enumDescriptions": [
+                "One module for the whole image.",
+                "One module per section.",
+                "One module per component, falling back to the section for functions outside any component.",
+                "Modules of Maximum Functions per Module functions each, in address order."
+            ],
+            "description": "How functions are grouped into modules. Debuggers load symbols a module at a time, while small stable modules diff better between runs."
+        }"#,
+    );
+    settings.register_setting_json(
+        LIBRARY_MODULES,
+        r#"{
+            "title": "Library Modules",
+            "type": "boolean",
+            "default": false,
+            "description": "Group functions recognized as C runtime code (msvcrt_static.obj) or matched by a signature library into modules of their own, whatever the module layout other than single. Matches are grouped by the library named in the function's \"library\" metadata, or into static_library.obj without one. Signature matchers don't record their library, so the metadata has to be set by a script or plugin."
+        }"#,
+    );
+    settings.register_setting_json(
+        IMPORT_MODULES,
+        r#"{
+            "title": "Import Modules",
+            "type": "boolean",
+            "default": false,
+            "description": "Group import thunks into an Import:<library> module per library the view links them to through its external locations, as the MSVC linker does, so tools tracing calls between modules see which DLL a thunk leads to. Ignored by the single module layout."
+        }"#,
+    );
+    settings.register_setting_json(
+        CROSS_MODULE_REFERENCES,
+        r#"{
+            "title": "Cross-Module References",
+            "type": "boolean",
+            "default": false,
+            "description": "Record which functions each module calls directly in other modules, such as the thunks of import modules, in the cross-scope import and export subsections of its module stream. Needs the intermediate language of every function."
+        }"#,
+    );
+    settings.register_setting_json(
+        TRIM_PADDING,
+        r#"{
+            "title": "Trim Trailing Padding",
+            "type": "boolean",
+            "default": false,
+            "description": "Exclude trailing int3 alignment filler from function sizes so debugger function end markers and size-based tools line up with the last real instruction."
+        }"#,
+    );
+    settings.register_setting_json(
+        USER_SECTIONS,
+        r#"{
+            "title": "Include User Sections",
+            "type": "boolean",
+            "default": false,
+            "description": "Append section headers for sections created in Binary Ninja that are not in the PE header, so functions inside them get valid addresses."
+        }"#,
+    );
+    settings.register_setting_json(
+        OVERLAY_SECTION,
+        r#"{
+            "title": "Synthesize Overlay Section",
+            "type": "boolean",
+            "default": false,
+            "description": "Append a synthetic .overlay section header covering functions past the last PE section, such as code in an overlay or in a region mapped by hand, instead of dropping them."
+        }"#,
+    );
+    settings.register_setting_json(
+        INCLUDE_UNWIND_FUNCTIONS,
+        r#"{
+            "title": "Include Functions from Exception Data",
+            "type": "boolean",
+            "default": false,
+            "description": "Emit placeholder symbols (unwind_<rva>) for x64 RUNTIME_FUNCTION entries that analysis did not find a function for, so stack walks through that code still resolve."
+        }"#,
+    );
+    settings.register_setting_json(
+        EXCEPTION_STREAM,
+        r#"{
+            "title": "Exception Data Stream",
+            "type": "boolean",
+            "default": false,
+            "description": "Copy the image's RUNTIME_FUNCTION table (.pdata) into the PDB's exception debug stream for consumers that read unwind data from the PDB."
+        }"#,
+    );
+    settings.register_setting_json(
+        SYMBOL_TYPES,
+        r#"{
+            "title": "Symbol Types",
+            "type": "array",
+            "elementType": "string",
+            "default": ["function", "importedfunction", "libraryfunction"],
+            "enum": ["function", "importedfunction", "libraryfunction", "data", "external"],
+            "enumDescriptions": [
+                "Functions (FunctionSymbol).",
+                "Import thunks and other imported functions (ImportedFunctionSymbol).",
+                "Functions matched by a signature library (LibraryFunctionSymbol).",
+                "Named data, emitted as public symbols (DataSymbol).",
+                "Symbols defined outside the image (ExternalSymbol), emitted as public symbols when they lie inside a section."
+            ],
+            "description": "Which kinds of Binary Ninja symbol are exported."
+        }"#,
+    );
+    settings.register_setting_json(
+        VTABLES,
+        r#"{
+            "title": "Vtable Symbols",
+            "type": "boolean",
+            "default": false,
+            "description": "Emit data symbols named like vtables (`vftable', vtable for) as arrays of pointers, with a label on every slot naming the function it dispatches to."
+        }"#,
+    );
+    settings.register_setting_json(
+        PARAMETER_LOCATIONS,
+        r#"{
+            "title": "Parameter Locations",
+            "type": "boolean",
+            "default": false,
+            "description": "Emit each function's parameters with the register or stack slot the calling convention puts them in, valid on the first instruction, so a debugger stopped at a function's entry shows its arguments."
+        }"#,
+    );
+    settings.register_setting_json(
+        CALL_SITE_TYPES,
+        r#"{
+            "title": "Call Site Types",
+            "type": "boolean",
+            "default": false,
+            "description": "Attach the signature of the target to indirect calls whose function pointer type analysis knows, so a debugger stopped at the call shows the arguments."
+        }"#,
+    );
+    settings.register_setting_json(
+        HEAP_ALLOC_SITES,
+        r#"{
+            "title": "Heap Allocation Sites",
+            "type": "boolean",
+            "default": false,
+            "description": "Mark direct calls to the heap allocators below with heap allocation site records, typed with what the result points at when analysis knows."
+        }"#,
+    );
+    // Debug formatting of plain ASCII names is valid JSON
+    let allocators = format!("{:?}", Options::default().heap_allocators);
+    settings.register_setting_json(
+        HEAP_ALLOCATORS,
+        &format!(
+            r#"{{
+            "title": "Heap Allocators",
+            "type": "array",
+            "elementType": "string",
+            "default": {allocators},
+            "description": "Functions treated as heap allocators, by raw or short name. Import prefixes and x86 decoration (_name, _name@8) are ignored."
+        }}"#
+        ),
+    );
+    settings.register_setting_json(
+        FRAME_INFO,
+        r#"{
+            "title": "Frame Information",
+            "type": "boolean",
+            "default": false,
+            "description": "Describe each function's frame size, frame pointer and stack cookie check with S_FRAMEPROC, so debuggers reconstruct frames in code the unwind data doesn't describe."
+        }"#,
+    );
+    settings.register_setting_json(
+        BASIC_BLOCK_LABELS,
+        r#"{
+            "title": "Basic Block Labels",
+            "type": "boolean",
+            "default": false,
+            "description": "Emit a label symbol at every basic block, named func$bb_0x<rva>, for coverage and trace tools that resolve addresses through PDB symbols."
+        }"#,
+    );
+    settings.register_setting_json(
+        DEBUG_INFO,
+        r#"{
+            "title": "Use Imported Debug Info",
+            "type": "boolean",
+            "default": true,
+            "description": "Fill in parameter names analysis only has generated names for, and named data without a symbol, from debug info imported into the view (a partial vendor PDB, DWARF), and write the types it defines to the PDB."
+        }"#,
+    );
+    settings.register_setting_json(
+        TYPE_ARCHIVES,
+        r#"{
+            "title": "Resolve Types from Type Archives",
+            "type": "boolean",
+            "default": true,
+            "description": "Look up types that parameters, call sites and heap allocations only reference by name in the type archives attached to the view, so types from shared type libraries get their size and kind. Archive types that function signatures and data variables refer to are written to the PDB with their members."
+        }"#,
+    );
+    settings.register_setting_json(
+        ENV_BLOCK,
+        r#"{
+            "title": "Module Environment Blocks",
+            "type": "boolean",
+            "default": false,
+            "description": "Start every module with an S_ENVBLOCK recording the working directory, the generating program and the options used, for forensic and build provenance tools."
+        }"#,
+    );
+    settings.register_setting_json(
+        MANIFEST_STREAM,
+        r#"{
+            "title": "Manifest Stream",
+            "type": "boolean",
+            "default": false,
+            "description": "Add a pdbgen/manifest named stream recording the plugin and Binary Ninja versions, when the PDB was generated and the options used, so a PDB found later can be traced back to the run that made it."
+        }"#,
+    );
+    settings.register_setting_json(
+        STRING_LITERALS,
+        r#"{
+            "title": "String Literal Symbols",
+            "type": "string",
+            "default": "off",
+            "enum": ["off", "mangled", "plain"],
+            "enumDescriptions": [
+                "Emit no symbols for strings.",
+                "Name strings ??_C@_... as MSVC does, which debuggers show as `string'.",
+                "Name strings after their quoted contents so references show the text inline."
+            ],
+            "description": "Emit a public symbol for every string Binary Ninja found. Large binaries can have hundreds of thousands of strings."
+        }"#,
+    );
+    settings.register_setting_json(
+        SKIP_DISCARDABLE_SECTIONS,
+        r#"{
+            "title": "Skip Discardable Sections",
+            "type": "boolean",
+            "default": false,
+            "description": "Leave functions, public symbols and vtables in sections the loader discards after initialization (such as INIT in drivers) out of the PDB, so it reflects the image as it looks at runtime."
+        }"#,
+    );
+    settings.register_setting_json(
+        SECTIONS,
+        r#"{
+            "title": "Sections",
+            "type": "array",
+            "elementType": "string",
+            "default": [],
+            "description": "Names of the sections whose functions and symbols are emitted, such as .text and .rdata. Empty emits every section. Section headers are always written so section indices match the image."
+        }"#,
+    );
+    settings.register_setting_json(
+        LAYOUT_RANGES,
+        r#"{
+            "title": "Layout Ranges",
+            "type": "array",
+            "elementType": "string",
+            "default": [],
+            "description": "For views of unpacked or dumped images laid out differently from the image a debugger loads, where the view's RVAs live in that image, one \"<view rva> <image rva> <size>\" range per entry in hex. Symbols outside every range are dropped."
+        }"#,
+    );
+    settings.register_setting_json(
+        DERIVE_LAYOUT,
+        r#"{
+            "title": "Derive Layout from Segments",
+            "type": "boolean",
+            "default": false,
+            "description": "When no layout ranges are given, derive them from where the view's segments are backed in the file and where the PE section table maps those file offsets."
+        }"#,
+    );
+    settings.register_setting_json(
+        MAX_FUNCTIONS_PER_MODULE,
+        r#"{
+            "title": "Maximum Functions per Module",
+            "type": "number",
+            "default": 100000,
+            "minValue": 0,
+            "maxValue": 4294967295,
+            "description": "Split modules containing more functions than this into several. With the chunked layout this is the size of every module. 0 disables splitting."
+        }"#,
+    );
+    settings.register_setting_json(
+        MODULE_NAME,
+        r#"{
+            "title": "Module Name",
+            "type": "string",
+            "default": "{name}_module{suffix}",
+            "description": "Name given to each module. {name} is the module's section, component or library, {section} the section it contributes to, {component} the component of its first function, {binary} the image's file name without extension and {suffix} _2, _3, ... for the parts of a split module, appended when left out. Names given to more than one module are numbered."
+        }"#,
+    );
+    settings.register_setting_json(
+        OBJECT_PATH,
+        r#"{
+            "title": "Object File Path",
+            "type": "string",
+            "default": "/fake/path/{name}{suffix}.obj",
+            "description": "Object file path recorded for each module, with the same placeholders as the module name. Slashes in the replaced values become underscores, the suffix goes ahead of the extension when left out and repeated paths are numbered. Import modules always follow the linker's naming."
+        }"#,
+    );
+    settings.register_setting_json(
+        PROFILE,
+        r#"{
+            "title": "Log Timing Breakdown",
+            "type": "boolean",
+            "default": false,
+            "description": "Log how long each phase of PDB generation took, to help diagnose slow runs on large binaries."
+        }"#,
+    );
+    settings.register_setting_json(
+        DETERMINISTIC,
+        r#"{
+            "title": "Deterministic Output",
+            "type": "boolean",
+            "default": false,
+            "description": "Emit symbols in a stable order so two runs over the same database produce byte-identical PDBs."
+        }"#,
+    );
+    settings.register_setting_json(
+        VALIDATE,
+        r#"{
+            "title": "Validate Output",
+            "type": "boolean",
+            "default": true,
+            "description": "Read the generated PDB back and check section contributions, symbol addresses and type references before replacing the existing PDB."
+        }"#,
+    );
+    let dbi_fields = [
+        (
+            DBI_VERSION,
+            "DBI Version Header",
+            4294967295u64,
+            "VersionHeader of the DBI stream, 19990903 for every current toolchain.",
+        ),
+        (
+            DBI_BUILD_NUMBER,
+            "DBI Build Number",
+            65535,
+            "Toolchain version, with bit 15 set, the major version in bits 8-14 and the minor in bits 0-7 (0x8E00 for Visual Studio 2015 and newer).",
+        ),
+        (
+            DBI_PDB_DLL_VERSION,
+            "DBI PDB DLL Version",
+            65535,
+            "Build number of the mspdb DLL that claims to have written the PDB.",
+        ),
+        (
+            DBI_PDB_DLL_REBUILD,
+            "DBI PDB DLL Rebuild",
+            65535,
+            "Rebuild number of the mspdb DLL that claims to have written the PDB.",
+        ),
+        (
+            DBI_FLAGS,
+            "DBI Flags",
+            65535,
+            "Bit 0 incrementally linked, bit 1 private symbols stripped, bit 2 conflicting types.",
+        ),
+        (
+            DBI_MACHINE,
+            "DBI Machine",
+            65535,
+            "IMAGE_FILE_MACHINE value of the image, such as 34404 (0x8664) for x64.",
+        ),
+    ];
+    for (key, title, max, description) in dbi_fields {
+        settings.register_setting_json(
+            key,
+            &format!(
+                r#"{{
+                    "title": "{title}",
+                    "type": "number",
+                    "default": -1,
+                    "minValue": -1,
+                    "maxValue": {max},
+                    "description": "{description} -1 keeps the value pdb-sdk writes."
+                }}"#
+            ),
+        );
+    }
+    settings.register_setting_json(
+        PAGE_SIZE,
+        r#"{
+            "title": "MSF Page Size",
+            "type": "string",
+            "default": "4096",
+            "enum": ["4096", "8192", "16384", "32768"],
+            "description": "Block size of the PDB container. Larger pages allow bigger PDBs but require a recent debugger (Visual Studio 2019 or newer, current WinDbg)."
+        }"#,
+    );
+    settings.register_setting_json(
+        MAX_SYMBOLS_PER_MODULE,
+        r#"{
+            "title": "Symbol Limit per Module",
+            "type": "number",
+            "default": 0,
+            "minValue": 0,
+            "maxValue": 4294967295,
+            "description": "Warn when a single module holds more procedure symbols than this. 0 disables the check."
+        }"#,
+    );
+    settings.register_setting_json(
+        MAX_TOTAL_SYMBOLS,
+        r#"{
+            "title": "Total Symbol Limit",
+            "type": "number",
+            "default": 5000000,
+            "minValue": 0,
+            "maxValue": 4294967295,
+            "description": "Warn when the PDB holds more procedure symbols than this, since debuggers can take minutes to load very large PDBs. 0 disables the check."
+        }"#,
+    );
+    settings.register_setting_json(
+        TRUNCATE_AT_SYMBOL_LIMIT,
+        r#"{
+            "title": "Truncate at Symbol Limit",
+            "type": "boolean",
+            "default": false,
+            "description": "Drop functions beyond the symbol limits instead of only warning about them."
+        }"#,
+    );
+    settings.register_setting_json(
+        DUPLICATE_NAMES,
+        r#"{
+            "title": "Duplicate Names",
+            "type": "string",
+            "default": "report",
+            "enum": ["keep", "report", "suffix"],
+            "enumDescriptions": [
+                "Emit colliding names unchanged.",
+                "Emit colliding names unchanged and warn about them.",
+                "Append @rva to every function whose name is shared with a function at another address."
+            ],
+            "description": "What to do when functions at different addresses share a name, which makes lookups by name in a debugger ambiguous."
+        }"#,
+    );
+    settings.register_setting_json(
+        NAME_FORM,
+        r#"{
+            "title": "Name Form",
+            "type": "string",
+            "default": "short",
+            "enum": ["raw", "short", "full"],
+            "enumDescriptions": [
+                "The raw name from the binary, mangled names left mangled.",
+                "The short name, without parameters or return type.",
+                "The full name, including the signature of demangled functions."
+            ],
+            "description": "Which of Binary Ninja's names (raw_name, short_name, full_name) is emitted for each symbol."
+        }"#,
+    );
+    settings.register_setting_json(
+        NAME_PRECEDENCE,
+        r#"{
+            "title": "Name Precedence",
+            "type": "string",
+            "default": "user",
+            "enum": ["user", "auto"],
+            "enumDescriptions": [
+                "Emit the name the user gave the function.",
+                "Emit the name the loader or analysis gave the function."
+            ],
+            "description": "Which name is emitted when a function has both a user defined and an analysis defined symbol."
+        }"#,
+    );
+    settings.register_setting_json(
+        SHADOWED_NAME_PUBLICS,
+        r#"{
+            "title": "Keep Shadowed Names",
+            "type": "boolean",
+            "default": false,
+            "description": "Also emit the name that lost to Name Precedence as a public symbol, so the function can be found by either."
+        }"#,
+    );
+    settings.register_setting_json(
+        RAW_AUTO_NAMES,
+        r#"{
+            "title": "Raw Loader Names",
+            "type": "boolean",
+            "default": false,
+            "description": "Emit the raw names of symbols defined by loaders and analysis verbatim instead of Binary Ninja's short names, for runtimes such as Go and Delphi whose tooling expects the exact name format. User names are unaffected."
+        }"#,
+    );
+    settings.register_setting_json(
+        ITANIUM_NAMES,
+        r#"{
+            "title": "Itanium Mangled Names",
+            "type": "string",
+            "default": "demangle",
+            "enum": ["keep", "demangle", "msvc"],
+            "enumDescriptions": [
+                "Use Binary Ninja's name unchanged.",
+                "Demangle _Z names to their qualified name without parameters.",
+                "Demangle, then re-encode global functions, constructors, destructors and const member functions as MSVC decorated names so Visual Studio groups methods by class. Signatures are not known and are written as void(void). Other scoped names stay undecorated."
+            ],
+            "description": "How to name functions with Itanium mangled names, as MinGW and clang targeting it produce."
+        }"#,
+    );
+    settings.register_setting_json(
+        RUST_NAMES,
+        r#"{
+            "title": "Rust Mangled Names",
+            "type": "string",
+            "default": "demangle",
+            "enum": ["keep", "demangle", "full"],
+            "enumDescriptions": [
+                "Use Binary Ninja's name unchanged.",
+                "Demangle legacy and v0 names, dropping hashes and crate disambiguators.",
+                "Demangle legacy and v0 names, keeping hashes and crate disambiguators."
+            ],
+            "description": "How to name functions with Rust mangled names."
+        }"#,
+    );
+    settings.register_setting_json(
+        RUST_MANGLED_PUBLICS,
+        r#"{
+            "title": "Keep Mangled Rust Names",
+            "type": "boolean",
+            "default": false,
+            "description": "Also emit the mangled name of every demangled Rust function as a public symbol, so either form can be looked up."
+        }"#,
+    );
+    settings.register_setting_json(
+        NAME_SANITIZATION,
+        r#"{
+            "title": "Name Sanitization",
+            "type": "string",
+            "default": "escape",
+            "enum": ["escape", "replace", "raw"],
+            "enumDescriptions": [
+                "Spell control characters out as \\u{..} escapes.",
+                "Replace control characters with underscores.",
+                "Emit names as collected. Only NUL and invalid UTF-8 are escaped."
+            ],
+            "description": "How symbol names containing control characters are written. Bytes that are not valid UTF-8 are always escaped as \\xNN, whichever is chosen."
+        }"#,
+    );
+    settings.register_setting_json(
+        LONG_NAME_POLICY,
+        r#"{
+            "title": "Long Name Policy",
+            "type": "string",
+            "default": "hash",
+            "enum": ["keep", "truncate", "hash"],
+            "enumDescriptions": [
+                "Emit long names unchanged.",
+                "Cut long names off at the maximum length.",
+                "Cut long names off and end them with a hash of the full name so they stay unique."
+            ],
+            "description": "What to do with symbol names longer than Maximum Name Length, such as deeply nested C++ templates that some tools fail to load."
+        }"#,
+    );
+    settings.register_setting_json(
+        MAX_NAME_LENGTH,
+        r#"{
+            "title": "Maximum Name Length",
+            "type": "number",
+            "default": 4096,
+            "minValue": 0,
+            "maxValue": 60000,
+            "description": "Longest symbol name in bytes before the long name policy applies. 0 disables the check."
+        }"#,
+    );
+}
+
+pub fn get_bool(key: &str) -> bool {
+    Settings::new().get_bool(key)
+}
+
+pub fn get_string(key: &str) -> String {
+    Settings::new().get_string(key).to_string()
+}
+
+/// Generation options as currently configured by the user
+pub fn load_options() -> Options {
+    let settings = Settings::new();
+    Options {
+        module_layout: match settings.get_string(MODULE_LAYOUT).to_string().as_str() {
+            "single" => ModuleLayout::Single,
+            "component" => ModuleLayout::Component,
+            "chunked" => ModuleLayout::Chunked,
+            _ => ModuleLayout::Section,
+        },
+        library_modules: settings.get_bool(LIBRARY_MODULES),
+        import_modules: settings.get_bool(IMPORT_MODULES),
+        cross_module_references: settings.get_bool(CROSS_MODULE_REFERENCES),
+        trim_padding: settings.get_bool(TRIM_PADDING),
+        user_sections: settings.get_bool(USER_SECTIONS),
+        overlay_section: settings.get_bool(OVERLAY_SECTION),
+        include_unwind_functions: settings.get_bool(INCLUDE_UNWIND_FUNCTIONS),
+        exception_stream: settings.get_bool(EXCEPTION_STREAM),
+        symbol_types: settings
+            .get_string_list(SYMBOL_TYPES)
+            .iter()
+            .filter_map(|symbol_type| match symbol_type.to_string().as_str() {
+                "function" => Some(SymbolType::Function),
+                "importedfunction" => Some(SymbolType::ImportedFunction),
+                "libraryfunction" => Some(SymbolType::LibraryFunction),
+                "data" => Some(SymbolType::Data),
+                "external" => Some(SymbolType::External),
+                _ => None,
+            })
+            .collect(),
+        vtables: settings.get_bool(VTABLES),
+        parameter_locations: settings.get_bool(PARAMETER_LOCATIONS),
+        call_site_types: settings.get_bool(CALL_SITE_TYPES),
+        heap_alloc_sites: settings.get_bool(HEAP_ALLOC_SITES),
+        frame_info: settings.get_bool(FRAME_INFO),
+        basic_block_labels: settings.get_bool(BASIC_BLOCK_LABELS),
+        debug_info: settings.get_bool(DEBUG_INFO),
+        type_archives: settings.get_bool(TYPE_ARCHIVES),
+        env_block: settings.get_bool(ENV_BLOCK),
+        manifest_stream: settings.get_bool(MANIFEST_STREAM),
+        heap_allocators: settings
+            .get_string_list(HEAP_ALLOCATORS)
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+        string_literals: match settings.get_string(STRING_LITERALS).to_string().as_str() {
+            "mangled" => StringLiteralNames::Mangled,
+            "plain" => StringLiteralNames::Plain,
+            _ => StringLiteralNames::Off,
+        },
+        skip_discardable_sections: settings.get_bool(SKIP_DISCARDABLE_SECTIONS),
+        sections: settings
+            .get_string_list(SECTIONS)
+            .iter()
+            .map(|section| section.to_string())
+            .collect(),
+        layout_ranges: settings
+            .get_string_list(LAYOUT_RANGES)
+            .iter()
+            .filter_map(|range| {
+                let range = range.to_string();
+                let parsed = parse_layout_range(&range);
+                if parsed.is_none() {
+                    warn!("Ignoring malformed layout range {range:?}");
+                }
+                parsed
+            })
+            .collect(),
+        derive_layout: settings.get_bool(DERIVE_LAYOUT),
+        max_functions_per_module: settings.get_integer(MAX_FUNCTIONS_PER_MODULE) as usize,
+        module_name: settings.get_string(MODULE_NAME).to_string(),
+        object_path: settings.get_string(OBJECT_PATH).to_string(),
+        deterministic: settings.get_bool(DETERMINISTIC),
+        validate: settings.get_bool(VALIDATE),
+        dbi_header: DbiHeader {
+            version: optional_integer(&settings, DBI_VERSION),
+            build_number: optional_integer(&settings, DBI_BUILD_NUMBER),
+            pdb_dll_version: optional_integer(&settings, DBI_PDB_DLL_VERSION),
+            pdb_dll_rebuild: optional_integer(&settings, DBI_PDB_DLL_REBUILD),
+            flags: optional_integer(&settings, DBI_FLAGS),
+            machine: optional_integer(&settings, DBI_MACHINE),
+        },
+        page_size: settings
+            .get_string(PAGE_SIZE)
+            .to_string()
+            .parse()
+            .unwrap_or(msf::DEFAULT_PAGE_SIZE),
+        max_symbols_per_module: settings.get_integer(MAX_SYMBOLS_PER_MODULE) as usize,
+        max_total_symbols: settings.get_integer(MAX_TOTAL_SYMBOLS) as usize,
+        truncate_at_symbol_limit: settings.get_bool(TRUNCATE_AT_SYMBOL_LIMIT),
+        duplicate_names: match settings.get_string(DUPLICATE_NAMES).to_string().as_str() {
+            "keep" => DuplicateNames::Keep,
+            "suffix" => DuplicateNames::Suffix,
+            _ => DuplicateNames::Report,
+        },
+        name_form: match settings.get_string(NAME_FORM).to_string().as_str() {
+            "raw" => NameForm::Raw,
+            "full" => NameForm::Full,
+            _ => NameForm::Short,
+        },
+        name_precedence: match settings.get_string(NAME_PRECEDENCE).to_string().as_str() {
+            "auto" => NamePrecedence::Auto,
+            _ => NamePrecedence::User,
+        },
+        shadowed_name_publics: settings.get_bool(SHADOWED_NAME_PUBLICS),
+        raw_auto_names: settings.get_bool(RAW_AUTO_NAMES),
+        itanium_names: match settings.get_string(ITANIUM_NAMES).to_string().as_str() {
+            "keep" => ItaniumNames::Keep,
+            "msvc" => ItaniumNames::Msvc,
+            _ => ItaniumNames::Demangle,
+        },
+        rust_names: match settings.get_string(RUST_NAMES).to_string().as_str() {
+            "keep" => RustNames::Keep,
+            "full" => RustNames::Full,
+            _ => RustNames::Demangle,
+        },
+        rust_mangled_publics: settings.get_bool(RUST_MANGLED_PUBLICS),
+        name_sanitization: match settings.get_string(NAME_SANITIZATION).to_string().as_str() {
+            "replace" => NameSanitization::Replace,
+            "raw" => NameSanitization::Raw,
+            _ => NameSanitization::Escape,
+        },
+        long_names: match settings.get_string(LONG_NAME_POLICY).to_string().as_str() {
+            "keep" => LongNamePolicy::Keep,
+            "truncate" => LongNamePolicy::Truncate,
+            _ => LongNamePolicy::Hash,
+        },
+        max_name_length: settings.get_integer(MAX_NAME_LENGTH) as usize,
+    }
+}
+
+/// An integer setting where -1 means unset
+fn optional_integer<T: TryFrom<i64>>(settings: &Settings, key: &str) -> Option<T> {
+    match settings.get_integer(key) as i64 {
+        -1 => None,
+        value => T::try_from(value).ok(),
+    }
+}
+
+/// A `<view rva> <image rva> <size>` layout range, each number in hex with an optional 0x
+fn parse_layout_range(range: &str) -> Option<LayoutRange> {
+    let mut numbers = range.split_whitespace().map(|number| {
+        let number = number.strip_prefix("0x").unwrap_or(number);
+        u32::from_str_radix(number, 16).ok()
+    });
+    let range = LayoutRange {
+        source: numbers.next()??,
+        target: numbers.next()??,
+        size: numbers.next()??,
+    };
+    numbers.next().is_none().then_some(range)
+}